@@ -0,0 +1,120 @@
+use super::{Party, Member};
+use crate::entities::{TtrpgEntity, Elements, Counter};
+use anyhow::{anyhow, Error};
+
+pub const LEVEL_LABEL: &str = "Level";
+pub const XP_LABEL: &str = "XP";
+const XP_LIMIT: i32 = 1_000_000; // xp counters hold totals well past the 355000 of level 20
+// Total xp needed to reach each character level in 5e
+const XP_PROGRESSION: [i32; 20] = [
+    0, 300, 900, 2700, 6500,
+    14000, 23000, 34000, 48000, 64000,
+    85000, 100000, 120000, 140000, 165000,
+    195000, 225000, 265000, 305000, 355000
+];
+
+pub struct LevelUp {
+    pub name: String,
+    pub from_level: i32,
+    pub to_level: i32
+}
+
+impl LevelUp {
+    pub fn get_description(&self) -> String {
+        format!("{} can advance from level {} to {}", self.name, self.from_level, self.to_level)
+    }
+}
+
+// The highest level reachable with the given amount of xp
+pub fn level_for_xp(xp: i32) -> i32 {
+    let mut level = 1;
+    for (index, threshold) in XP_PROGRESSION.iter().enumerate() {
+        if xp >= *threshold {
+            level = index as i32 + 1;
+        }
+    }
+    level
+}
+
+pub fn xp_for_level(level: i32) -> i32 {
+    XP_PROGRESSION[(level.clamp(1, 20) - 1) as usize]
+}
+
+impl Member {
+    // Reads the "Level" and "XP" counters of a character, either one can be derived from the other
+    pub fn from_entity(entity: &TtrpgEntity) -> Result<Member, Error> {
+        let level = entity.find_counter(LEVEL_LABEL).map(|c| c.number);
+        let xp = entity.find_counter(XP_LABEL).map(|c| c.number);
+        let (level, xp) = match (level, xp) {
+            (Some(level), Some(xp)) => (level, xp),
+            (Some(level), None) => (level, xp_for_level(level)),
+            (None, Some(xp)) => (level_for_xp(xp), xp),
+            (None, None) => return Err(anyhow!("{} has no {} or {} counter", entity.name, LEVEL_LABEL, XP_LABEL)),
+        };
+        if level < 1 || level > 20 {
+            return Err(anyhow!("{} has an invalid level of {}", entity.name, level));
+        }
+        Ok(Member {name: entity.name.clone(), entity_id: entity.id.clone(), entity_index: None, level, xp: xp.max(0)})
+    }
+    // Adds xp and returns the level up it makes available, if any
+    pub fn add_xp(&mut self, xp: i32) -> Option<LevelUp> {
+        self.xp = (self.xp + xp).max(0);
+        let reachable = level_for_xp(self.xp);
+        if reachable > self.level {
+            return Some(LevelUp {name: self.name.clone(), from_level: self.level, to_level: reachable});
+        }
+        None
+    }
+}
+
+impl Party {
+    // Builds the party from every active entity that is marked as a player character
+    pub fn from_entities(entities: &[TtrpgEntity]) -> Result<Party, Error> {
+        let mut party = Party::new();
+        for (index, entity) in entities.iter().enumerate() {
+            if entity.active.get() && entity.player_character.get() {
+                let mut member = Member::from_entity(entity)?;
+                member.entity_index = Some(index);
+                party.add_member(member);
+            }
+        }
+        if party.members.is_empty() {
+            return Err(anyhow!("No active player characters to build a party from"));
+        }
+        Ok(party)
+    }
+    // Writes each member's xp back to the entity it was built from and reports who can level up.
+    // Saved entities are found by their id, unsaved ones by where they were in the entities.
+    pub fn write_to_entities(&self, entities: &mut [TtrpgEntity]) -> Vec<LevelUp> {
+        let mut level_ups: Vec<LevelUp> = Vec::new();
+        for member in self.members.iter() {
+            let index = if member.entity_id.is_empty() {
+                member.entity_index.filter(|i| entities.get(*i).map_or(false, |e| e.id.is_empty() && e.name == member.name))
+            } else {
+                entities.iter().position(|e| e.id == member.entity_id)
+            };
+            let entity = match index {
+                Some(index) => &mut entities[index],
+                None => continue,
+            };
+            match entity.find_counter_mut(XP_LABEL) {
+                Some(counter) => {
+                    // counters made by hand only go up to NUMBER_LIMIT
+                    counter.limit = Some(counter.limit.unwrap_or(0).max(XP_LIMIT));
+                    let change = member.xp - counter.number;
+                    counter.increment(change);
+                },
+                None => {
+                    let order_num = entity.elements.len() as u32 + 1;
+                    entity.add_element(Elements::Counter(Counter::with_limit(order_num, order_num, XP_LABEL.to_string(), member.xp, XP_LIMIT)));
+                }
+            }
+            let level = entity.find_counter(LEVEL_LABEL).map(|c| c.number).unwrap_or(member.level);
+            let reachable = level_for_xp(member.xp);
+            if reachable > level {
+                level_ups.push(LevelUp {name: member.name.clone(), from_level: level, to_level: reachable});
+            }
+        }
+        level_ups
+    }
+}
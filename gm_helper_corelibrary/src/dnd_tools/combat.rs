@@ -0,0 +1,486 @@
+use super::{StatBlock, DamageType, MAX_HP_LABEL, RESISTANCES_LABEL, VULNERABILITIES_LABEL, IMMUNITIES_LABEL, damage_types_of};
+use crate::entities::{TtrpgEntity, Counter, Roll, Condition, Elements, Stacking};
+use crate::session_log::{SessionLog, LogKind};
+use anyhow::{anyhow, Error};
+use rand::{thread_rng, Rng};
+
+pub const HP_LABEL: &str = "HP";
+pub const AC_LABEL: &str = "AC";
+pub const DEX_LABEL: &str = "DEX";
+
+#[derive(Clone, Debug)]
+pub enum CombatEvent {
+    CombatStarted,
+    RoundStarted(u32),
+    TurnStarted {round: u32, name: String},
+    Damaged {name: String, amount: i32, hit_points: i32},
+    Healed {name: String, amount: i32, hit_points: i32},
+    ConcentrationCheck {name: String, difficulty: i32, spell: String},
+    ConcentrationBroken {name: String, spell: String},
+    DeathSave {name: String, roll: u32, successes: u32, failures: u32},
+    Stabilized(String),
+    ConditionApplied {name: String, condition: String},
+    ConditionExpired {name: String, condition: String},
+    DamageRolled {name: String, calculation: String},
+    KilledOutright(String),
+    Defeated(String),
+    CombatEnded
+}
+
+impl CombatEvent {
+    pub fn get_description(&self) -> String {
+        match self {
+            CombatEvent::CombatStarted => "Combat started".to_string(),
+            CombatEvent::RoundStarted(round) => format!("Round {}", round),
+            CombatEvent::TurnStarted {round, name} => format!("Round {}: {}'s turn", round, name),
+            CombatEvent::Damaged {name, amount, hit_points} => format!("{} takes {} damage ({} hp left)", name, amount, hit_points),
+            CombatEvent::Healed {name, amount, hit_points} => format!("{} heals {} ({} hp)", name, amount, hit_points),
+            CombatEvent::ConcentrationCheck {name, difficulty, spell} => format!("{} must make a DC {} constitution save to keep {}", name, difficulty, spell),
+            CombatEvent::ConcentrationBroken {name, spell} => format!("{} loses concentration on {}", name, spell),
+            CombatEvent::DeathSave {name, roll, successes, failures} => format!("{} rolls {} on a death save ({} successes, {} failures)", name, roll, successes, failures),
+            CombatEvent::Stabilized(name) => format!("{} is stable", name),
+            CombatEvent::ConditionApplied {name, condition} => format!("{} is affected by {}", name, condition),
+            CombatEvent::ConditionExpired {name, condition} => format!("{} is no longer affected by {}", name, condition),
+            CombatEvent::DamageRolled {name, calculation} => format!("Damage to {}: {}", name, calculation),
+            CombatEvent::KilledOutright(name) => format!("{} is killed outright by massive damage", name),
+            CombatEvent::Defeated(name) => format!("{} is defeated", name),
+            CombatEvent::CombatEnded => "Combat ended".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DeathSaves {
+    pub successes: u32,
+    pub failures: u32,
+    pub stable: bool
+}
+
+#[derive(Clone, Debug)]
+pub struct Combatant {
+    pub name: String,
+    pub entity_name: Option<String>, // the TtrpgEntity this combatant was added from
+    pub entity_id: String, // id of that entity, names can repeat
+    pub entity_index: Option<usize>, // where it was in the entities, for entities not saved yet and without an id
+    pub is_player: bool,
+    pub initiative: i32,
+    pub initiative_modifier: i32, // dexterity modifier, breaks initiative ties
+    pub armor_class: i32,
+    pub hit_points: Counter,
+    pub max_hit_points: i32,
+    pub temporary_hit_points: i32,
+    pub concentrating_on: Option<String>,
+    pub death_saves: DeathSaves,
+    pub conditions: Vec<Condition>,
+    pub resistances: Vec<DamageType>,
+    pub vulnerabilities: Vec<DamageType>,
+    pub immunities: Vec<DamageType>,
+    pub stat_block: Option<StatBlock>
+}
+
+impl Combatant {
+    // Players at 0 hp are dying until they die or stabilize, everyone else is out of the fight
+    pub fn is_defeated(&self) -> bool {
+        if self.is_player {
+            self.death_saves.failures >= 3
+        } else {
+            self.hit_points.number <= 0
+        }
+    }
+    pub fn is_dying(&self) -> bool {
+        self.is_player && self.hit_points.number <= 0 && !self.death_saves.stable && self.death_saves.failures < 3
+    }
+    pub fn get_description(&self) -> String {
+        let mut description = format!(
+            "{} ({}) AC {} HP {}/{}",
+            self.name,
+            self.initiative,
+            self.armor_class,
+            self.hit_points.number,
+            self.max_hit_points
+        );
+        if self.temporary_hit_points > 0 {
+            description += &format!(" +{} temp", self.temporary_hit_points);
+        }
+        if let Some(spell) = &self.concentrating_on {
+            description += &format!(", concentrating on {}", spell);
+        }
+        for condition in self.conditions.iter() {
+            description += &format!(", {}", condition.get_description());
+        }
+        description
+    }
+}
+
+pub struct Combat {
+    pub combatants: Vec<Combatant>,
+    pub round: u32,
+    pub turn: usize,
+    pub started: bool,
+    events: Vec<CombatEvent>
+}
+
+impl Combat {
+    pub fn new() -> Combat {
+        Combat {
+            combatants: Vec::new(),
+            round: 0,
+            turn: 0,
+            started: false,
+            events: Vec::new()
+        }
+    }
+    // Reads the "HP", "Max HP" and "AC" counters, the "DEX" attribute and the damage types listed in
+    // the "Resistances", "Vulnerabilities" and "Immunities" stories of the entity at `index`, an entity
+    // already in the fight is left as it is
+    pub fn add_entity(&mut self, entities: &[TtrpgEntity], index: usize) -> Result<(), Error> {
+        let entity = match entities.get(index) {
+            Some(entity) => entity,
+            None => return Err(anyhow!("No entity at {}", index)),
+        };
+        if self.combatants.iter().any(|c| entity_position(c, entities) == Some(index)) {
+            return Ok(());
+        }
+        let hit_points = match entity.find_counter(HP_LABEL) {
+            Some(counter) => counter.number,
+            None => return Err(anyhow!("{} has no {} counter", entity.name, HP_LABEL)),
+        };
+        let max_hit_points = entity.find_counter(MAX_HP_LABEL).map(|c| c.number).unwrap_or(hit_points).max(hit_points);
+        let armor_class = entity.find_counter(AC_LABEL).map(|c| c.number).unwrap_or(10);
+        let initiative_modifier = entity.find_attribute(DEX_LABEL)
+            .map(|a| a.modifier)
+            .unwrap_or(0);
+        self.add_combatant(Combatant {
+            name: entity.name.clone(),
+            entity_name: Some(entity.name.clone()),
+            entity_id: entity.id.clone(),
+            entity_index: Some(index),
+            is_player: entity.player_character.get(),
+            initiative: 0,
+            initiative_modifier,
+            armor_class,
+            hit_points: Counter::new(0, 0, HP_LABEL.to_string(), hit_points),
+            max_hit_points,
+            temporary_hit_points: 0,
+            concentrating_on: None,
+            death_saves: DeathSaves::default(),
+            conditions: entity.conditions().into_iter().cloned().collect(),
+            resistances: damage_types_of(entity, RESISTANCES_LABEL),
+            vulnerabilities: damage_types_of(entity, VULNERABILITIES_LABEL),
+            immunities: damage_types_of(entity, IMMUNITIES_LABEL),
+            stat_block: None
+        });
+        Ok(())
+    }
+    pub fn add_stat_block(&mut self, stat_block: &StatBlock) {
+        self.add_combatant(Combatant {
+            name: stat_block.name.clone(),
+            entity_name: None,
+            entity_id: "".to_string(),
+            entity_index: None,
+            is_player: false,
+            initiative: 0,
+            initiative_modifier: stat_block.initiative,
+            armor_class: stat_block.armor_class,
+            hit_points: Counter::new(0, 0, HP_LABEL.to_string(), stat_block.hit_points),
+            max_hit_points: stat_block.hit_points,
+            temporary_hit_points: 0,
+            concentrating_on: None,
+            death_saves: DeathSaves::default(),
+            conditions: Vec::new(),
+            resistances: stat_block.resistances.clone(),
+            vulnerabilities: stat_block.vulnerabilities.clone(),
+            immunities: stat_block.immunities.clone(),
+            stat_block: Some(stat_block.clone())
+        });
+    }
+    // Duplicate names are numbered so "Goblin", "Goblin 2", "Goblin 3" can be told apart
+    fn add_combatant(&mut self, mut combatant: Combatant) {
+        let base_name = combatant.name.clone();
+        let mut number = 1;
+        while self.combatants.iter().any(|c| c.name == combatant.name) {
+            number += 1;
+            combatant.name = format!("{} {}", base_name, number);
+        }
+        self.combatants.push(combatant);
+    }
+    pub fn remove_combatant(&mut self, index: usize) -> Result<Combatant, Error> {
+        self.check_index(index)?;
+        let removed = self.combatants.remove(index);
+        if index < self.turn || (self.turn >= self.combatants.len() && self.turn > 0) {
+            self.turn -= 1;
+        }
+        Ok(removed)
+    }
+
+    pub fn roll_initiative(&mut self) {
+        self.roll_initiative_with(&mut thread_rng());
+    }
+    // Orders combatants by initiative, ties go to the higher dexterity modifier
+    pub fn roll_initiative_with<R: Rng>(&mut self, rng: &mut R) {
+        let d20 = Roll::new(20, 1);
+        for combatant in self.combatants.iter_mut() {
+            combatant.initiative = d20.roll_with(rng)[0] as i32 + combatant.initiative_modifier;
+        }
+        self.combatants.sort_by(|a, b| {
+            b.initiative.cmp(&a.initiative).then(b.initiative_modifier.cmp(&a.initiative_modifier))
+        });
+        self.round = 1;
+        self.turn = 0;
+        self.started = true;
+        self.events.push(CombatEvent::CombatStarted);
+        self.events.push(CombatEvent::RoundStarted(1));
+        if let Some(first) = self.combatants.first() {
+            self.events.push(CombatEvent::TurnStarted {round: 1, name: first.name.clone()});
+        }
+    }
+    pub fn current(&self) -> Option<&Combatant> {
+        if self.started {self.combatants.get(self.turn)} else {None}
+    }
+    // Moves to the next combatant still in the fight, starting a new round after the last one
+    pub fn next_turn(&mut self) -> Result<(), Error> {
+        if !self.started {
+            return Err(anyhow!("Roll initiative before advancing turns"));
+        }
+        if self.combatants.iter().all(|c| c.is_defeated()) {
+            return Err(anyhow!("Every combatant is defeated"));
+        }
+        self.expire_conditions(Some(self.turn), |cd| cd.end_turn());
+        loop {
+            self.turn += 1;
+            if self.turn >= self.combatants.len() {
+                self.turn = 0;
+                self.round += 1;
+                self.events.push(CombatEvent::RoundStarted(self.round));
+                self.expire_conditions(None, |cd| cd.tick_rounds(1));
+            }
+            if !self.combatants[self.turn].is_defeated() {
+                break;
+            }
+        }
+        self.events.push(CombatEvent::TurnStarted {round: self.round, name: self.combatants[self.turn].name.clone()});
+        Ok(())
+    }
+    pub fn end(&mut self) {
+        self.started = false;
+        self.round = 0;
+        self.turn = 0;
+        self.events.push(CombatEvent::CombatEnded);
+    }
+
+    pub fn damage(&mut self, index: usize, amount: i32) -> Result<(), Error> {
+        self.take_damage(index, amount, false)
+    }
+    // Temporary hit points soak damage first. Damage on a dying player is a failed death save (two
+    // on a critical) and damage left over at 0 hp that reaches their maximum kills them outright, also
+    // when they were already at 0 hp
+    pub(super) fn take_damage(&mut self, index: usize, amount: i32, critical: bool) -> Result<(), Error> {
+        self.check_index(index)?;
+        if amount <= 0 {
+            return Ok(());
+        }
+        let combatant = &mut self.combatants[index];
+        let was_defeated = combatant.is_defeated();
+        let soaked = amount.min(combatant.temporary_hit_points);
+        combatant.temporary_hit_points -= soaked;
+        let remaining = amount - soaked;
+        if combatant.hit_points.number <= 0 && combatant.is_player && remaining > 0 {
+            let failures = if critical {2} else {1};
+            combatant.death_saves.stable = false;
+            combatant.death_saves.failures = (combatant.death_saves.failures + failures).min(3);
+        }
+        let lost = remaining.min(combatant.hit_points.number.max(0));
+        combatant.hit_points.decrement(lost);
+        self.events.push(CombatEvent::Damaged {name: combatant.name.clone(), amount, hit_points: combatant.hit_points.number});
+        if combatant.is_player && !was_defeated && remaining > 0 && remaining - lost >= combatant.max_hit_points {
+            combatant.death_saves.failures = 3;
+            self.events.push(CombatEvent::KilledOutright(combatant.name.clone()));
+        }
+        if let Some(spell) = combatant.concentrating_on.clone() {
+            if combatant.hit_points.number <= 0 {
+                combatant.concentrating_on = None;
+                self.events.push(CombatEvent::ConcentrationBroken {name: combatant.name.clone(), spell});
+            } else if remaining > 0 {
+                self.events.push(CombatEvent::ConcentrationCheck {name: combatant.name.clone(), difficulty: (remaining / 2).max(10), spell});
+            }
+        }
+        if combatant.is_defeated() && !was_defeated {
+            self.events.push(CombatEvent::Defeated(combatant.name.clone()));
+        }
+        Ok(())
+    }
+    pub fn heal(&mut self, index: usize, amount: i32) -> Result<(), Error> {
+        self.check_index(index)?;
+        let combatant = &mut self.combatants[index];
+        if amount <= 0 || (combatant.is_player && combatant.death_saves.failures >= 3) {
+            return Ok(());
+        }
+        let healed = amount.min(combatant.max_hit_points - combatant.hit_points.number.max(0));
+        if combatant.hit_points.number < 0 {
+            combatant.hit_points.increment(-combatant.hit_points.number);
+        }
+        combatant.hit_points.increment(healed);
+        combatant.death_saves = DeathSaves::default();
+        self.events.push(CombatEvent::Healed {name: combatant.name.clone(), amount: healed, hit_points: combatant.hit_points.number});
+        Ok(())
+    }
+    // Temporary hit points don't stack, the higher value is kept
+    pub fn set_temporary_hit_points(&mut self, index: usize, amount: i32) -> Result<(), Error> {
+        self.check_index(index)?;
+        let combatant = &mut self.combatants[index];
+        combatant.temporary_hit_points = combatant.temporary_hit_points.max(amount);
+        Ok(())
+    }
+    pub fn concentrate(&mut self, index: usize, spell: &str) -> Result<(), Error> {
+        self.check_index(index)?;
+        let combatant = &mut self.combatants[index];
+        if let Some(previous) = combatant.concentrating_on.replace(spell.to_string()) {
+            self.events.push(CombatEvent::ConcentrationBroken {name: combatant.name.clone(), spell: previous});
+        }
+        Ok(())
+    }
+    pub fn break_concentration(&mut self, index: usize) -> Result<(), Error> {
+        self.check_index(index)?;
+        let combatant = &mut self.combatants[index];
+        if let Some(spell) = combatant.concentrating_on.take() {
+            self.events.push(CombatEvent::ConcentrationBroken {name: combatant.name.clone(), spell});
+        }
+        Ok(())
+    }
+
+    pub fn apply_condition(&mut self, index: usize, mut condition: Condition) -> Result<(), Error> {
+        self.check_index(index)?;
+        condition.applied_during_own_turn = self.started && index == self.turn;
+        let combatant = &mut self.combatants[index];
+        self.events.push(CombatEvent::ConditionApplied {name: combatant.name.clone(), condition: condition.get_description()});
+        let existing = combatant.conditions.iter_mut().find(|cd| cd.label == condition.label);
+        match existing {
+            Some(existing) if condition.stacking != Stacking::Independent => existing.stack(condition),
+            _ => combatant.conditions.push(condition),
+        }
+        Ok(())
+    }
+    pub fn resolve_condition_save(&mut self, index: usize, ability: &str, total: u32) -> Result<(), Error> {
+        self.check_index(index)?;
+        self.expire_conditions(Some(index), |cd| cd.resolve_save(ability, total));
+        Ok(())
+    }
+    // Out of combat time passing, e.g. a short rest, counts conditions down in minutes
+    pub fn advance_time(&mut self, minutes: u32) {
+        self.expire_conditions(None, |cd| cd.advance_minutes(minutes));
+    }
+    // Copies the combatants' conditions back onto the entities they were added from
+    pub fn write_conditions_to_entities(&self, entities: &mut [TtrpgEntity]) {
+        for combatant in self.combatants.iter() {
+            let entity = match entity_position(combatant, entities) {
+                Some(index) => &mut entities[index],
+                None => continue,
+            };
+            let keys: Vec<String> = entity.elements.iter()
+                .filter(|(_key, element)| matches!(element, Elements::Condition(_)))
+                .map(|(key, _element)| key.clone())
+                .collect();
+            for key in keys {
+                entity.remove_element(&key);
+            }
+            for condition in combatant.conditions.iter() {
+                entity.add_element(Elements::Condition(condition.clone()));
+            }
+        }
+    }
+    // Copies the combatants' hit points back onto the HP counters of the entities they were added from
+    pub fn write_hit_points_to_entities(&self, entities: &mut [TtrpgEntity]) {
+        for combatant in self.combatants.iter() {
+            let entity = match entity_position(combatant, entities) {
+                Some(index) => &mut entities[index],
+                None => continue,
+            };
+            if let Some(counter) = entity.find_counter_mut(HP_LABEL) {
+                let change = combatant.hit_points.number - counter.number;
+                counter.increment(change);
+            }
+        }
+    }
+    fn expire_conditions<F: FnMut(&mut Condition) -> bool>(&mut self, only: Option<usize>, mut expired: F) {
+        for (index, combatant) in self.combatants.iter_mut().enumerate() {
+            if only.is_some() && only != Some(index) {
+                continue;
+            }
+            let mut kept = Vec::new();
+            for mut condition in combatant.conditions.drain(..) {
+                if expired(&mut condition) {
+                    self.events.push(CombatEvent::ConditionExpired {name: combatant.name.clone(), condition: condition.label.clone()});
+                } else {
+                    kept.push(condition);
+                }
+            }
+            combatant.conditions = kept;
+        }
+    }
+
+    pub fn roll_death_save(&mut self, index: usize) -> Result<(), Error> {
+        self.roll_death_save_with(index, &mut thread_rng())
+    }
+    // A natural 20 brings the player back with 1 hp, a natural 1 counts as two failures
+    pub fn roll_death_save_with<R: Rng>(&mut self, index: usize, rng: &mut R) -> Result<(), Error> {
+        self.check_index(index)?;
+        if !self.combatants[index].is_dying() {
+            return Err(anyhow!("{} is not making death saves", self.combatants[index].name));
+        }
+        let roll = Roll::new(20, 1).roll_with(rng)[0];
+        if roll == 20 {
+            return self.heal(index, 1);
+        }
+        let combatant = &mut self.combatants[index];
+        match roll {
+            1 => combatant.death_saves.failures = (combatant.death_saves.failures + 2).min(3),
+            r if r >= 10 => combatant.death_saves.successes += 1,
+            _ => combatant.death_saves.failures += 1,
+        }
+        self.events.push(CombatEvent::DeathSave {
+            name: combatant.name.clone(),
+            roll,
+            successes: combatant.death_saves.successes,
+            failures: combatant.death_saves.failures
+        });
+        if combatant.death_saves.successes >= 3 {
+            combatant.death_saves.stable = true;
+            self.events.push(CombatEvent::Stabilized(combatant.name.clone()));
+        } else if combatant.death_saves.failures >= 3 {
+            self.events.push(CombatEvent::Defeated(combatant.name.clone()));
+        }
+        Ok(())
+    }
+
+    // Hands back every event since the last call
+    pub fn take_events(&mut self) -> Vec<CombatEvent> {
+        std::mem::take(&mut self.events)
+    }
+    pub fn log_events(&mut self, log: &mut SessionLog) {
+        for event in self.take_events() {
+            log.record(LogKind::Combat, &event.get_description());
+        }
+    }
+
+    pub(super) fn push_event(&mut self, event: CombatEvent) {
+        self.events.push(event);
+    }
+    pub(super) fn check_index(&self, index: usize) -> Result<(), Error> {
+        if index >= self.combatants.len() {
+            return Err(anyhow!("No combatant at position {}", index));
+        }
+        Ok(())
+    }
+}
+
+// Saved entities are found by their id, unsaved ones by where they were in the entities
+fn entity_position(combatant: &Combatant, entities: &[TtrpgEntity]) -> Option<usize> {
+    if combatant.entity_name.is_none() {
+        return None;
+    }
+    if combatant.entity_id.is_empty() {
+        combatant.entity_index.filter(|i| entities.get(*i).map_or(false, |e| e.id.is_empty() && Some(&e.name) == combatant.entity_name.as_ref()))
+    } else {
+        entities.iter().position(|e| e.id == combatant.entity_id)
+    }
+}
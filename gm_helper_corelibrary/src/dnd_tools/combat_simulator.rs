@@ -0,0 +1,195 @@
+use super::{Encounter, StatBlock};
+use crate::entities::Roll;
+use anyhow::{anyhow, Error};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TargetPolicy {
+    Random,
+    LowestHp, // focus fire on the most wounded enemy
+    HighestHp
+}
+
+pub struct SimulationReport {
+    pub combats: u32,
+    pub party_wins: u32,
+    pub stalemates: u32, // combats that hit the round limit
+    pub win_rate: f32,
+    pub average_rounds: f32,
+    pub average_party_hp_lost: f32,
+    pub average_party_deaths: f32,
+    pub deaths_per_member: Vec<(String, u32)>
+}
+
+impl SimulationReport {
+    pub fn get_description(&self) -> String {
+        format!(
+            "{} combats: {:.1}% party wins, {:.1} rounds, {:.1} hp lost, {:.2} deaths on average",
+            self.combats,
+            self.win_rate * 100.0,
+            self.average_rounds,
+            self.average_party_hp_lost,
+            self.average_party_deaths
+        )
+    }
+}
+
+pub struct CombatSimulator {
+    pub party: Vec<StatBlock>,
+    pub monsters: Vec<StatBlock>,
+    pub policy: TargetPolicy,
+    pub max_rounds: u32,
+    pub seed: Option<u64>
+}
+
+// A combatant's running state within a single simulated combat
+struct Fighter {
+    index: usize,
+    is_party: bool,
+    hit_points: i32,
+    initiative: i32
+}
+
+impl CombatSimulator {
+    pub fn new(party: Vec<StatBlock>, monsters: Vec<StatBlock>, policy: TargetPolicy, seed: Option<u64>) -> CombatSimulator {
+        CombatSimulator {
+            party,
+            monsters,
+            policy,
+            max_rounds: 100,
+            seed
+        }
+    }
+    // Every monster in the encounter needs a stat block to be simulated
+    pub fn from_encounter(party: Vec<StatBlock>, encounter: &Encounter, policy: TargetPolicy, seed: Option<u64>) -> Result<CombatSimulator, Error> {
+        let mut monsters: Vec<StatBlock> = Vec::new();
+        for monster in encounter.monsters.iter() {
+            match &monster.stat_block {
+                Some(stat_block) => monsters.push(stat_block.clone()),
+                None => return Err(anyhow!("{} has no stat block to simulate", monster.name)),
+            }
+        }
+        Ok(CombatSimulator::new(party, monsters, policy, seed))
+    }
+
+    pub fn run(&self, combats: u32) -> Result<SimulationReport, Error> {
+        if self.party.is_empty() || self.monsters.is_empty() {
+            return Err(anyhow!("Both sides need at least one combatant to simulate"));
+        }
+        if combats == 0 {
+            return Err(anyhow!("At least one combat must be simulated"));
+        }
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let mut party_wins = 0;
+        let mut stalemates = 0;
+        let mut total_rounds = 0;
+        let mut total_hp_lost = 0;
+        let mut total_deaths = 0;
+        let mut deaths_per_member: Vec<(String, u32)> = self.party.iter().map(|p| (p.name.clone(), 0)).collect();
+
+        for _ in 0..combats {
+            let (fighters, rounds) = self.simulate(&mut rng);
+            let party_alive = fighters.iter().any(|f| f.is_party && f.hit_points > 0);
+            let monsters_alive = fighters.iter().any(|f| !f.is_party && f.hit_points > 0);
+            if party_alive && !monsters_alive {
+                party_wins += 1;
+            } else if party_alive && monsters_alive {
+                stalemates += 1;
+            }
+            total_rounds += rounds;
+            for fighter in fighters.iter().filter(|f| f.is_party) {
+                let max = self.party[fighter.index].hit_points;
+                total_hp_lost += max - fighter.hit_points.max(0);
+                if fighter.hit_points <= 0 {
+                    total_deaths += 1;
+                    deaths_per_member[fighter.index].1 += 1;
+                }
+            }
+        }
+
+        Ok(SimulationReport {
+            combats,
+            party_wins,
+            stalemates,
+            win_rate: party_wins as f32 / combats as f32,
+            average_rounds: total_rounds as f32 / combats as f32,
+            average_party_hp_lost: total_hp_lost as f32 / combats as f32,
+            average_party_deaths: total_deaths as f32 / combats as f32,
+            deaths_per_member
+        })
+    }
+
+    fn stat_block(&self, fighter: &Fighter) -> &StatBlock {
+        if fighter.is_party {&self.party[fighter.index]} else {&self.monsters[fighter.index]}
+    }
+
+    // Runs a single combat to the end, a creature at 0 hp is out of the fight
+    fn simulate(&self, rng: &mut StdRng) -> (Vec<Fighter>, u32) {
+        let d20 = Roll::new(20, 1);
+        let mut fighters: Vec<Fighter> = Vec::new();
+        for (index, stat_block) in self.party.iter().enumerate() {
+            let initiative = d20.roll_with(rng)[0] as i32 + stat_block.initiative;
+            fighters.push(Fighter {index, is_party: true, hit_points: stat_block.hit_points, initiative});
+        }
+        for (index, stat_block) in self.monsters.iter().enumerate() {
+            let initiative = d20.roll_with(rng)[0] as i32 + stat_block.initiative;
+            fighters.push(Fighter {index, is_party: false, hit_points: stat_block.hit_points, initiative});
+        }
+        // ties go to the higher initiative modifier
+        fighters.sort_by(|a, b| {
+            b.initiative.cmp(&a.initiative)
+                .then(self.stat_block(b).initiative.cmp(&self.stat_block(a).initiative))
+        });
+
+        let mut rounds = 0;
+        while rounds < self.max_rounds {
+            rounds += 1;
+            for turn in 0..fighters.len() {
+                if fighters[turn].hit_points <= 0 {
+                    continue;
+                }
+                let attacker = self.stat_block(&fighters[turn]).clone();
+                let is_party = fighters[turn].is_party;
+                for _ in 0..attacker.attacks {
+                    let target = match self.pick_target(&fighters, !is_party, rng) {
+                        Some(target) => target,
+                        None => return (fighters, rounds),
+                    };
+                    let natural = d20.roll_with(rng)[0];
+                    let armor_class = self.stat_block(&fighters[target]).armor_class;
+                    if natural == 1 || (natural != 20 && natural as i32 + attacker.attack_bonus < armor_class) {
+                        continue;
+                    }
+                    let damage = if natural == 20 {
+                        attacker.damage.critical_with(rng).1
+                    } else {
+                        attacker.damage.roll_with(rng).1
+                    };
+                    fighters[target].hit_points -= damage.max(0);
+                }
+            }
+            let party_alive = fighters.iter().any(|f| f.is_party && f.hit_points > 0);
+            let monsters_alive = fighters.iter().any(|f| !f.is_party && f.hit_points > 0);
+            if !party_alive || !monsters_alive {
+                break;
+            }
+        }
+        (fighters, rounds)
+    }
+
+    fn pick_target(&self, fighters: &[Fighter], party_side: bool, rng: &mut StdRng) -> Option<usize> {
+        let living: Vec<usize> = (0..fighters.len())
+            .filter(|&i| fighters[i].is_party == party_side && fighters[i].hit_points > 0)
+            .collect();
+        match self.policy {
+            TargetPolicy::Random => living.choose(rng).copied(),
+            TargetPolicy::LowestHp => living.into_iter().min_by_key(|&i| fighters[i].hit_points),
+            TargetPolicy::HighestHp => living.into_iter().max_by_key(|&i| fighters[i].hit_points),
+        }
+    }
+}
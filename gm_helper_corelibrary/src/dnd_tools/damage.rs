@@ -0,0 +1,158 @@
+use super::{Combat, CombatEvent};
+use crate::entities::{DiceExpression, TtrpgEntity, Elements};
+use anyhow::{anyhow, Error};
+use rand::{thread_rng, Rng};
+
+pub const RESISTANCES_LABEL: &str = "Resistances";
+pub const VULNERABILITIES_LABEL: &str = "Vulnerabilities";
+pub const IMMUNITIES_LABEL: &str = "Immunities";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DamageType {
+    Acid,
+    Bludgeoning,
+    Cold,
+    Fire,
+    Force,
+    Lightning,
+    Necrotic,
+    Piercing,
+    Poison,
+    Psychic,
+    Radiant,
+    Slashing,
+    Thunder
+}
+
+impl DamageType {
+    pub fn parse(name: &str) -> Option<DamageType> {
+        match name.to_lowercase().as_str() {
+            "acid" => Some(DamageType::Acid),
+            "bludgeoning" => Some(DamageType::Bludgeoning),
+            "cold" => Some(DamageType::Cold),
+            "fire" => Some(DamageType::Fire),
+            "force" => Some(DamageType::Force),
+            "lightning" => Some(DamageType::Lightning),
+            "necrotic" => Some(DamageType::Necrotic),
+            "piercing" => Some(DamageType::Piercing),
+            "poison" => Some(DamageType::Poison),
+            "psychic" => Some(DamageType::Psychic),
+            "radiant" => Some(DamageType::Radiant),
+            "slashing" => Some(DamageType::Slashing),
+            "thunder" => Some(DamageType::Thunder),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> String {
+        format!("{:?}", self).to_lowercase()
+    }
+}
+
+// Damage types named in the entity's stories with the given label, e.g. a "Resistances" story of "fire, cold"
+pub fn damage_types_of(entity: &TtrpgEntity, label: &str) -> Vec<DamageType> {
+    let mut damage_types: Vec<DamageType> = Vec::new();
+    for (_key, element) in entity.elements.iter() {
+        if let Elements::Story(story) = element {
+            if !story.label.eq_ignore_ascii_case(label) {
+                continue;
+            }
+            for word in story.raw_narration.split(|c: char| !c.is_alphabetic()) {
+                if let Some(damage_type) = DamageType::parse(word) {
+                    if !damage_types.contains(&damage_type) {
+                        damage_types.push(damage_type);
+                    }
+                }
+            }
+        }
+    }
+    damage_types
+}
+
+// A damage roll made of typed parts such as "2d6 fire + 1d8+3 slashing"
+#[derive(Clone, Debug)]
+pub struct DamageRoll {
+    pub parts: Vec<(DiceExpression, DamageType)>
+}
+
+impl DamageRoll {
+    pub fn parse(expression: &str) -> Result<DamageRoll, Error> {
+        let spaced = expression.replace('+', " + ").replace('-', " - ");
+        let mut parts: Vec<(DiceExpression, DamageType)> = Vec::new();
+        let mut dice = String::new();
+        for token in spaced.split_whitespace() {
+            match DamageType::parse(token) {
+                Some(damage_type) => {
+                    let trimmed = dice.trim().trim_start_matches('+').trim();
+                    if trimmed.is_empty() {
+                        return Err(anyhow!("No dice before {} in '{}'", token, expression));
+                    }
+                    parts.push((DiceExpression::parse(trimmed)?, damage_type));
+                    dice.clear();
+                },
+                None => {
+                    dice.push_str(token);
+                }
+            }
+        }
+        if !dice.trim().trim_start_matches('+').trim().is_empty() {
+            return Err(anyhow!("'{}' in '{}' has no damage type", dice, expression));
+        }
+        if parts.is_empty() {
+            return Err(anyhow!("Empty damage roll"));
+        }
+        Ok(DamageRoll {parts})
+    }
+}
+
+pub struct DamageReport {
+    pub rolled: Vec<(DamageType, i32)>,
+    pub total: i32, // after resistances, before temporary hit points
+    pub calculation: String
+}
+
+impl Combat {
+    pub fn apply_damage(&mut self, index: usize, damage: &DamageRoll, critical: bool) -> Result<DamageReport, Error> {
+        self.apply_damage_with(index, damage, critical, &mut thread_rng())
+    }
+    // Rolls every part, halves resisted, doubles vulnerable and ignores immune damage types of the
+    // target, then applies the total and logs each step
+    pub fn apply_damage_with<R: Rng>(&mut self, index: usize, damage: &DamageRoll, critical: bool, rng: &mut R) -> Result<DamageReport, Error> {
+        self.check_index(index)?;
+        let combatant = &self.combatants[index];
+        let mut rolled: Vec<(DamageType, i32)> = Vec::new();
+        let mut steps: Vec<String> = Vec::new();
+        let mut total = 0;
+        for (expression, damage_type) in damage.parts.iter() {
+            let (dice, amount) = if critical {expression.critical_with(rng)} else {expression.roll_with(rng)};
+            let amount = amount.max(0);
+            let resisted = combatant.resistances.contains(damage_type);
+            let vulnerable = combatant.vulnerabilities.contains(damage_type);
+            let (adjusted, note) = if combatant.immunities.contains(damage_type) {
+                (0, " immune")
+            } else if resisted && vulnerable {
+                (amount, "")
+            } else if resisted {
+                (amount / 2, " resisted")
+            } else if vulnerable {
+                (amount * 2, " vulnerable")
+            } else {
+                (amount, "")
+            };
+            let dice = if dice.is_empty() {"".to_string()} else {format!(" {:?}", dice)};
+            steps.push(format!("{}{} = {} {}{} -> {}", expression.expression, dice, amount, damage_type.name(), note, adjusted));
+            rolled.push((*damage_type, adjusted));
+            total += adjusted;
+        }
+        let mut calculation = steps.join(", ");
+        if critical {
+            calculation = format!("critical: {}", calculation);
+        }
+        if combatant.temporary_hit_points > 0 {
+            calculation += &format!("; {} temporary hp absorb {}", combatant.temporary_hit_points, total.min(combatant.temporary_hit_points));
+        }
+        calculation += &format!("; total {}", total);
+        self.push_event(CombatEvent::DamageRolled {name: combatant.name.clone(), calculation: calculation.clone()});
+        self.take_damage(index, total, critical)?;
+        Ok(DamageReport {rolled, total, calculation})
+    }
+}
@@ -0,0 +1,263 @@
+use super::{Party, Encounter, Monster};
+use anyhow::{anyhow, Error};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+// XP thresholds per character level (Easy, Medium, Hard, Deadly) from the 5e basic rules (1)
+const XP_THRESHOLDS: [[i32; 4]; 20] = [
+    [25, 50, 75, 100],
+    [50, 100, 150, 200],
+    [75, 150, 225, 400],
+    [125, 250, 375, 500],
+    [250, 500, 750, 1100],
+    [300, 600, 900, 1400],
+    [350, 750, 1100, 1700],
+    [450, 900, 1400, 2100],
+    [550, 1100, 1600, 2400],
+    [600, 1200, 1900, 2800],
+    [800, 1600, 2400, 3600],
+    [1000, 2000, 3000, 4500],
+    [1100, 2200, 3400, 5100],
+    [1250, 2500, 3800, 5700],
+    [1400, 2800, 4300, 6400],
+    [1600, 3200, 4800, 7200],
+    [2000, 3900, 5900, 8800],
+    [2100, 4200, 6300, 9500],
+    [2400, 4900, 7300, 10900],
+    [2800, 5700, 8500, 12700],
+];
+const MULTIPLIERS: [f32; 8] = [0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 4.0, 5.0];
+const ATTEMPTS_PER_PROPOSAL: usize = 200;
+const SWARM_MINIMUM: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Deadly
+}
+
+impl Difficulty {
+    fn index(&self) -> usize {
+        match self {
+            Difficulty::Easy => 0,
+            Difficulty::Medium => 1,
+            Difficulty::Hard => 2,
+            Difficulty::Deadly => 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EncounterShape {
+    Any,
+    BossAndMinions, // one strong monster backed by several weaker ones of a single kind
+    Swarm // many copies of a single weak monster
+}
+
+pub struct EncounterProposal {
+    pub encounter: Encounter,
+    pub shape: EncounterShape,
+    pub base_xp: i32,
+    pub adjusted_xp: i32,
+}
+
+impl EncounterProposal {
+    pub fn get_description(&self) -> String {
+        let mut names: Vec<String> = Vec::new();
+        for monster in self.encounter.monsters.iter() {
+            names.push(monster.name.clone());
+        }
+        format!("{:?}: {} ({}xp, {}xp adjusted)", self.shape, names.join(", "), self.base_xp, self.adjusted_xp)
+    }
+}
+
+pub struct EncounterBuilder {
+    pub difficulty: Difficulty,
+    pub shape: EncounterShape,
+    pub tags: Vec<String>, // every tag must be present on a monster for it to be picked
+    pub max_monsters: usize,
+    pub proposals: usize,
+    pub seed: Option<u64>
+}
+
+impl EncounterBuilder {
+    pub fn new(difficulty: Difficulty, shape: EncounterShape, seed: Option<u64>) -> EncounterBuilder {
+        EncounterBuilder {
+            difficulty,
+            shape,
+            tags: Vec::new(),
+            max_monsters: 15,
+            proposals: 3,
+            seed
+        }
+    }
+    pub fn add_tag(&mut self, tag: &str) {
+        self.tags.push(tag.to_lowercase());
+    }
+    // Returns up to `proposals` distinct encounters whose adjusted xp lands within the party's budget
+    pub fn build(&self, party: &Party, pool: &[Monster]) -> Result<Vec<EncounterProposal>, Error> {
+        if party.members.is_empty() {
+            return Err(anyhow!("Cannot build an encounter for an empty party"));
+        }
+        if self.max_monsters == 0 {
+            return Err(anyhow!("An encounter needs room for at least one monster"));
+        }
+        let candidates: Vec<&Monster> = pool.iter()
+            .filter(|m| m.xp > 0 && self.tags.iter().all(|t| m.has_tag(t)))
+            .collect();
+        if candidates.is_empty() {
+            return Err(anyhow!("No monsters in the pool match the tags {:?}", self.tags));
+        }
+        let (low, high) = xp_budget(party, self.difficulty);
+        let party_size = party.members.len();
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        let mut proposals: Vec<EncounterProposal> = Vec::new();
+        let mut seen: Vec<Vec<String>> = Vec::new();
+        for _ in 0..(self.proposals * ATTEMPTS_PER_PROPOSAL) {
+            if proposals.len() >= self.proposals {
+                break;
+            }
+            let shape = self.shape;
+            let monsters = match shape {
+                EncounterShape::Any => self.random_mix(&candidates, party_size, high, &mut rng),
+                EncounterShape::BossAndMinions => self.boss_and_minions(&candidates, party_size, high, &mut rng),
+                EncounterShape::Swarm => self.swarm(&candidates, party_size, high, &mut rng),
+            };
+            let monsters = match monsters {
+                Some(monsters) => monsters,
+                None => continue,
+            };
+            let adjusted_xp = adjusted_xp(&monsters, party_size);
+            if adjusted_xp < low || adjusted_xp > high {
+                continue;
+            }
+            let mut signature: Vec<String> = monsters.iter().map(|m| format!("{}:{}", m.name, m.xp)).collect();
+            signature.sort();
+            if seen.contains(&signature) {
+                continue;
+            }
+            seen.push(signature);
+            let mut encounter = Encounter::new();
+            for monster in monsters.iter() {
+                encounter.add_monster((*monster).clone());
+            }
+            proposals.push(EncounterProposal {
+                encounter,
+                shape,
+                base_xp: monsters.iter().map(|m| m.xp).sum(),
+                adjusted_xp
+            });
+        }
+        if proposals.is_empty() {
+            return Err(anyhow!("Unable to fit an encounter between {}xp and {}xp with the given monsters", low, high));
+        }
+        Ok(proposals)
+    }
+
+    fn random_mix<'a>(&self, candidates: &[&'a Monster], party_size: usize, high: i32, rng: &mut StdRng) -> Option<Vec<&'a Monster>> {
+        let target = rng.gen_range(1..=self.max_monsters);
+        let mut monsters: Vec<&Monster> = Vec::new();
+        while monsters.len() < target {
+            let fitting: Vec<&&Monster> = candidates.iter()
+                .filter(|m| {
+                    let mut trial = monsters.clone();
+                    trial.push(m);
+                    adjusted_xp(&trial, party_size) <= high
+                })
+                .collect();
+            match fitting.choose(rng) {
+                Some(monster) => monsters.push(monster),
+                None => break,
+            }
+        }
+        if monsters.is_empty() {None} else {Some(monsters)}
+    }
+
+    fn boss_and_minions<'a>(&self, candidates: &[&'a Monster], party_size: usize, high: i32, rng: &mut StdRng) -> Option<Vec<&'a Monster>> {
+        let boss = *candidates.iter()
+            .filter(|m| adjusted_xp(&[**m], party_size) <= high)
+            .collect::<Vec<_>>()
+            .choose(rng)?;
+        let minion = *candidates.iter()
+            .filter(|m| m.challenge_rating < boss.challenge_rating)
+            .collect::<Vec<_>>()
+            .choose(rng)?;
+        let mut monsters: Vec<&Monster> = vec![boss];
+        while monsters.len() < self.max_monsters {
+            let mut trial = monsters.clone();
+            trial.push(minion);
+            if adjusted_xp(&trial, party_size) > high {
+                break;
+            }
+            monsters = trial;
+        }
+        if monsters.len() < 3 {None} else {Some(monsters)}
+    }
+
+    fn swarm<'a>(&self, candidates: &[&'a Monster], party_size: usize, high: i32, rng: &mut StdRng) -> Option<Vec<&'a Monster>> {
+        let kind = *candidates.choose(rng)?;
+        let mut monsters: Vec<&Monster> = Vec::new();
+        while monsters.len() < self.max_monsters {
+            let mut trial = monsters.clone();
+            trial.push(kind);
+            if adjusted_xp(&trial, party_size) > high {
+                break;
+            }
+            monsters = trial;
+        }
+        if monsters.len() < SWARM_MINIMUM {None} else {Some(monsters)}
+    }
+}
+
+// Sum of every member's threshold for the given difficulty
+pub fn party_threshold(party: &Party, difficulty: Difficulty) -> i32 {
+    let mut threshold = 0;
+    for member in party.members.iter() {
+        let level = member.level.clamp(1, 20);
+        threshold += XP_THRESHOLDS[(level - 1) as usize][difficulty.index()];
+    }
+    threshold
+}
+
+// The lower and upper adjusted xp bounds for an encounter of the given difficulty
+pub fn xp_budget(party: &Party, difficulty: Difficulty) -> (i32, i32) {
+    let low = party_threshold(party, difficulty);
+    let high = match difficulty {
+        Difficulty::Easy => party_threshold(party, Difficulty::Medium) - 1,
+        Difficulty::Medium => party_threshold(party, Difficulty::Hard) - 1,
+        Difficulty::Hard => party_threshold(party, Difficulty::Deadly) - 1,
+        Difficulty::Deadly => low + low / 2,
+    };
+    (low, high)
+}
+
+// Encounter multiplier by number of monsters, shifted for small (< 3) and large (6+) parties
+pub fn encounter_multiplier(num_monsters: usize, party_size: usize) -> f32 {
+    let mut index: usize = match num_monsters {
+        0 => return 0.0,
+        1 => 1,
+        2 => 2,
+        n if n <= 6 => 3,
+        n if n <= 10 => 4,
+        n if n <= 14 => 5,
+        _ => 6,
+    };
+    if party_size < 3 {
+        index += 1;
+    } else if party_size >= 6 {
+        index -= 1;
+    }
+    MULTIPLIERS[index]
+}
+
+fn adjusted_xp(monsters: &[&Monster], party_size: usize) -> i32 {
+    let total: i32 = monsters.iter().map(|m| m.xp).sum();
+    (total as f32 * encounter_multiplier(monsters.len(), party_size)) as i32
+}
@@ -1,4 +1,24 @@
 #![allow(dead_code, unused_variables, unused_assignments)]
+mod encounter_builder;
+mod stat_block;
+mod combat_simulator;
+mod characters;
+mod xp_awards;
+mod combat;
+mod damage;
+mod rest;
+mod progression;
+
+pub use encounter_builder::*;
+pub use stat_block::*;
+pub use combat_simulator::*;
+pub use characters::*;
+pub use xp_awards::*;
+pub use combat::*;
+pub use damage::*;
+pub use rest::*;
+pub use progression::*;
+
 pub struct Party {
     members: Vec<Member>,
 }
@@ -23,46 +43,94 @@ impl Party {
 }
 
 pub struct Member {
+    name: String, // name of the TtrpgEntity the member was built from
+    entity_id: String, // id of that entity, names can repeat
+    entity_index: Option<usize>, // where it was in the entities, for entities not saved yet and without an id
     level: i32,
     xp: i32,
 }
 
 impl Member {
     pub fn new(level: i32, xp: i32) -> Member {
-        Member {level, xp}
+        Member {name: "".to_string(), entity_id: "".to_string(), entity_index: None, level, xp}
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn entity_id(&self) -> &str {
+        &self.entity_id
+    }
+    // Identifies the member's entity, its id once saved or its position in the entities before that
+    pub fn key(&self) -> String {
+        match (self.entity_id.is_empty(), self.entity_index) {
+            (true, Some(index)) => format!("#{}", index),
+            _ => self.entity_id.clone(),
+        }
+    }
+    pub fn level(&self) -> i32 {
+        self.level
+    }
+    pub fn xp(&self) -> i32 {
+        self.xp
     }
     pub fn get_description(self) -> String {
         format!("Level {} character: {}xp", self.level, self.xp)
     }
 }
 
+#[derive(Clone)]
 pub struct Encounter {
     monsters: Vec<Monster>,
 }
 
 impl Encounter {
+    pub fn new() -> Encounter {
+        Encounter {monsters: Vec::new()}
+    }
     pub fn add_monster(&mut self, monster: Monster) {
         self.monsters.push(monster);
     }
     pub fn empty(&mut self) {
         self.monsters.clear();
     }
+    pub fn total_xp(&self) -> i32 {
+        self.monsters.iter().map(|m| m.xp).sum()
+    }
     pub fn get_description(self) -> String {
         format!("{} monsters", self.monsters.len())
     }
 }
 
+#[derive(Clone)]
 pub struct Monster {
+    name: String,
     xp: i32,
     challenge_rating: f32,
+    tags: Vec<String>, // environment, creature type, etc.
+    stat_block: Option<StatBlock>,
 }
 
 impl Monster {
     pub fn new(challenge_rating: f32, xp: i32) -> Monster {
-        Monster {challenge_rating, xp}
+        Monster {name: "Monster".to_string(), challenge_rating, xp, tags: Vec::new(), stat_block: None}
+    }
+    pub fn named(name: &str, challenge_rating: f32, xp: i32) -> Monster {
+        Monster {name: name.to_string(), challenge_rating, xp, tags: Vec::new(), stat_block: None}
+    }
+    pub fn add_tag(&mut self, tag: &str) {
+        let tag = tag.to_lowercase();
+        if !self.tags.contains(&tag) {
+            self.tags.push(tag);
+        }
+    }
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(&tag.to_lowercase())
+    }
+    pub fn set_stat_block(&mut self, stat_block: StatBlock) {
+        self.stat_block = Some(stat_block);
     }
     pub fn get_description(self) -> String {
-        format!("Monster challenge rating {}: {}xp", self.challenge_rating as i32, self.xp)
+        format!("{} challenge rating {}: {}xp", self.name, self.challenge_rating as i32, self.xp)
     }
 }
 
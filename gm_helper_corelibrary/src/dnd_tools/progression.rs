@@ -0,0 +1,231 @@
+use super::{HP_LABEL, MAX_HP_LABEL, CON_LABEL, HIT_DICE_LABEL, LEVEL_LABEL, XP_LABEL, level_for_xp, xp_for_level};
+use crate::entities::{TtrpgEntity, Elements, Counter, Resource, Recharge, Story, Roll, proficiency_bonus};
+use anyhow::{anyhow, Error};
+use rand::{thread_rng, Rng};
+use serde::{Serialize, Deserialize};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HitPointGain {
+    Rolled,
+    Average // half the hit die plus one
+}
+
+// A feature with uses becomes a resource, e.g. Action Surge once per short rest. Listing it again
+// at a later level with more uses raises the maximum.
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct ClassFeature {
+    pub level: i32,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub uses: Option<i32>,
+    #[serde(default)]
+    pub recharge: Option<Recharge>
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct ClassProgression {
+    pub name: String,
+    pub hit_die: u32,
+    #[serde(default)]
+    pub features: Vec<ClassFeature>
+}
+
+impl ClassProgression {
+    pub fn new(name: &str, hit_die: u32) -> Result<ClassProgression, Error> {
+        let class = ClassProgression {name: name.to_string(), hit_die, features: Vec::new()};
+        class.validate()?;
+        Ok(class)
+    }
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.name.trim().is_empty() {
+            return Err(anyhow!("A class needs a name"));
+        }
+        if ![6, 8, 10, 12].contains(&self.hit_die) {
+            return Err(anyhow!("{} has a d{} hit die, it should be a d6, d8, d10 or d12", self.name, self.hit_die));
+        }
+        for feature in self.features.iter() {
+            if feature.level < 1 || feature.level > 20 {
+                return Err(anyhow!("{} feature {} is at level {}, outside 1 to 20", self.name, feature.name, feature.level));
+            }
+            if feature.name.trim().is_empty() {
+                return Err(anyhow!("{} has a feature without a name at level {}", self.name, feature.level));
+            }
+            if let Some(recharge) = &feature.recharge {
+                recharge.check().map_err(|e| anyhow!("{} feature {}: {}", self.name, feature.name, e))?;
+            }
+        }
+        Ok(())
+    }
+    pub fn features_at(&self, level: i32) -> Vec<&ClassFeature> {
+        self.features.iter().filter(|f| f.level == level).collect()
+    }
+}
+
+pub struct LevelUpReport {
+    pub name: String,
+    pub from_level: i32,
+    pub to_level: i32,
+    pub changes: Vec<String>
+}
+
+impl LevelUpReport {
+    pub fn get_description(&self) -> String {
+        format!("{} reached level {}\n{}", self.name, self.to_level, self.changes.join("\n"))
+    }
+}
+
+impl TtrpgEntity {
+    // The "Level" counter, level 1 for entities without one
+    pub fn level(&self) -> i32 {
+        self.find_counter(LEVEL_LABEL).map(|c| c.number.clamp(1, 20)).unwrap_or(1)
+    }
+    pub fn proficiency_bonus(&self) -> i32 {
+        proficiency_bonus(self.level() as u32)
+    }
+    // Whether the "XP" counter has reached the next level's threshold
+    pub fn can_level_up(&self) -> bool {
+        let level = self.level();
+        level < 20 && self.find_counter(XP_LABEL).map_or(false, |c| level_for_xp(c.number) > level)
+    }
+    pub fn xp_to_next_level(&self) -> Option<i32> {
+        let level = self.level();
+        if level >= 20 {
+            return None;
+        }
+        let xp = self.find_counter(XP_LABEL).map(|c| c.number).unwrap_or(xp_for_level(level));
+        Some((xp_for_level(level + 1) - xp).max(0))
+    }
+    // Makes the entity a first level member of the class: maximum hit die plus constitution
+    // for hit points, one hit die and the first level features. Entities past first level are
+    // refused so their hit points and level are not lost.
+    pub fn start_class(&mut self, class: &ClassProgression) -> Result<LevelUpReport, Error> {
+        class.validate()?;
+        if self.level() > 1 {
+            return Err(anyhow!("{} is already level {}, undo their level ups before starting {}", self.name, self.level(), class.name));
+        }
+        let description = format!("{} level 1 {}", self.name, class.name);
+        self.record_history(&description, |entity| {
+            let from_level = entity.level();
+            let mut changes: Vec<String> = Vec::new();
+            set_counter(entity, LEVEL_LABEL, 1);
+            let constitution = entity.find_attribute(CON_LABEL).map(|a| a.modifier).unwrap_or(0);
+            let hit_points = (class.hit_die as i32 + constitution).max(1);
+            set_counter(entity, MAX_HP_LABEL, hit_points);
+            set_counter(entity, HP_LABEL, hit_points);
+            changes.push(format!("{} hp (d{} {:+} con)", hit_points, class.hit_die, constitution));
+            let order_num = entity.elements.len() as u32 + 1;
+            entity.add_element(Elements::Resource(Resource::hit_dice(order_num, order_num, 1, class.hit_die)));
+            changes.push(format!("Hit dice d{} to 1", class.hit_die));
+            grant_features(entity, class, 1, &mut changes)?;
+            entity.recalculate()?;
+            Ok(LevelUpReport {name: entity.name.clone(), from_level, to_level: 1, changes})
+        })
+    }
+    pub fn level_up(&mut self, class: &ClassProgression, hit_points: HitPointGain) -> Result<LevelUpReport, Error> {
+        self.level_up_with(class, hit_points, &mut thread_rng())
+    }
+    // Gains a level in the class: hit points, a hit die, proficiency on every skill and the
+    // features unlocked at the new level. Kept in the history so it can be undone.
+    pub fn level_up_with<R: Rng>(&mut self, class: &ClassProgression, hit_points: HitPointGain, rng: &mut R) -> Result<LevelUpReport, Error> {
+        class.validate()?;
+        let from_level = self.level();
+        if from_level >= 20 {
+            return Err(anyhow!("{} is already level 20", self.name));
+        }
+        let to_level = from_level + 1;
+        let description = format!("{} level {} {}", self.name, to_level, class.name);
+        self.record_history(&description, |entity| {
+            let mut changes: Vec<String> = Vec::new();
+            set_counter(entity, LEVEL_LABEL, to_level);
+            changes.push(format!("Level {} to {}", from_level, to_level));
+
+            let constitution = entity.find_attribute(CON_LABEL).map(|a| a.modifier).unwrap_or(0);
+            let (die, how) = match hit_points {
+                HitPointGain::Rolled => (Roll::new(class.hit_die, 1).roll_with(rng)[0] as i32, "rolled"),
+                HitPointGain::Average => (class.hit_die as i32 / 2 + 1, "average"),
+            };
+            let gained = (die + constitution).max(1);
+            if entity.find_counter(MAX_HP_LABEL).is_none() && entity.find_counter(HP_LABEL).is_none() {
+                return Err(anyhow!("{} has no {} or {} counter to raise", entity.name, HP_LABEL, MAX_HP_LABEL));
+            }
+            for label in [MAX_HP_LABEL, HP_LABEL] {
+                if let Some(counter) = entity.find_counter_mut(label) {
+                    counter.increment(gained);
+                }
+            }
+            changes.push(format!("Gained {} hp (d{} {} {} {:+} con)", gained, class.hit_die, how, die, constitution));
+
+            match entity.elements.get_mut(HIT_DICE_LABEL) {
+                Some(Elements::Resource(r)) => {
+                    r.max += 1;
+                    r.current += 1;
+                },
+                _ => {
+                    let order_num = entity.elements.len() as u32 + 1;
+                    entity.add_element(Elements::Resource(Resource::hit_dice(order_num, order_num, to_level, class.hit_die)));
+                },
+            }
+            changes.push(format!("Hit dice d{} to {}", class.hit_die, to_level));
+
+            let proficiency = proficiency_bonus(to_level as u32);
+            if proficiency != proficiency_bonus(from_level as u32) {
+                changes.push(format!("Proficiency bonus +{}", proficiency));
+            }
+            for (_key, element) in entity.elements.iter_mut() {
+                if let Elements::Skill(sk) = element {
+                    sk.level = to_level as u32;
+                    sk.proficiency = proficiency;
+                }
+            }
+
+            grant_features(entity, class, to_level, &mut changes)?;
+            entity.recalculate()?;
+            Ok(LevelUpReport {name: entity.name.clone(), from_level, to_level, changes})
+        })
+    }
+}
+
+// Adds the class features unlocked at the level to the entity
+fn grant_features(entity: &mut TtrpgEntity, class: &ClassProgression, level: i32, changes: &mut Vec<String>) -> Result<(), Error> {
+    for feature in class.features_at(level) {
+        let order_num = entity.elements.len() as u32 + 1;
+        match feature.uses {
+            Some(uses) => {
+                match entity.elements.get_mut(&feature.name) {
+                    Some(Elements::Resource(r)) => {
+                        r.max = uses;
+                        r.restore_full();
+                    },
+                    _ => {
+                        let recharge = feature.recharge.unwrap_or(Recharge::LongRest);
+                        entity.add_element(Elements::Resource(Resource::new(order_num, order_num, feature.name.clone(), uses, recharge)?));
+                    },
+                }
+                changes.push(format!("{} ({} uses)", feature.name, uses));
+            },
+            None => {
+                let story = Story::new(order_num, order_num, &feature.name, &feature.description)?;
+                story.edit.set(false);
+                entity.add_element(Elements::Story(story));
+                changes.push(feature.name.clone());
+            },
+        }
+    }
+    Ok(())
+}
+
+fn set_counter(entity: &mut TtrpgEntity, label: &str, value: i32) {
+    match entity.find_counter_mut(label) {
+        Some(counter) => {
+            counter.set(value);
+        },
+        None => {
+            let order_num = entity.elements.len() as u32 + 1;
+            entity.add_element(Elements::Counter(Counter::new(order_num, order_num, label.to_string(), value)));
+        },
+    }
+}
@@ -0,0 +1,126 @@
+use super::HP_LABEL;
+use crate::entities::{TtrpgEntity, Elements, Recharge, Roll};
+use anyhow::{anyhow, Error};
+use rand::{thread_rng, Rng};
+
+pub const MAX_HP_LABEL: &str = "Max HP";
+pub const CON_LABEL: &str = "CON";
+pub const HIT_DICE_LABEL: &str = "Hit Dice";
+
+pub struct RestReport {
+    pub changes: Vec<String>
+}
+
+impl RestReport {
+    pub fn get_description(&self) -> String {
+        if self.changes.is_empty() {
+            return "Nothing changed".to_string();
+        }
+        self.changes.join("\n")
+    }
+}
+
+impl TtrpgEntity {
+    pub fn short_rest(&mut self, hit_dice: u32) -> Result<RestReport, Error> {
+        self.short_rest_with(hit_dice, &mut thread_rng())
+    }
+    // Spends hit dice to heal (each die plus the constitution modifier) and restores short rest resources
+    pub fn short_rest_with<R: Rng>(&mut self, hit_dice: u32, rng: &mut R) -> Result<RestReport, Error> {
+        let mut changes: Vec<String> = Vec::new();
+        if hit_dice > 0 {
+            let constitution = self.find_attribute(CON_LABEL)
+                .map(|a| a.modifier)
+                .unwrap_or(0);
+            let die = match self.elements.get_mut(HIT_DICE_LABEL) {
+                Some(Elements::Resource(r)) => {
+                    r.spend(hit_dice as i32)?;
+                    r.die.unwrap_or(8)
+                },
+                _ => return Err(anyhow!("{} has no {} to spend", self.name, HIT_DICE_LABEL)),
+            };
+            let mut healing = 0;
+            for rolled in Roll::new(die, hit_dice).roll_with(rng) {
+                healing += (rolled as i32 + constitution).max(0);
+            }
+            let healed = self.heal_up_to_max(healing);
+            changes.push(format!("Spent {} hit dice (d{}) and healed {} hp", hit_dice, die, healed));
+        }
+        changes.extend(self.restore_resources(&[Recharge::ShortRest]));
+        Ok(RestReport {changes})
+    }
+    // Restores all hit points, half of the spent hit dice, short and long rest resources and one level of exhaustion
+    pub fn long_rest(&mut self) -> RestReport {
+        let mut changes: Vec<String> = Vec::new();
+        let healed = self.heal_up_to_max(i32::MAX);
+        if healed > 0 {
+            changes.push(format!("Healed {} hp", healed));
+        }
+        if let Some(Elements::Resource(r)) = self.elements.get_mut(HIT_DICE_LABEL) {
+            let restored = r.restore((r.max / 2).max(1));
+            if restored > 0 {
+                changes.push(format!("Regained {} hit dice", restored));
+            }
+        }
+        changes.extend(self.restore_resources(&[Recharge::ShortRest, Recharge::LongRest]));
+        if let Some(Elements::Condition(cd)) = self.elements.get_mut("Exhaustion") {
+            if cd.level > 1 {
+                cd.level -= 1;
+                changes.push(format!("Exhaustion reduced to {}", cd.level));
+            } else {
+                self.elements.remove("Exhaustion");
+                changes.push("Exhaustion removed".to_string());
+            }
+        }
+        RestReport {changes}
+    }
+    pub fn dawn(&mut self) -> RestReport {
+        RestReport {changes: self.restore_resources(&[Recharge::Dawn])}
+    }
+    pub fn roll_recharges(&mut self) -> RestReport {
+        self.roll_recharges_with(&mut thread_rng())
+    }
+    // Rolls for every spent "recharge x-y" resource, usually at the start of a monster's turn
+    pub fn roll_recharges_with<R: Rng>(&mut self, rng: &mut R) -> RestReport {
+        let mut changes: Vec<String> = Vec::new();
+        for (_key, element) in self.elements.iter_mut() {
+            if let Elements::Resource(r) = element {
+                if let Some(rolled) = r.roll_recharge(rng) {
+                    let result = if r.current == r.max {"recharged"} else {"not recharged"};
+                    changes.push(format!("{} rolled {} to recharge: {}", r.label, rolled, result));
+                }
+            }
+        }
+        RestReport {changes}
+    }
+
+    fn restore_resources(&mut self, recharges: &[Recharge]) -> Vec<String> {
+        let mut changes: Vec<String> = Vec::new();
+        for (_key, element) in self.elements.iter_mut() {
+            if let Elements::Resource(r) = element {
+                if r.label != HIT_DICE_LABEL && recharges.contains(&r.recharge) {
+                    let restored = r.restore_full();
+                    if restored > 0 {
+                        changes.push(format!("{} restored by {} to {}/{}", r.label, restored, r.current, r.max));
+                    }
+                }
+            }
+        }
+        changes.sort();
+        changes
+    }
+    // Heals the HP counter without going over the Max HP counter when there is one
+    fn heal_up_to_max(&mut self, amount: i32) -> i32 {
+        let max = self.find_counter(MAX_HP_LABEL).map(|c| c.number);
+        let counter = match self.find_counter_mut(HP_LABEL) {
+            Some(counter) => counter,
+            None => return 0,
+        };
+        let healed = match max {
+            Some(max) => amount.min(max - counter.number).max(0),
+            None if amount == i32::MAX => 0,
+            None => amount,
+        };
+        counter.increment(healed);
+        healed
+    }
+}
@@ -0,0 +1,47 @@
+use super::DamageType;
+use crate::entities::DiceExpression;
+use anyhow::Error;
+
+// The combat numbers of a creature, shared by party members and monsters alike
+#[derive(Clone, Debug)]
+pub struct StatBlock {
+    pub name: String,
+    pub armor_class: i32,
+    pub hit_points: i32,
+    pub attack_bonus: i32,
+    pub damage: DiceExpression,
+    pub attacks: u32, // attacks made per turn
+    pub initiative: i32, // initiative modifier
+    pub resistances: Vec<DamageType>,
+    pub vulnerabilities: Vec<DamageType>,
+    pub immunities: Vec<DamageType>
+}
+
+impl StatBlock {
+    pub fn new(name: &str, armor_class: i32, hit_points: i32, attack_bonus: i32, damage: &str, initiative: i32) -> Result<StatBlock, Error> {
+        let stat_block = StatBlock {
+            name: name.to_string(),
+            armor_class,
+            hit_points,
+            attack_bonus,
+            damage: DiceExpression::parse(damage)?,
+            attacks: 1,
+            initiative,
+            resistances: Vec::new(),
+            vulnerabilities: Vec::new(),
+            immunities: Vec::new()
+        };
+        Ok(stat_block)
+    }
+    pub fn get_description(&self) -> String {
+        format!(
+            "{} AC {} HP {} +{} to hit, {} damage x{}",
+            self.name,
+            self.armor_class,
+            self.hit_points,
+            self.attack_bonus,
+            self.damage.expression,
+            self.attacks
+        )
+    }
+}
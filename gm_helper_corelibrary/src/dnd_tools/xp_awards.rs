@@ -0,0 +1,95 @@
+use super::{Party, Member, Encounter, LevelUp, xp_for_level, level_for_xp};
+use anyhow::{anyhow, Error};
+use std::collections::HashMap;
+
+// How a member took part in an encounter or session, keyed by Member::key so members sharing a
+// name are told apart. Members without an entry count as present
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Participation {
+    Present,
+    Absent,
+    Bonus(i32) // present with extra xp on top of their share
+}
+
+pub struct XpAward {
+    pub name: String,
+    pub xp: i32,
+    pub level_up: Option<LevelUp>
+}
+
+pub struct AwardReport {
+    pub milestone: bool,
+    pub total_xp: i32,
+    pub share: i32,
+    pub awards: Vec<XpAward>
+}
+
+impl AwardReport {
+    pub fn get_description(&self) -> String {
+        let mut lines: Vec<String> = if self.milestone {
+            vec![format!("Milestone reached: {}xp awarded", self.total_xp)]
+        } else {
+            vec![format!("{}xp split into shares of {}xp", self.total_xp, self.share)]
+        };
+        for award in self.awards.iter() {
+            match &award.level_up {
+                Some(level_up) => lines.push(format!("{}: +{}xp, {}", award.name, award.xp, level_up.get_description())),
+                None => lines.push(format!("{}: +{}xp", award.name, award.xp)),
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+fn participation_of(participation: &HashMap<String, Participation>, member: &Member) -> Participation {
+    *participation.get(&member.key()).unwrap_or(&Participation::Present)
+}
+
+impl Party {
+    // Splits the encounter's xp evenly (rounded down) between the members who took part
+    pub fn award_encounter(&mut self, encounter: &Encounter, participation: &HashMap<String, Participation>) -> Result<AwardReport, Error> {
+        let present = self.members.iter()
+            .filter(|m| participation_of(participation, m) != Participation::Absent)
+            .count();
+        if present == 0 {
+            return Err(anyhow!("No party members took part in the encounter"));
+        }
+        let total_xp = encounter.total_xp();
+        let share = total_xp / present as i32;
+        let mut awards: Vec<XpAward> = Vec::new();
+        for member in self.members.iter_mut() {
+            let xp = match participation_of(participation, member) {
+                Participation::Absent => continue,
+                Participation::Present => share,
+                Participation::Bonus(bonus) => share + bonus,
+            };
+            let level_up = member.add_xp(xp);
+            awards.push(XpAward {name: member.name.clone(), xp, level_up});
+        }
+        Ok(AwardReport {milestone: false, total_xp, share, awards})
+    }
+    // Milestone campaigns level up per session milestone, each present member is raised to the
+    // xp threshold of their next level so xp based tools stay in step
+    pub fn award_milestone(&mut self, participation: &HashMap<String, Participation>) -> Result<AwardReport, Error> {
+        let mut awards: Vec<XpAward> = Vec::new();
+        let mut total_xp = 0;
+        for member in self.members.iter_mut() {
+            if participation_of(participation, member) == Participation::Absent {
+                continue;
+            }
+            let current = member.level.max(level_for_xp(member.xp));
+            if current >= 20 {
+                awards.push(XpAward {name: member.name.clone(), xp: 0, level_up: None});
+                continue;
+            }
+            let xp = xp_for_level(current + 1) - member.xp;
+            total_xp += xp;
+            let level_up = member.add_xp(xp);
+            awards.push(XpAward {name: member.name.clone(), xp, level_up});
+        }
+        if awards.is_empty() {
+            return Err(anyhow!("No party members took part in the milestone"));
+        }
+        Ok(AwardReport {milestone: true, total_xp, share: 0, awards})
+    }
+}
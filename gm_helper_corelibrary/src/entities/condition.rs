@@ -0,0 +1,151 @@
+use super::DiceExpression;
+use anyhow::{anyhow, Error};
+use serde::{Serialize, Deserialize};
+use std::cell::Cell;
+
+const ROUNDS_PER_MINUTE: u32 = 10; // a round is six seconds
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Duration {
+    Rounds(u32),
+    Minutes(u32),
+    UntilSave {ability: String, difficulty: u32},
+    UntilEndOfNextTurn,
+    Indefinite
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stacking {
+    Replace, // the newest application wins
+    Refresh, // keeps whichever duration lasts longer
+    Stack {max_level: u32}, // levels add up to a maximum, e.g. exhaustion
+    Independent // every application is tracked on its own
+}
+
+// A bonus or penalty added to rolls of the targeted attribute or skill label, "*" targets every roll
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct RollModifier {
+    pub target: String,
+    pub expression: DiceExpression
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct Condition {
+    pub edit: Cell<bool>,
+    pub id: u32,
+    pub order_num: u32,
+    pub label: String,
+    pub source: String,
+    pub duration: Duration,
+    pub remaining_rounds: Option<u32>, // only rounds and minutes durations count down
+    pub stacking: Stacking,
+    pub level: u32,
+    pub modifiers: Vec<RollModifier>,
+    #[serde(default)]
+    pub applied_during_own_turn: bool // the end of the turn it was applied in doesn't count as the next turn
+}
+
+impl Condition {
+    pub fn new(id: u32, order_num: u32, label: String, source: String, duration: Duration, stacking: Stacking) -> Result<Condition, Error> {
+        let remaining_rounds = match &duration {
+            Duration::Rounds(0) | Duration::Minutes(0) => return Err(anyhow!("{} needs a duration longer than zero", label)),
+            Duration::Rounds(rounds) => Some(*rounds),
+            Duration::Minutes(minutes) => Some(minutes * ROUNDS_PER_MINUTE),
+            _ => None,
+        };
+        let condition = Condition {
+            edit: Cell::new(false),
+            id,
+            order_num,
+            label,
+            source,
+            duration,
+            remaining_rounds,
+            stacking,
+            level: 1,
+            modifiers: Vec::new(),
+            applied_during_own_turn: false
+        };
+        Ok(condition)
+    }
+    // Exhaustion stacks up to six levels and lasts until removed by a long rest
+    pub fn exhaustion(id: u32, order_num: u32, source: String) -> Condition {
+        Condition::new(id, order_num, "Exhaustion".to_string(), source, Duration::Indefinite, Stacking::Stack {max_level: 6})
+            .expect("exhaustion has a valid duration")
+    }
+    pub fn add_modifier(&mut self, target: &str, expression: &str) -> Result<(), Error> {
+        self.modifiers.push(RollModifier {target: target.to_string(), expression: DiceExpression::parse(expression)?});
+        Ok(())
+    }
+    pub fn applies_to(&self, label: &str) -> bool {
+        self.modifiers.iter().any(|m| m.target == "*" || m.target.eq_ignore_ascii_case(label))
+    }
+    pub fn get_description(&self) -> String {
+        let level = if self.level > 1 {format!(" {}", self.level)} else {"".to_string()};
+        let duration = match (&self.duration, self.remaining_rounds) {
+            (Duration::UntilSave {ability, difficulty}, _) => format!("until a DC {} {} save", difficulty, ability),
+            (Duration::UntilEndOfNextTurn, _) => "until the end of next turn".to_string(),
+            (Duration::Indefinite, _) => "indefinitely".to_string(),
+            (_, Some(rounds)) => format!("{} rounds left", rounds),
+            (_, None) => "expired".to_string(),
+        };
+        format!("{}{} from {} ({})", self.label, level, self.source, duration)
+    }
+    // Combines a new application of the same condition according to its stacking rule
+    pub fn stack(&mut self, incoming: Condition) {
+        match incoming.stacking {
+            Stacking::Replace | Stacking::Independent => *self = incoming,
+            Stacking::Refresh => {
+                let longer = match (self.remaining_rounds, incoming.remaining_rounds) {
+                    (Some(current), Some(new)) => new > current,
+                    (Some(_), None) => true,
+                    _ => false,
+                };
+                if longer {
+                    self.duration = incoming.duration;
+                    self.remaining_rounds = incoming.remaining_rounds;
+                    self.source = incoming.source;
+                    self.applied_during_own_turn = incoming.applied_during_own_turn;
+                }
+            },
+            Stacking::Stack {max_level} => {
+                self.level = (self.level + incoming.level).min(max_level);
+            }
+        }
+    }
+    // Each of these returns true once the condition has expired
+    pub fn tick_rounds(&mut self, rounds: u32) -> bool {
+        match self.remaining_rounds {
+            Some(remaining) => {
+                let remaining = remaining.saturating_sub(rounds);
+                self.remaining_rounds = Some(remaining);
+                remaining == 0
+            },
+            None => false,
+        }
+    }
+    pub fn advance_minutes(&mut self, minutes: u32) -> bool {
+        self.tick_rounds(minutes * ROUNDS_PER_MINUTE)
+    }
+    // Called at the end of each of the affected's turns
+    pub fn end_turn(&mut self) -> bool {
+        if self.duration != Duration::UntilEndOfNextTurn {
+            return false;
+        }
+        if self.applied_during_own_turn {
+            self.applied_during_own_turn = false;
+            return false;
+        }
+        true
+    }
+    pub fn resolve_save(&mut self, ability: &str, total: u32) -> bool {
+        match &self.duration {
+            Duration::UntilSave {ability: needed, difficulty} => needed.eq_ignore_ascii_case(ability) && total >= *difficulty,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use super::{TtrpgEntity, Elements, Skill, Formula, score_modifier};
+use anyhow::{anyhow, Error};
+use serde::{Serialize, Deserialize};
+use std::cell::Cell;
+use std::collections::HashMap;
+
+// A number computed from other elements with a formula, e.g. a save of "floor((@DEX - 10) / 2) + @Proficiency"
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct Derived {
+    pub edit: Cell<bool>,
+    pub id: u32,
+    pub order_num: u32,
+    pub label: String,
+    pub formula: String,
+    pub value: f64
+}
+
+impl Derived {
+    pub fn new(id: u32, order_num: u32, label: String, formula: &str) -> Result<Derived, Error> {
+        Formula::parse(formula)?;
+        let derived = Derived {
+            edit: Cell::new(false),
+            id,
+            order_num,
+            label,
+            formula: formula.to_string(),
+            value: 0.0
+        };
+        Ok(derived)
+    }
+    pub fn get_description(&self) -> String {
+        format!("{}: {} ({})", self.label, self.value, self.formula)
+    }
+}
+
+impl TtrpgEntity {
+    // The number a formula sees for a label: attribute scores, counters, skill levels,
+    // remaining resources and other derived values
+    pub fn value_of(&self, label: &str) -> Option<f64> {
+        for (_key, element) in self.elements.iter() {
+            let value = match element {
+                Elements::Attribute(a) if a.label.eq_ignore_ascii_case(label) => a.roll.base_result as f64,
+                Elements::Counter(c) if c.label.eq_ignore_ascii_case(label) => c.number as f64,
+                Elements::Skill(sk) if sk.label.eq_ignore_ascii_case(label) => sk.skill_level as f64,
+                Elements::Resource(r) if r.label.eq_ignore_ascii_case(label) => r.current as f64,
+                Elements::Derived(d) if d.label.eq_ignore_ascii_case(label) => d.value,
+                _ => continue,
+            };
+            return Some(value);
+        }
+        None
+    }
+    // Recalculates every derived value and skill with a formula in dependency order, returning the labels that changed
+    pub fn recalculate(&mut self) -> Result<Vec<String>, Error> {
+        let order = self.derived_order()?;
+        self.evaluate_derived(&order)
+    }
+    // Recalculates only the derived values and skills that depend on the label, directly or through others
+    pub fn recalculate_dependents(&mut self, label: &str) -> Result<Vec<String>, Error> {
+        let order = self.derived_order()?;
+        let formulas = self.derived_formulas()?;
+        let mut affected: Vec<String> = vec![label.to_lowercase()];
+        let mut dependents: Vec<String> = Vec::new();
+        for key in order.iter() {
+            let (derived_label, formula) = &formulas[key];
+            if formula.dependencies().iter().any(|d| affected.contains(d)) {
+                affected.push(derived_label.to_lowercase());
+                dependents.push(key.clone());
+            }
+        }
+        self.evaluate_derived(&dependents)
+    }
+    // Changes an attribute's score and updates everything derived from it
+    pub fn set_attribute_score(&mut self, label: &str, score: u32) -> Result<Vec<String>, Error> {
+        let mut found = false;
+        for (_key, element) in self.elements.iter_mut() {
+            if let Elements::Attribute(a) = element {
+                if a.label.eq_ignore_ascii_case(label) {
+                    a.roll.base_result = score;
+                    a.modifier = score_modifier(score);
+                    found = true;
+                }
+            }
+        }
+        if !found {
+            return Err(anyhow!("{} has no attribute {}", self.name, label));
+        }
+        self.recalculate_dependents(label)
+    }
+    // Edits an attribute through Attribute::edit and updates everything derived from it
+    pub fn edit_attribute(&mut self, label: &str, new_text: String, change_base_by: u32) -> Result<Vec<String>, Error> {
+        let mut found = false;
+        for (_key, element) in self.elements.iter_mut() {
+            if let Elements::Attribute(a) = element {
+                if a.label.eq_ignore_ascii_case(label) {
+                    a.edit(new_text.clone(), change_base_by);
+                    found = true;
+                }
+            }
+        }
+        if !found {
+            return Err(anyhow!("{} has no attribute {}", self.name, label));
+        }
+        self.recalculate_dependents(label)
+    }
+
+    // Element keys of derived values and skills with a formula mapped to their label and parsed formula
+    fn derived_formulas(&self) -> Result<HashMap<String, (String, Formula)>, Error> {
+        let mut formulas = HashMap::new();
+        for (key, element) in self.elements.iter() {
+            let (label, formula) = match element {
+                Elements::Derived(d) => (&d.label, &d.formula),
+                Elements::Skill(Skill {label, formula: Some(formula), ..}) => (label, formula),
+                _ => continue,
+            };
+            let formula = Formula::parse(formula).map_err(|e| anyhow!("{}: {}", label, e))?;
+            formulas.insert(key.clone(), (label.clone(), formula));
+        }
+        Ok(formulas)
+    }
+    // Orders derived values so each comes after the ones it reads, failing on cycles
+    fn derived_order(&self) -> Result<Vec<String>, Error> {
+        let formulas = self.derived_formulas()?;
+        let mut by_label: HashMap<String, String> = HashMap::new();
+        for (key, (label, _formula)) in formulas.iter() {
+            by_label.insert(label.to_lowercase(), key.clone());
+        }
+        let mut keys: Vec<&String> = formulas.keys().collect();
+        keys.sort();
+        let mut order: Vec<String> = Vec::new();
+        let mut visiting: Vec<String> = Vec::new();
+        for key in keys {
+            visit(key, &formulas, &by_label, &mut visiting, &mut order)?;
+        }
+        Ok(order)
+    }
+    fn evaluate_derived(&mut self, keys: &[String]) -> Result<Vec<String>, Error> {
+        let formulas = self.derived_formulas()?;
+        let mut changed: Vec<String> = Vec::new();
+        for key in keys.iter() {
+            let (label, formula) = &formulas[key];
+            let value = formula.evaluate(&|l: &str| self.value_of(l))
+                .map_err(|e| anyhow!("{}: {}", label, e))?;
+            match self.elements.get_mut(key) {
+                Some(Elements::Derived(d)) if d.value != value => {
+                    d.value = value;
+                    changed.push(d.label.clone());
+                },
+                // skill levels are whole and never negative
+                Some(Elements::Skill(sk)) if sk.skill_level != value.floor().max(0.0) as u32 => {
+                    sk.skill_level = value.floor().max(0.0) as u32;
+                    changed.push(sk.label.clone());
+                },
+                _ => (),
+            }
+        }
+        Ok(changed)
+    }
+}
+
+fn visit(key: &String, formulas: &HashMap<String, (String, Formula)>, by_label: &HashMap<String, String>, visiting: &mut Vec<String>, order: &mut Vec<String>) -> Result<(), Error> {
+    if order.contains(key) {
+        return Ok(());
+    }
+    if let Some(start) = visiting.iter().position(|k| k == key) {
+        let mut cycle: Vec<String> = visiting[start..].iter().map(|k| formulas[k].0.clone()).collect();
+        cycle.push(formulas[key].0.clone());
+        return Err(anyhow!("Derived values depend on each other in a cycle: {}", cycle.join(" -> ")));
+    }
+    visiting.push(key.clone());
+    for dependency in formulas[key].1.dependencies() {
+        if let Some(dependency_key) = by_label.get(&dependency) {
+            visit(dependency_key, formulas, by_label, visiting, order)?;
+        }
+    }
+    visiting.pop();
+    order.push(key.clone());
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use super::{Roll, NUMBER_LIMIT};
+use anyhow::{anyhow, Error};
+use rand::{thread_rng, Rng};
+use serde::{Serialize, Deserialize};
+
+// A sum of dice and flat modifiers such as "2d6 + 1d4 - 1"
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct DiceExpression {
+    pub expression: String,
+    pub rolls: Vec<(i32, Roll)>, // sign of the term and the dice to roll
+    pub modifier: i32
+}
+
+impl DiceExpression {
+    pub fn parse(expression: &str) -> Result<DiceExpression, Error> {
+        let cleaned: String = expression.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase();
+        if cleaned.is_empty() {
+            return Err(anyhow!("Empty dice expression"));
+        }
+        let mut rolls: Vec<(i32, Roll)> = Vec::new();
+        let mut modifier: i32 = 0;
+        let mut sign: i32 = 1;
+        let mut term = String::new();
+        // a trailing '+' flushes the last term
+        for c in cleaned.chars().chain("+".chars()) {
+            if c == '+' || c == '-' {
+                if term.is_empty() {
+                    if rolls.is_empty() && modifier == 0 && c == '-' {
+                        sign = -1;
+                        continue;
+                    }
+                    return Err(anyhow!("Missing term in dice expression '{}'", expression));
+                }
+                match term.split_once('d') {
+                    Some((amount, dice)) => {
+                        let amount: u32 = if amount.is_empty() {1} else {
+                            amount.parse().map_err(|_| anyhow!("Invalid dice amount '{}' in '{}'", amount, expression))?
+                        };
+                        let dice: u32 = dice.parse().map_err(|_| anyhow!("Invalid dice size '{}' in '{}'", dice, expression))?;
+                        if amount == 0 || dice == 0 || amount > NUMBER_LIMIT as u32 || dice > NUMBER_LIMIT as u32 {
+                            return Err(anyhow!("Dice out of range in '{}'", expression));
+                        }
+                        rolls.push((sign, Roll::new(dice, amount)));
+                    },
+                    None => {
+                        let number: i32 = term.parse().map_err(|_| anyhow!("Invalid number '{}' in '{}'", term, expression))?;
+                        if number > NUMBER_LIMIT {
+                            return Err(anyhow!("Modifier out of range in '{}'", expression));
+                        }
+                        modifier += sign * number;
+                    }
+                }
+                term.clear();
+                sign = if c == '-' {-1} else {1};
+            } else {
+                term.push(c);
+            }
+        }
+        Ok(DiceExpression {
+            expression: cleaned,
+            rolls,
+            modifier
+        })
+    }
+    pub fn roll(&self) -> (Vec<u32>, i32) {
+        self.roll_with(&mut thread_rng())
+    }
+    // Returns every die rolled and the total including modifiers
+    pub fn roll_with<R: Rng>(&self, rng: &mut R) -> (Vec<u32>, i32) {
+        self.roll_times(rng, 1)
+    }
+    // Critical hits roll every die twice; the flat modifier is only added once
+    pub fn critical_with<R: Rng>(&self, rng: &mut R) -> (Vec<u32>, i32) {
+        self.roll_times(rng, 2)
+    }
+    pub fn average(&self) -> f32 {
+        let mut average = self.modifier as f32;
+        for (sign, roll) in self.rolls.iter() {
+            average += *sign as f32 * roll.amount as f32 * (roll.dice as f32 + 1.0) / 2.0;
+        }
+        average
+    }
+    pub fn get_description(&self) -> String {
+        format!("{} (avg {})", self.expression, self.average())
+    }
+    fn roll_times<R: Rng>(&self, rng: &mut R, times: u32) -> (Vec<u32>, i32) {
+        let mut dice: Vec<u32> = Vec::new();
+        let mut total = self.modifier;
+        for (sign, roll) in self.rolls.iter() {
+            for _ in 0..times {
+                let rolled = roll.roll_with(rng);
+                total += sign * rolled.iter().sum::<u32>() as i32;
+                dice.extend(rolled);
+            }
+        }
+        (dice, total)
+    }
+}
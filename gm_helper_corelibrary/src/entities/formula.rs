@@ -0,0 +1,303 @@
+use anyhow::{anyhow, Error};
+
+// Formulas reference other elements by label with "@STR" or "@{Max HP}" for labels with spaces,
+// e.g. "floor((@STR - 10) / 2)" or "@level >= 5 ? 3 : 2". Comparisons give 1 or 0.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Label(String),
+    Name(String),
+    Operator(String),
+    OpenParen,
+    CloseParen,
+    Comma,
+    Question,
+    Colon
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(f64),
+    Label(String),
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(String, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>)
+}
+
+#[derive(Clone, Debug)]
+pub struct Formula {
+    pub source: String,
+    expr: Expr
+}
+
+impl Formula {
+    pub fn parse(source: &str) -> Result<Formula, Error> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {tokens, position: 0, source};
+        let expr = parser.conditional()?;
+        if parser.position < parser.tokens.len() {
+            return Err(anyhow!("Unexpected {:?} in formula '{}'", parser.tokens[parser.position], source));
+        }
+        Ok(Formula {source: source.to_string(), expr})
+    }
+    // Every label the formula reads, lowercased and without duplicates
+    pub fn dependencies(&self) -> Vec<String> {
+        let mut labels = Vec::new();
+        collect_labels(&self.expr, &mut labels);
+        labels
+    }
+    // `lookup` resolves a lowercased label to its current value
+    pub fn evaluate<F: Fn(&str) -> Option<f64>>(&self, lookup: &F) -> Result<f64, Error> {
+        evaluate(&self.expr, lookup, &self.source)
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, Error> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number = text.parse::<f64>().map_err(|_| anyhow!("Invalid number '{}' in formula '{}'", text, source))?;
+            tokens.push(Token::Number(number));
+        } else if c == '@' {
+            i += 1;
+            let label: String = if i < chars.len() && chars[i] == '{' {
+                let start = i + 1;
+                while i < chars.len() && chars[i] != '}' {
+                    i += 1;
+                }
+                if i >= chars.len() {
+                    return Err(anyhow!("Unclosed '@{{' in formula '{}'", source));
+                }
+                i += 1;
+                chars[start..i - 1].iter().collect()
+            } else {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                chars[start..i].iter().collect()
+            };
+            if label.trim().is_empty() {
+                return Err(anyhow!("Missing label after '@' in formula '{}'", source));
+            }
+            tokens.push(Token::Label(label.trim().to_lowercase()));
+        } else if c.is_alphabetic() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Name(chars[start..i].iter().collect::<String>().to_lowercase()));
+        } else {
+            let pair: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            let token = match pair.as_str() {
+                ">=" | "<=" | "==" | "!=" | "&&" | "||" => {
+                    i += 2;
+                    Token::Operator(pair)
+                },
+                _ => {
+                    i += 1;
+                    match c {
+                        '+' | '-' | '*' | '/' | '%' | '<' | '>' | '!' => Token::Operator(c.to_string()),
+                        '(' => Token::OpenParen,
+                        ')' => Token::CloseParen,
+                        ',' => Token::Comma,
+                        '?' => Token::Question,
+                        ':' => Token::Colon,
+                        _ => return Err(anyhow!("Unexpected '{}' at position {} in formula '{}'", c, i, source)),
+                    }
+                }
+            };
+            tokens.push(token);
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    source: &'a str
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+    fn next(&mut self) -> Result<Token, Error> {
+        let token = self.tokens.get(self.position).cloned()
+            .ok_or_else(|| anyhow!("Formula '{}' ends unexpectedly", self.source))?;
+        self.position += 1;
+        Ok(token)
+    }
+    fn expect(&mut self, expected: Token) -> Result<(), Error> {
+        let token = self.next()?;
+        if token != expected {
+            return Err(anyhow!("Expected {:?} but found {:?} in formula '{}'", expected, token, self.source));
+        }
+        Ok(())
+    }
+    fn operator_in(&self, operators: &[&str]) -> Option<String> {
+        match self.peek() {
+            Some(Token::Operator(op)) if operators.contains(&op.as_str()) => Some(op.clone()),
+            _ => None,
+        }
+    }
+    fn conditional(&mut self) -> Result<Expr, Error> {
+        let condition = self.binary(0)?;
+        if self.peek() == Some(&Token::Question) {
+            self.position += 1;
+            let then = self.conditional()?;
+            self.expect(Token::Colon)?;
+            let otherwise = self.conditional()?;
+            return Ok(Expr::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise)));
+        }
+        Ok(condition)
+    }
+    // Binary operators from the loosest to the tightest binding
+    fn binary(&mut self, level: usize) -> Result<Expr, Error> {
+        const LEVELS: [&[&str]; 6] = [&["||"], &["&&"], &["==", "!="], &["<", "<=", ">", ">="], &["+", "-"], &["*", "/", "%"]];
+        if level >= LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self.operator_in(LEVELS[level]) {
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+    fn unary(&mut self) -> Result<Expr, Error> {
+        match self.operator_in(&["-", "!"]) {
+            Some(op) => {
+                self.position += 1;
+                let operand = self.unary()?;
+                if op == "-" {Ok(Expr::Negate(Box::new(operand)))} else {Ok(Expr::Not(Box::new(operand)))}
+            },
+            None => self.primary(),
+        }
+    }
+    fn primary(&mut self) -> Result<Expr, Error> {
+        match self.next()? {
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::Label(label) => Ok(Expr::Label(label)),
+            Token::OpenParen => {
+                let inner = self.conditional()?;
+                self.expect(Token::CloseParen)?;
+                Ok(inner)
+            },
+            Token::Name(name) => {
+                self.expect(Token::OpenParen)?;
+                let mut arguments = Vec::new();
+                if self.peek() != Some(&Token::CloseParen) {
+                    loop {
+                        arguments.push(self.conditional()?);
+                        if self.peek() == Some(&Token::Comma) {
+                            self.position += 1;
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                self.expect(Token::CloseParen)?;
+                Ok(Expr::Call(name, arguments))
+            },
+            token => Err(anyhow!("Unexpected {:?} in formula '{}'", token, self.source)),
+        }
+    }
+}
+
+fn collect_labels(expr: &Expr, labels: &mut Vec<String>) {
+    match expr {
+        Expr::Number(_) => {},
+        Expr::Label(label) => {
+            if !labels.contains(label) {
+                labels.push(label.clone());
+            }
+        },
+        Expr::Negate(inner) | Expr::Not(inner) => collect_labels(inner, labels),
+        Expr::Binary(_, left, right) => {
+            collect_labels(left, labels);
+            collect_labels(right, labels);
+        },
+        Expr::Conditional(condition, then, otherwise) => {
+            collect_labels(condition, labels);
+            collect_labels(then, labels);
+            collect_labels(otherwise, labels);
+        },
+        Expr::Call(_, arguments) => {
+            for argument in arguments.iter() {
+                collect_labels(argument, labels);
+            }
+        },
+    }
+}
+
+fn truth(value: bool) -> f64 {
+    if value {1.0} else {0.0}
+}
+
+fn evaluate<F: Fn(&str) -> Option<f64>>(expr: &Expr, lookup: &F, source: &str) -> Result<f64, Error> {
+    match expr {
+        Expr::Number(n) => Ok(*n),
+        Expr::Label(label) => lookup(label).ok_or_else(|| anyhow!("Unknown label @{} in formula '{}'", label, source)),
+        Expr::Negate(inner) => Ok(-evaluate(inner, lookup, source)?),
+        Expr::Not(inner) => Ok(truth(evaluate(inner, lookup, source)? == 0.0)),
+        Expr::Conditional(condition, then, otherwise) => {
+            if evaluate(condition, lookup, source)? != 0.0 {
+                evaluate(then, lookup, source)
+            } else {
+                evaluate(otherwise, lookup, source)
+            }
+        },
+        Expr::Binary(op, left, right) => {
+            let left = evaluate(left, lookup, source)?;
+            let right = evaluate(right, lookup, source)?;
+            match op.as_str() {
+                "+" => Ok(left + right),
+                "-" => Ok(left - right),
+                "*" => Ok(left * right),
+                "/" | "%" if right == 0.0 => Err(anyhow!("Division by zero in formula '{}'", source)),
+                "/" => Ok(left / right),
+                "%" => Ok(left % right),
+                "<" => Ok(truth(left < right)),
+                "<=" => Ok(truth(left <= right)),
+                ">" => Ok(truth(left > right)),
+                ">=" => Ok(truth(left >= right)),
+                "==" => Ok(truth(left == right)),
+                "!=" => Ok(truth(left != right)),
+                "&&" => Ok(truth(left != 0.0 && right != 0.0)),
+                _ => Ok(truth(left != 0.0 || right != 0.0)),
+            }
+        },
+        Expr::Call(name, arguments) => {
+            let mut values = Vec::new();
+            for argument in arguments.iter() {
+                values.push(evaluate(argument, lookup, source)?);
+            }
+            match (name.as_str(), values.as_slice()) {
+                ("floor", [x]) => Ok(x.floor()),
+                ("ceil", [x]) => Ok(x.ceil()),
+                ("round", [x]) => Ok(x.round()),
+                ("abs", [x]) => Ok(x.abs()),
+                ("min", [first, rest @ ..]) => Ok(rest.iter().fold(*first, |a, b| a.min(*b))),
+                ("max", [first, rest @ ..]) => Ok(rest.iter().fold(*first, |a, b| a.max(*b))),
+                ("floor" | "ceil" | "round" | "abs", _) => Err(anyhow!("{}() takes one argument in formula '{}'", name, source)),
+                ("min" | "max", _) => Err(anyhow!("{}() needs at least one argument in formula '{}'", name, source)),
+                _ => Err(anyhow!("Unknown function {}() in formula '{}'", name, source)),
+            }
+        },
+    }
+}
@@ -0,0 +1,164 @@
+use super::{TtrpgEntity, Elements, Attribute};
+use crate::session_log::{SessionLog, LogKind};
+use anyhow::{anyhow, Error};
+use rand::{thread_rng, Rng};
+
+pub const ABILITY_LABELS: [&str; 6] = ["STR", "DEX", "CON", "INT", "WIS", "CHA"];
+pub const STANDARD_ARRAY: [u32; 6] = [15, 14, 13, 12, 10, 8];
+pub const HEROIC_ARRAY: [u32; 6] = [17, 15, 13, 12, 10, 8];
+
+// Cost of each score a point buy allows, scores missing from the table cannot be bought
+#[derive(Clone, Debug)]
+pub struct PointBuy {
+    pub costs: Vec<(u32, u32)>,
+    pub budget: u32
+}
+
+impl PointBuy {
+    pub fn new(costs: Vec<(u32, u32)>, budget: u32) -> Result<PointBuy, Error> {
+        if costs.is_empty() {
+            return Err(anyhow!("A point buy needs at least one score in its cost table"));
+        }
+        Ok(PointBuy {costs, budget})
+    }
+    // 8 to 15 for 27 points
+    pub fn standard() -> PointBuy {
+        PointBuy {
+            costs: vec![(8, 0), (9, 1), (10, 2), (11, 3), (12, 4), (13, 5), (14, 7), (15, 9)],
+            budget: 27
+        }
+    }
+    // Same costs, extended to 17, with a larger budget
+    pub fn heroic() -> PointBuy {
+        let mut point_buy = PointBuy::standard();
+        point_buy.costs.extend([(16, 12), (17, 15)]);
+        point_buy.budget = 35;
+        point_buy
+    }
+    pub fn cost(&self, score: u32) -> Option<u32> {
+        self.costs.iter().find(|(s, _)| *s == score).map(|(_, cost)| *cost)
+    }
+    // The total cost of the scores, failing when a score is not for sale or the budget is overspent
+    pub fn spend(&self, scores: &[u32]) -> Result<u32, Error> {
+        let mut total = 0;
+        for score in scores.iter() {
+            total += self.cost(*score).ok_or_else(|| {
+                let lowest = self.costs.iter().map(|(s, _)| *s).min().unwrap_or(0);
+                let highest = self.costs.iter().map(|(s, _)| *s).max().unwrap_or(0);
+                anyhow!("{} cannot be bought, scores go from {} to {}", score, lowest, highest)
+            })?;
+        }
+        if total > self.budget {
+            return Err(anyhow!("Spent {} points but the budget is {}", total, self.budget));
+        }
+        Ok(total)
+    }
+}
+
+pub enum Generation {
+    FourDropLowest, // 4d6 and drop the lowest die
+    ThreeInOrder, // 3d6 straight down the list
+    HeroicFourDropLowest, // 4d6 drop lowest rerolling ones
+    HeroicInOrder, // 2d6 + 6 in order
+    PointBuy(PointBuy, Vec<u32>), // the scores bought, in the same order as the labels
+    StandardArray(Vec<u32>), // the standard array rearranged to match the labels
+    HeroicArray(Vec<u32>)
+}
+
+impl Generation {
+    pub fn name(&self) -> &str {
+        match self {
+            Generation::FourDropLowest => "4d6 drop lowest",
+            Generation::ThreeInOrder => "3d6 in order",
+            Generation::HeroicFourDropLowest => "4d6 drop lowest rerolling ones",
+            Generation::HeroicInOrder => "2d6+6 in order",
+            Generation::PointBuy(..) => "point buy",
+            Generation::StandardArray(_) => "standard array",
+            Generation::HeroicArray(_) => "heroic array",
+        }
+    }
+    // One score per label along with a description of how it came about
+    pub fn scores_with<R: Rng>(&self, labels: &[&str], rng: &mut R) -> Result<Vec<(u32, String)>, Error> {
+        let mut scores = Vec::new();
+        match self {
+            Generation::FourDropLowest | Generation::HeroicFourDropLowest => {
+                let reroll_ones = matches!(self, Generation::HeroicFourDropLowest);
+                for _ in labels.iter() {
+                    let mut dice: Vec<u32> = (0..4).map(|_| if reroll_ones {rng.gen_range(2..=6)} else {rng.gen_range(1..=6)}).collect();
+                    dice.sort_unstable_by(|a, b| b.cmp(a));
+                    let dropped = dice.pop().unwrap_or(0);
+                    let score = dice.iter().sum();
+                    scores.push((score, format!("{:?} dropping {}", dice, dropped)));
+                }
+            },
+            Generation::ThreeInOrder | Generation::HeroicInOrder => {
+                let (amount, bonus) = if matches!(self, Generation::HeroicInOrder) {(2, 6)} else {(3, 0)};
+                for _ in labels.iter() {
+                    let dice: Vec<u32> = (0..amount).map(|_| rng.gen_range(1..=6)).collect();
+                    let score = dice.iter().sum::<u32>() + bonus;
+                    let description = if bonus > 0 {format!("{:?} + {}", dice, bonus)} else {format!("{:?}", dice)};
+                    scores.push((score, description));
+                }
+            },
+            Generation::PointBuy(point_buy, bought) => {
+                check_count(labels, bought)?;
+                let spent = point_buy.spend(bought)?;
+                for score in bought.iter() {
+                    scores.push((*score, format!("bought for {} of {} points", point_buy.cost(*score).unwrap_or(0), spent)));
+                }
+            },
+            Generation::StandardArray(assigned) | Generation::HeroicArray(assigned) => {
+                check_count(labels, assigned)?;
+                let array = if matches!(self, Generation::HeroicArray(_)) {HEROIC_ARRAY} else {STANDARD_ARRAY};
+                // each score of the array can be used once
+                let mut remaining = array.to_vec();
+                for score in assigned.iter() {
+                    match remaining.iter().position(|s| s == score) {
+                        Some(index) => {
+                            remaining.remove(index);
+                        },
+                        None => return Err(anyhow!("{:?} is not an arrangement of the {} {:?}", assigned, self.name(), array)),
+                    }
+                }
+                for score in assigned.iter() {
+                    scores.push((*score, format!("from the {}", self.name())));
+                }
+            },
+        }
+        Ok(scores)
+    }
+}
+
+fn check_count(labels: &[&str], scores: &[u32]) -> Result<(), Error> {
+    if labels.len() != scores.len() {
+        return Err(anyhow!("{} scores given for {} attributes", scores.len(), labels.len()));
+    }
+    Ok(())
+}
+
+impl TtrpgEntity {
+    pub fn generate_attributes(&mut self, generation: &Generation, labels: &[&str], log: &mut SessionLog) -> Result<Vec<u32>, Error> {
+        self.generate_attributes_with(generation, labels, log, &mut thread_rng())
+    }
+    // Replaces the attributes with the labels by a freshly generated set, logging every roll made
+    pub fn generate_attributes_with<R: Rng>(&mut self, generation: &Generation, labels: &[&str], log: &mut SessionLog, rng: &mut R) -> Result<Vec<u32>, Error> {
+        let scores = generation.scores_with(labels, rng)?;
+        let kind = match generation {
+            Generation::PointBuy(..) | Generation::StandardArray(_) | Generation::HeroicArray(_) => LogKind::Note,
+            _ => LogKind::Roll,
+        };
+        for (label, (score, description)) in labels.iter().zip(scores.iter()) {
+            let existing = self.elements.get(*label).and_then(|element| match element {
+                Elements::Attribute(a) => Some((a.id, a.order_num, a.description.clone())),
+                _ => None,
+            });
+            let id = self.elements.len() as u32 + 1;
+            let (id, order_num, attribute_description) = existing.unwrap_or((id, id, String::new()));
+            let attribute = Attribute::from_score(id, order_num, label.to_string(), attribute_description, *score, format!("{}: {}", generation.name(), description));
+            self.add_element(Elements::Attribute(attribute));
+            log.record(kind, &format!("{} {} {}: {} = {}", self.name, label, generation.name(), description, score));
+        }
+        self.recalculate()?;
+        Ok(scores.into_iter().map(|(score, _)| score).collect())
+    }
+}
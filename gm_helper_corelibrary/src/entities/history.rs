@@ -0,0 +1,87 @@
+use super::{TtrpgEntity, Elements};
+use anyhow::Error;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const HISTORY_LIMIT: usize = 50;
+
+// The elements a change replaced, None for elements it added
+#[derive(Serialize, Deserialize)]
+#[derive(Clone)]
+pub struct HistoryEntry {
+    pub timestamp: u64,
+    pub description: String,
+    pub previous: Vec<(String, Option<Elements>)>
+}
+
+impl HistoryEntry {
+    pub fn get_description(&self) -> String {
+        format!("{} ({} elements changed)", self.description, self.previous.len())
+    }
+}
+
+impl TtrpgEntity {
+    // Runs a change that can later be undone. A failed change is rolled back so it applies all or nothing.
+    pub fn record_history<T, F: FnOnce(&mut TtrpgEntity) -> Result<T, Error>>(&mut self, description: &str, change: F) -> Result<T, Error> {
+        let before = self.elements.clone();
+        let result = match change(self) {
+            Ok(result) => result,
+            Err(e) => {
+                self.elements = before;
+                return Err(e);
+            }
+        };
+        let previous = changed_elements(&before, &self.elements);
+        if !previous.is_empty() {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            self.history.push(HistoryEntry {timestamp, description: description.to_string(), previous});
+            if self.history.len() > HISTORY_LIMIT {
+                self.history.remove(0);
+            }
+        }
+        Ok(result)
+    }
+    // Puts back what the latest change replaced and returns that change
+    pub fn undo(&mut self) -> Option<HistoryEntry> {
+        let entry = self.history.pop()?;
+        for (key, element) in entry.previous.iter() {
+            match element {
+                Some(element) => {
+                    self.elements.insert(key.clone(), element.clone());
+                },
+                None => {
+                    self.elements.remove(key);
+                },
+            }
+        }
+        Some(entry)
+    }
+}
+
+// Elements are compared through their json, anything that cannot be serialized counts as changed
+fn changed_elements(before: &HashMap<String, Elements>, after: &HashMap<String, Elements>) -> Vec<(String, Option<Elements>)> {
+    let mut previous = Vec::new();
+    for (key, element) in before.iter() {
+        let unchanged = match after.get(key) {
+            Some(current) => {
+                let old = serde_json::to_string(element).ok();
+                old.is_some() && old == serde_json::to_string(current).ok()
+            },
+            None => false,
+        };
+        if !unchanged {
+            previous.push((key.clone(), Some(element.clone())));
+        }
+    }
+    for key in after.keys() {
+        if !before.contains_key(key) {
+            previous.push((key.clone(), None));
+        }
+    }
+    previous.sort_by(|a, b| a.0.cmp(&b.0));
+    previous
+}
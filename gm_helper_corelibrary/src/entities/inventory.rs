@@ -0,0 +1,372 @@
+use super::{TtrpgEntity, Elements};
+use anyhow::{anyhow, Error};
+use serde::{Serialize, Deserialize};
+use std::cell::Cell;
+
+pub const INVENTORY_LABEL: &str = "Inventory";
+pub const STR_LABEL: &str = "STR";
+pub const CARRYING_CAPACITY_LABEL: &str = "Carrying Capacity";
+const COINS_PER_POUND: u64 = 50;
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Denomination {
+    Copper,
+    Silver,
+    Electrum,
+    Gold,
+    Platinum
+}
+
+impl Denomination {
+    pub fn value_in_copper(&self) -> u64 {
+        match self {
+            Denomination::Copper => 1,
+            Denomination::Silver => 10,
+            Denomination::Electrum => 50,
+            Denomination::Gold => 100,
+            Denomination::Platinum => 1000,
+        }
+    }
+}
+
+const DENOMINATIONS: [Denomination; 5] = [
+    Denomination::Copper,
+    Denomination::Silver,
+    Denomination::Electrum,
+    Denomination::Gold,
+    Denomination::Platinum
+];
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, Default)]
+pub struct CoinPurse {
+    pub copper: u64,
+    pub silver: u64,
+    pub electrum: u64,
+    pub gold: u64,
+    pub platinum: u64
+}
+
+impl CoinPurse {
+    pub fn new() -> CoinPurse {
+        CoinPurse::default()
+    }
+    fn coins_mut(&mut self, denomination: Denomination) -> &mut u64 {
+        match denomination {
+            Denomination::Copper => &mut self.copper,
+            Denomination::Silver => &mut self.silver,
+            Denomination::Electrum => &mut self.electrum,
+            Denomination::Gold => &mut self.gold,
+            Denomination::Platinum => &mut self.platinum,
+        }
+    }
+    pub fn add(&mut self, denomination: Denomination, amount: u64) {
+        *self.coins_mut(denomination) += amount;
+    }
+    // Adds an amount of copper as the fewest gold, silver and copper coins
+    pub fn add_copper_value(&mut self, copper: u64) {
+        self.gold += copper / 100;
+        self.silver += (copper % 100) / 10;
+        self.copper += copper % 10;
+    }
+    pub fn total_in_copper(&self) -> u64 {
+        self.copper + self.silver * 10 + self.electrum * 50 + self.gold * 100 + self.platinum * 1000
+    }
+    pub fn coin_count(&self) -> u64 {
+        self.copper + self.silver + self.electrum + self.gold + self.platinum
+    }
+    pub fn weight(&self) -> f32 {
+        self.coin_count() as f32 / COINS_PER_POUND as f32
+    }
+    // Pays with the smallest coins first and takes change when a larger coin has to be broken
+    pub fn spend(&mut self, copper: u64) -> Result<(), Error> {
+        if copper > self.total_in_copper() {
+            return Err(anyhow!("Not enough coins: {}cp needed but only {}cp in the purse", copper, self.total_in_copper()));
+        }
+        let mut owed = copper;
+        for denomination in DENOMINATIONS.iter() {
+            let value = denomination.value_in_copper();
+            let coins = self.coins_mut(*denomination);
+            let paid = (*coins).min(owed / value);
+            *coins -= paid;
+            owed -= paid * value;
+        }
+        if owed > 0 {
+            for denomination in DENOMINATIONS.iter() {
+                let coins = self.coins_mut(*denomination);
+                if *coins > 0 {
+                    *coins -= 1;
+                    self.add_copper_value(denomination.value_in_copper() - owed);
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+    // Exchanges copper and silver up into gold where possible
+    pub fn consolidate(&mut self) {
+        let small = self.copper + self.silver * 10;
+        self.copper = 0;
+        self.silver = 0;
+        self.add_copper_value(small);
+    }
+    pub fn get_description(&self) -> String {
+        format!("{}pp {}gp {}ep {}sp {}cp", self.platinum, self.gold, self.electrum, self.silver, self.copper)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EquipSlot {
+    Head,
+    Neck,
+    Cloak,
+    Body,
+    Hands,
+    MainHand,
+    OffHand,
+    Feet,
+    Ring1,
+    Ring2
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct Item {
+    pub name: String,
+    pub quantity: u32,
+    pub weight: f32, // pounds per item
+    pub value: u64, // copper pieces per item
+    pub description: String,
+    pub tags: Vec<String>,
+    pub capacity: Option<f32>, // pounds a container item can hold
+    pub container: Option<String>, // name of the container item this is stored in
+    pub equipped: Option<EquipSlot>
+}
+
+impl Item {
+    pub fn new(name: &str, quantity: u32, weight: f32, value: u64, description: &str) -> Item {
+        Item {
+            name: name.to_string(),
+            quantity,
+            weight: weight.max(0.0),
+            value,
+            description: description.to_string(),
+            tags: Vec::new(),
+            capacity: None,
+            container: None,
+            equipped: None
+        }
+    }
+    pub fn container(name: &str, weight: f32, value: u64, capacity: f32, description: &str) -> Item {
+        let mut item = Item::new(name, 1, weight, value, description);
+        item.capacity = Some(capacity);
+        item
+    }
+    pub fn total_weight(&self) -> f32 {
+        self.weight * self.quantity as f32
+    }
+    pub fn get_description(&self) -> String {
+        let mut description = format!("{} x{} ({}lb, {}cp)", self.name, self.quantity, self.total_weight(), self.value * self.quantity as u64);
+        if let Some(slot) = self.equipped {
+            description += &format!(" equipped {:?}", slot);
+        }
+        if let Some(container) = &self.container {
+            description += &format!(" in {}", container);
+        }
+        description
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encumbrance {
+    Unencumbered,
+    Encumbered, // over 5 x strength, speed drops by 10
+    HeavilyEncumbered, // over 10 x strength, speed drops by 20 and disadvantage on physical rolls
+    OverCapacity // over 15 x strength
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct Inventory {
+    pub edit: Cell<bool>,
+    pub id: u32,
+    pub order_num: u32,
+    pub label: String,
+    pub items: Vec<Item>,
+    pub purse: CoinPurse
+}
+
+impl Inventory {
+    pub fn new(id: u32, order_num: u32) -> Inventory {
+        Inventory {
+            edit: Cell::new(false),
+            id,
+            order_num,
+            label: INVENTORY_LABEL.to_string(),
+            items: Vec::new(),
+            purse: CoinPurse::new()
+        }
+    }
+    fn position(&self, name: &str) -> Result<usize, Error> {
+        self.items.iter().position(|i| i.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| anyhow!("No {} in the inventory", name))
+    }
+    // Stacks with an existing loose, unequipped item of the same name
+    pub fn add_item(&mut self, item: Item) {
+        let existing = self.items.iter_mut().find(|i| {
+            i.name.eq_ignore_ascii_case(&item.name) && i.container == item.container && i.equipped.is_none() && i.capacity.is_none()
+        });
+        match existing {
+            Some(existing) if item.equipped.is_none() && item.capacity.is_none() => existing.quantity += item.quantity,
+            _ => self.items.push(item),
+        }
+    }
+    // Takes up to `quantity` of an item out, emptying anything stored inside a removed container
+    pub fn remove_item(&mut self, name: &str, quantity: u32) -> Result<Item, Error> {
+        let index = self.position(name)?;
+        if self.items[index].quantity < quantity {
+            return Err(anyhow!("Only {} {} in the inventory", self.items[index].quantity, name));
+        }
+        let mut removed = self.items[index].clone();
+        removed.quantity = quantity;
+        removed.equipped = None;
+        removed.container = None;
+        self.items[index].quantity -= quantity;
+        if self.items[index].quantity == 0 {
+            let emptied = self.items.remove(index).name;
+            for item in self.items.iter_mut() {
+                if item.container.as_ref() == Some(&emptied) {
+                    item.container = None;
+                }
+            }
+        }
+        Ok(removed)
+    }
+    pub fn contents_weight(&self, container: &str) -> f32 {
+        self.items.iter()
+            .filter(|i| i.container.as_deref() == Some(container))
+            .map(|i| i.total_weight())
+            .sum()
+    }
+    pub fn put_in(&mut self, name: &str, container: &str) -> Result<(), Error> {
+        let container_index = self.position(container)?;
+        let capacity = match self.items[container_index].capacity {
+            Some(capacity) => capacity,
+            None => return Err(anyhow!("{} is not a container", container)),
+        };
+        let index = self.position(name)?;
+        if index == container_index {
+            return Err(anyhow!("{} cannot be put inside itself", name));
+        }
+        let container_name = self.items[container_index].name.clone();
+        if self.contents_weight(&container_name) + self.items[index].total_weight() > capacity {
+            return Err(anyhow!("{} does not fit in {}", name, container));
+        }
+        self.items[index].equipped = None;
+        self.items[index].container = Some(container_name);
+        Ok(())
+    }
+    pub fn take_out(&mut self, name: &str) -> Result<(), Error> {
+        let index = self.position(name)?;
+        self.items[index].container = None;
+        Ok(())
+    }
+    // Equipping into an occupied slot unequips whatever was there, which is returned
+    pub fn equip(&mut self, name: &str, slot: EquipSlot) -> Result<Option<String>, Error> {
+        let index = self.position(name)?;
+        let mut previous = None;
+        for item in self.items.iter_mut() {
+            if item.equipped == Some(slot) {
+                item.equipped = None;
+                previous = Some(item.name.clone());
+            }
+        }
+        self.items[index].container = None;
+        self.items[index].equipped = Some(slot);
+        Ok(previous)
+    }
+    pub fn unequip(&mut self, slot: EquipSlot) {
+        for item in self.items.iter_mut() {
+            if item.equipped == Some(slot) {
+                item.equipped = None;
+            }
+        }
+    }
+    pub fn equipped(&self, slot: EquipSlot) -> Option<&Item> {
+        self.items.iter().find(|i| i.equipped == Some(slot))
+    }
+    pub fn total_weight(&self) -> f32 {
+        self.items.iter().map(|i| i.total_weight()).sum::<f32>() + self.purse.weight()
+    }
+    pub fn total_value(&self) -> u64 {
+        self.items.iter().map(|i| i.value * i.quantity as u64).sum::<u64>() + self.purse.total_in_copper()
+    }
+    pub fn get_description(&self) -> String {
+        format!("{} items, {}lb, {}", self.items.len(), self.total_weight(), self.purse.get_description())
+    }
+}
+
+impl TtrpgEntity {
+    pub fn inventory(&self) -> Option<&Inventory> {
+        match self.elements.get(INVENTORY_LABEL) {
+            Some(Elements::Inventory(inventory)) => Some(inventory),
+            _ => None,
+        }
+    }
+    // Adds an empty inventory the first time one is needed
+    pub fn inventory_mut(&mut self) -> &mut Inventory {
+        if self.inventory().is_none() {
+            let order_num = self.elements.len() as u32 + 1;
+            self.add_element(Elements::Inventory(Inventory::new(order_num, order_num)));
+        }
+        match self.elements.get_mut(INVENTORY_LABEL) {
+            Some(Elements::Inventory(inventory)) => inventory,
+            _ => unreachable!("inventory was just added"),
+        }
+    }
+    // A "Carrying Capacity" derived value when there is one, otherwise strength score times 15
+    pub fn carrying_capacity(&self) -> f32 {
+        match self.elements.get(CARRYING_CAPACITY_LABEL) {
+            Some(Elements::Derived(d)) => d.value as f32,
+            _ => self.strength_score() as f32 * 15.0,
+        }
+    }
+    // Thresholds are thirds of the carrying capacity: 5, 10 and 15 times strength by default
+    pub fn encumbrance(&self) -> Encumbrance {
+        let carried = self.inventory().map(|i| i.total_weight()).unwrap_or(0.0);
+        let capacity = self.carrying_capacity();
+        if carried > capacity {
+            Encumbrance::OverCapacity
+        } else if carried > capacity * 2.0 / 3.0 {
+            Encumbrance::HeavilyEncumbered
+        } else if carried > capacity / 3.0 {
+            Encumbrance::Encumbered
+        } else {
+            Encumbrance::Unencumbered
+        }
+    }
+    fn strength_score(&self) -> u32 {
+        self.find_attribute(STR_LABEL).map(|a| a.roll.base_result).unwrap_or(10)
+    }
+}
+
+// Moves items between entities, e.g. looting a defeated monster
+pub fn transfer_item(from: &mut TtrpgEntity, to: &mut TtrpgEntity, name: &str, quantity: u32) -> Result<(), Error> {
+    let item = match from.elements.get_mut(INVENTORY_LABEL) {
+        Some(Elements::Inventory(inventory)) => inventory.remove_item(name, quantity)?,
+        _ => return Err(anyhow!("{} has no inventory", from.name)),
+    };
+    to.inventory_mut().add_item(item);
+    Ok(())
+}
+
+pub fn transfer_coins(from: &mut TtrpgEntity, to: &mut TtrpgEntity, copper: u64) -> Result<(), Error> {
+    match from.elements.get_mut(INVENTORY_LABEL) {
+        Some(Elements::Inventory(inventory)) => inventory.purse.spend(copper)?,
+        _ => return Err(anyhow!("{} has no inventory", from.name)),
+    };
+    to.inventory_mut().purse.add_copper_value(copper);
+    Ok(())
+}
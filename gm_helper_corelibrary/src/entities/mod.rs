@@ -1,10 +1,33 @@
 #![allow(dead_code)]
+mod dice_expression;
+mod condition;
+mod resource;
+mod inventory;
+mod formula;
+mod derived;
+mod resolution;
+mod template;
+mod generation;
+mod history;
+
+pub use dice_expression::*;
+pub use condition::*;
+pub use resource::*;
+pub use inventory::*;
+pub use formula::*;
+pub use derived::*;
+pub use resolution::*;
+pub use template::*;
+pub use generation::*;
+pub use history::*;
+
 use std::cell::Cell;
-use anyhow::{Ok, Error};
+use anyhow::{Ok, Error, anyhow};
 use rand::{thread_rng, Rng};
 use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
 use std::path::PathBuf;
+use crate::libtext::{AudioSpan, Transcript};
 
 //Constants
 const NUMBER_LIMIT:i32 = 10_000;
@@ -22,7 +45,11 @@ pub enum Elements {
     Attribute(Attribute),
     Skill(Skill),
     Counter(Counter),
-    Table(Table)
+    Table(Table),
+    Condition(Condition),
+    Resource(Resource),
+    Inventory(Inventory),
+    Derived(Derived)
 }
 // Traits
 trait DiceRoll {
@@ -46,15 +73,31 @@ fn escape_sql(input: &str) -> String {
     input.replace("'", "''")
 }
 
+pub fn score_modifier(score: u32) -> i32 {
+    (score as i32 - 10).div_euclid(2)
+}
+
+pub fn proficiency_bonus(level: u32) -> i32 {
+    2 + (level.max(1) as i32 - 1) / 4
+}
+
 // structs
 #[derive(Serialize, Deserialize)]
 pub struct TtrpgEntity {
     pub active: Cell<bool>,
     pub edit: Cell<bool>,
+    #[serde(default)]
+    pub player_character: Cell<bool>,
+    #[serde(default)]
+    pub ruleset: String, // name of the ruleset pack the entity was created from
     pub id: String,
     pub name: String,
     pub database: PathBuf,
-    pub elements: HashMap<String, Elements>
+    pub elements: HashMap<String, Elements>,
+    #[serde(default)]
+    pub history: Vec<HistoryEntry>, // changes that can be undone, newest last
+    #[serde(default)]
+    pub transcripts: Vec<Transcript> // what was said at the table while the entity was in play
 }
 
 impl TtrpgEntity {
@@ -71,10 +114,14 @@ impl TtrpgEntity {
         TtrpgEntity {
             active: Cell::new(active),
             edit: Cell::new(edit),
+            player_character: Cell::new(false),
+            ruleset: String::new(),
             id: id_string,
             name,
             database: path,
-            elements: HashMap::new()
+            elements: HashMap::new(),
+            history: Vec::new(),
+            transcripts: Vec::new()
         }
     }
     pub fn add_element(&mut self, element: Elements) -> Option<Elements>{
@@ -96,6 +143,27 @@ impl TtrpgEntity {
             Elements::Table(t) => {
                 self.elements.insert(t.label.clone(), Elements::Table(t))
             },
+            Elements::Condition(cd) => {
+                // conditions with the same label combine according to their stacking rule
+                if cd.stacking == Stacking::Independent {
+                    self.elements.insert(format!("{}-{}", cd.label.clone(), length_of_elements), Elements::Condition(cd))
+                } else if let Some(Elements::Condition(existing)) = self.elements.get_mut(&cd.label) {
+                    let previous = existing.clone();
+                    existing.stack(cd);
+                    Some(Elements::Condition(previous))
+                } else {
+                    self.elements.insert(cd.label.clone(), Elements::Condition(cd))
+                }
+            },
+            Elements::Resource(r) => {
+                self.elements.insert(r.label.clone(), Elements::Resource(r))
+            },
+            Elements::Inventory(i) => {
+                self.elements.insert(i.label.clone(), Elements::Inventory(i))
+            },
+            Elements::Derived(d) => {
+                self.elements.insert(d.label.clone(), Elements::Derived(d))
+            },
         }
     }
     pub fn retrieve_all_element_keys(&self) -> Vec<String> {
@@ -108,6 +176,130 @@ impl TtrpgEntity {
     pub fn remove_element(&mut self, key: &str) {
         self.elements.remove(key);
     }
+    // Counters are keyed by their label but lookups by players are case insensitive
+    pub fn find_counter(&self, label: &str) -> Option<&Counter> {
+        for (_key, element) in self.elements.iter() {
+            if let Elements::Counter(c) = element {
+                if c.label.eq_ignore_ascii_case(label) {
+                    return Some(c);
+                }
+            }
+        }
+        None
+    }
+    pub fn find_attribute(&self, label: &str) -> Option<&Attribute> {
+        for (_key, element) in self.elements.iter() {
+            if let Elements::Attribute(a) = element {
+                if a.label.eq_ignore_ascii_case(label) {
+                    return Some(a);
+                }
+            }
+        }
+        None
+    }
+    pub fn conditions(&self) -> Vec<&Condition> {
+        let mut conditions = Vec::new();
+        for (_key, element) in self.elements.iter() {
+            if let Elements::Condition(cd) = element {
+                conditions.push(cd);
+            }
+        }
+        conditions
+    }
+    // Adds a condition, one applied during the entity's own turn survives the end of that turn
+    pub fn apply_condition(&mut self, mut condition: Condition, during_own_turn: bool) -> Option<Elements> {
+        condition.applied_during_own_turn = during_own_turn;
+        self.add_element(Elements::Condition(condition))
+    }
+    // Rolls every active condition modifier that targets the label, returns the total and what was applied
+    pub fn condition_modifier(&self, label: &str) -> (i32, Vec<String>) {
+        let mut total = 0;
+        let mut applied = Vec::new();
+        for condition in self.conditions() {
+            for modifier in condition.modifiers.iter() {
+                if modifier.target == "*" || modifier.target.eq_ignore_ascii_case(label) {
+                    let rolled = modifier.expression.roll().1 * condition.level as i32;
+                    total += rolled;
+                    applied.push(format!("{} {:+}", condition.label, rolled));
+                }
+            }
+        }
+        (total, applied)
+    }
+    // Rolls a d20 skill check with the modifiers of any active conditions added to it
+    pub fn roll_skill(&self, label: &str, advantage: Boon, critical: u32, difficulty: u32) -> Result<String, Error> {
+        let resolution = Resolution::d20(critical)?;
+        let (resolved, applied) = self.check_skill_with(label, &resolution, &advantage, difficulty as i32, &mut thread_rng())?;
+        let result = format!("{} - {}", label, resolved.get_description());
+        if applied.is_empty() {
+            return Ok(result);
+        }
+        Ok(format!("{} [{}]", result, applied.join(", ")))
+    }
+    // Checks a skill with any resolution mechanic, roll over checks add the skill's attribute modifier.
+    // Returns the result and the condition modifiers that applied
+    pub fn check_skill_with<R: Rng>(&self, label: &str, resolution: &Resolution, advantage: &Boon, difficulty: i32, rng: &mut R) -> Result<(Resolved, Vec<String>), Error> {
+        let mut skill = None;
+        for (_key, element) in self.elements.iter() {
+            if let Elements::Skill(sk) = element {
+                if sk.label.eq_ignore_ascii_case(label) {
+                    skill = Some(sk);
+                }
+            }
+        }
+        let skill = match skill {
+            Some(skill) => skill,
+            None => return Err(anyhow!("{} has no skill {}", self.name, label)),
+        };
+        let (mut modifier, applied) = self.condition_modifier(label);
+        if !resolution.rolls_under() {
+            if let Some(attribute) = skill.attribute.as_ref().and_then(|a| self.find_attribute(a)) {
+                modifier += attribute.modifier;
+            }
+        }
+        let resolved = skill.check_with(resolution, advantage, modifier, difficulty, rng)?;
+        Ok((resolved, applied))
+    }
+    // Removes and returns every condition for which `expired` returns true
+    fn expire_conditions<F: FnMut(&mut Condition) -> bool>(&mut self, mut expired: F) -> Vec<Condition> {
+        let mut keys = Vec::new();
+        for (key, element) in self.elements.iter_mut() {
+            if let Elements::Condition(cd) = element {
+                if expired(cd) {
+                    keys.push(key.clone());
+                }
+            }
+        }
+        let mut removed = Vec::new();
+        for key in keys {
+            if let Some(Elements::Condition(cd)) = self.elements.remove(&key) {
+                removed.push(cd);
+            }
+        }
+        removed
+    }
+    pub fn tick_conditions(&mut self, rounds: u32) -> Vec<Condition> {
+        self.expire_conditions(|cd| cd.tick_rounds(rounds))
+    }
+    pub fn advance_time(&mut self, minutes: u32) -> Vec<Condition> {
+        self.expire_conditions(|cd| cd.advance_minutes(minutes))
+    }
+    pub fn end_turn(&mut self) -> Vec<Condition> {
+        self.expire_conditions(|cd| cd.end_turn())
+    }
+    pub fn resolve_condition_save(&mut self, ability: &str, total: u32) -> Vec<Condition> {
+        self.expire_conditions(|cd| cd.resolve_save(ability, total))
+    }
+    pub fn find_counter_mut(&mut self, label: &str) -> Option<&mut Counter> {
+        for (_key, element) in self.elements.iter_mut() {
+            if let Elements::Counter(c) = element {
+                if c.label.eq_ignore_ascii_case(label) {
+                    return Some(c);
+                }
+            }
+        }
+        None
+    }
 }
 
 impl SaveLoad for TtrpgEntity {
@@ -139,6 +331,8 @@ pub struct Story {
     pub order_num: u32,
     pub label: String,
     pub raw_narration: String,
+    #[serde(default)]
+    pub audio: Option<AudioSpan>, // the recording the story was transcribed from
 }
 
 impl Story {
@@ -149,6 +343,7 @@ impl Story {
             order_num,
             label: label.to_string(), 
             raw_narration: escape_sql(raw_narration), 
+            audio: None,
         };
         Ok(story)
     }
@@ -175,7 +370,7 @@ pub struct Attribute {
     pub order_num: u32,
     pub label: String,
     pub description: String,
-    pub modifier: u32, // (Ability score - 10) / 2
+    pub modifier: i32, // (Ability score - 10) / 2 rounded down
     pub roll: Outcome
 }
 
@@ -188,11 +383,31 @@ impl Attribute {
             label,
             description,
             edit: Cell::new(false),
-            modifier: (roll.base_result - 10) / 2 as u32,
+            modifier: score_modifier(roll.base_result),
             roll
         };
         Ok(attribute)
     }
+    // An attribute whose score was decided elsewhere, such as point buy or a set of rolls
+    pub fn from_score(id: u32, order_num: u32, label: String, description: String, score: u32, roll_description: String) -> Attribute {
+        let roll = Outcome {
+            roll_description,
+            base_result: score,
+            max: score,
+            min: score,
+            attribute: true,
+            critical: 0
+        };
+        Attribute {
+            id,
+            order_num,
+            label,
+            description,
+            edit: Cell::new(false),
+            modifier: score_modifier(score),
+            roll
+        }
+    }
     pub fn get_description(self) -> String {
         let description = format!(
             "{} {}({})",
@@ -202,9 +417,10 @@ impl Attribute {
         );
         description
     }
+    // TtrpgEntity::edit_attribute also recalculates the skills and derived values that read the attribute
     pub fn edit(&mut self, new_text: String, change_base_by: u32) { // change the value of the base roll (can increase / decrease atttributes this way)
         self.roll.base_result += change_base_by;
-        self.modifier = (self.roll.base_result - 10) / 2 as u32;
+        self.modifier = score_modifier(self.roll.base_result);
         self.description = format!("{} {} ({})", new_text, self.roll.base_result, self.modifier);
     }
 }
@@ -219,7 +435,11 @@ pub struct Skill {
     pub level: u32,
     pub skill_level: u32,
     pub has_proficiency: bool,
-    pub proficiency: i32 // 2 + (1/4 * level - 1)
+    pub proficiency: i32, // 2 + (level - 1) / 4
+    #[serde(default)]
+    pub attribute: Option<String>, // the attribute whose modifier roll over checks add
+    #[serde(default)]
+    pub formula: Option<String> // computes skill_level from other elements, e.g. "@DEX * 2" for a save
 }
 
 impl Skill {
@@ -232,10 +452,18 @@ impl Skill {
             level, 
             skill_level,
             has_proficiency,
-            proficiency: 2 + (1/4 * level - 1) as i32
+            proficiency: proficiency_bonus(level),
+            attribute: None,
+            formula: None
         };
         Ok(skill)
     }
+    // Skills with a formula are recalculated with derived values whenever what they read changes
+    pub fn set_formula(&mut self, formula: &str) -> Result<(), Error> {
+        Formula::parse(formula)?;
+        self.formula = Some(formula.to_string());
+        Ok(())
+    }
 
     pub fn get_description(self) -> Result<String, Error> {
         if self.has_proficiency {
@@ -248,47 +476,20 @@ impl Skill {
     }
 
     pub fn roll_skill(self, advantage: Boon, critical: u32, difficulty: u32) -> String {
-            match advantage {
-                Boon::Advantage => {
-                    let mut roll = Outcome::new(&Roll::new(20, 2), critical, false);
-                    roll.base_result = if self.proficiency < 0 {roll.max - self.proficiency as u32} else {roll.max + self.proficiency as u32};
-                    let success = roll.success_of_roll(None, difficulty);
-                    format!(
-                        "{}({}) - rolled with advantage {}, {} vs {}",
-                        self.label,
-                        self.proficiency,
-                        roll.base_result,
-                        if success.0 {"success"} else {"failure"},
-                        success.1
-                    )
-                },
-                Boon::Disadvantage => {
-                    let mut roll = Outcome::new(&Roll::new(20, 2), critical, false);
-                    roll.base_result = if self.proficiency < 0 {roll.min - self.proficiency as u32} else {roll.min + self.proficiency as u32};
-                    let success = roll.success_of_roll(None, difficulty);
-                    format!(
-                        "{}({}) - rolled with disadvantage {}, {} vs {}",
-                        self.label,
-                        self.proficiency,
-                        roll.base_result,
-                        if success.0 {"success"} else {"failure"},
-                        success.1
-                    )
-                },
-                Boon::Plain => {
-                    let mut roll = Outcome::new(&Roll::new(20, 1), critical, false);
-                    roll.base_result += if self.proficiency < 0 {roll.base_result - self.proficiency as u32} else {roll.base_result + self.proficiency as u32};
-                    let success = roll.success_of_roll(None, difficulty);
-                    format!(
-                        "{}({}) - rolled {}, {} vs {}",
-                        self.label,
-                        self.proficiency,
-                        roll.base_result,
-                        if success.0 {"success"} else {"failure"},
-                        success.1
-                    )
-                }
-            }
+        Resolution::d20(critical)
+            .and_then(|resolution| self.check_with(&resolution, &advantage, 0, difficulty as i32, &mut thread_rng()))
+            .map(|resolved| format!("{}({}) - {}", self.label, self.proficiency, resolved.get_description()))
+            .unwrap_or_else(|e| format!("{} could not be rolled: {}", self.label, e))
+    }
+    // Roll under mechanics check against the skill value, which the modifier raises or lowers;
+    // the others add the modifier, and the proficiency when proficient, to the dice and check against the difficulty
+    pub fn check_with<R: Rng>(&self, resolution: &Resolution, advantage: &Boon, modifier: i32, difficulty: i32, rng: &mut R) -> Result<Resolved, Error> {
+        if resolution.rolls_under() {
+            resolution.resolve_with(advantage, modifier, self.skill_level as i32, rng)
+        } else {
+            let proficiency = if self.has_proficiency {self.proficiency} else {0};
+            resolution.resolve_with(advantage, proficiency + modifier, difficulty, rng)
+        }
     }
 }
 
@@ -299,7 +500,9 @@ pub struct Counter {
     pub id: u32,
     pub order_num: u32,
     pub label: String,
-    pub number: i32
+    pub number: i32,
+    #[serde(default)]
+    pub limit: Option<i32> // a wider range than NUMBER_LIMIT for running totals like xp
 }
 
 impl Counter {
@@ -310,19 +513,38 @@ impl Counter {
             order_num,
             edit: Cell::new(false),
             label,
-            number
+            number,
+            limit: None
         }
     }
+    pub fn with_limit(id: u32, order_num: u32, label: String, number: i32, limit: i32) -> Counter {
+        let number = if number > limit || number < (limit * -1) {0} else {number};
+        Counter {
+            id,
+            order_num,
+            edit: Cell::new(false),
+            label,
+            number,
+            limit: Some(limit)
+        }
+    }
+    fn range(&self) -> i32 {
+        self.limit.unwrap_or(NUMBER_LIMIT)
+    }
     pub fn get_description(self) -> String {
         format!("{}: {}", self.label, self.number)
     }
     pub fn increment(&mut self, number: i32) {
         self.number += number;
-        if self.number > NUMBER_LIMIT || self.number < NUMBER_LIMIT * -1 {self.number = 0} else {self.number = self.number};
+        if self.number > self.range() || self.number < self.range() * -1 {self.number = 0} else {self.number = self.number};
     }
     pub fn decrement(&mut self, number: i32) {
         self.number -= number;
-        if self.number > NUMBER_LIMIT || self.number < NUMBER_LIMIT * -1 {self.number = 0} else {self.number = self.number};
+        if self.number > self.range() || self.number < self.range() * -1 {self.number = 0} else {self.number = self.number};
+    }
+    // Replaces the number, keeping the counter's limit
+    pub fn set(&mut self, number: i32) {
+        self.number = if number > self.range() || number < self.range() * -1 {0} else {number};
     }
 }
 
@@ -390,16 +612,20 @@ impl Roll {
             amount
         }
     }
+    // Rolls with the given generator so that seeded rngs give reproducible results
+    pub fn roll_with<R: Rng>(&self, rng: &mut R) -> Vec<u32> {
+        let mut rolls: Vec<u32> = vec![];
+        for _ in 0..self.amount {
+            rolls.push(rng.gen_range(1..= self.dice));
+        }
+        rolls
+    }
 }
 
 impl DiceRoll for Roll {
     fn roll(&self) -> (Vec<u32>, String) {
         let mut rng = thread_rng();
-        let mut rolls: Vec<u32> = vec![];
-        for _ in 0..self.amount {
-            rolls.push(rng.gen_range(1..= self.dice));
-        }
-        (rolls, self.dice_label.clone())
+        (self.roll_with(&mut rng), self.dice_label.clone())
     }
 }
 
@@ -447,20 +673,19 @@ impl Outcome {
         }
     }
 
-    pub fn success_of_roll(&self, opposition: Option<&Outcome>, difficulty: u32) -> (bool, u32) {
+    // Whether the roll beats the difficulty, or the result of an attribute it is opposed by.
+    // The resolution decides if high or low rolls win, the critical only matters for crits.
+    pub fn success_of_roll(&self, opposition: Option<&Outcome>, difficulty: u32, resolution: &Resolution) -> (bool, u32) {
         let difficulty = match opposition {
-            Some(opposition) if opposition.attribute => opposition.base_result.clone(),
+            Some(opposition) if opposition.attribute => opposition.base_result,
             _ => difficulty,
         };
         let winner = match opposition {
-            Some(opposition) if self.critical == 20 => {
-                self.base_result >= difficulty && self.base_result >= opposition.base_result
-            }
-            Some(opposition) => self.base_result <= difficulty && self.base_result <= opposition.base_result,
-            None if self.critical == 20 => self.base_result >= difficulty,
-            None => self.base_result <= difficulty,
+            Some(opposition) if resolution.rolls_under() => self.base_result <= difficulty && self.base_result <= opposition.base_result,
+            Some(opposition) => self.base_result >= difficulty && self.base_result >= opposition.base_result,
+            None if resolution.rolls_under() => self.base_result <= difficulty,
+            None => self.base_result >= difficulty,
         };
-    
         (winner, difficulty)
-    }    
+    }
 }
\ No newline at end of file
@@ -0,0 +1,211 @@
+use super::{Boon, DiceExpression};
+use anyhow::{anyhow, Error};
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+
+// How a check is decided
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Resolution {
+    RollOver {dice: String, critical: u32}, // d20 + modifier against a difficulty class, natural `critical` or higher always succeeds
+    RollUnder {dice: String}, // d100 at or under the skill value, hard at half and extreme at a fifth
+    Bands {dice: String, full: i32, partial: i32} // 2d6 + stat, e.g. 10+ full success and 7-9 partial
+}
+
+// Ordered from worst to best so results can be compared
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Degree {
+    CriticalFailure,
+    Failure,
+    PartialSuccess,
+    Success,
+    HardSuccess,
+    ExtremeSuccess,
+    CriticalSuccess
+}
+
+impl Degree {
+    pub fn is_success(&self) -> bool {
+        *self >= Degree::PartialSuccess
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Attacker,
+    Defender
+}
+
+#[derive(Clone, Debug)]
+pub struct Resolved {
+    pub dice: Vec<u32>,
+    pub natural: i32, // sum of the dice before modifiers
+    pub total: i32,
+    pub target: i32, // difficulty to reach for roll over, skill value to get under for roll under
+    pub degree: Degree
+}
+
+impl Resolved {
+    pub fn get_description(&self) -> String {
+        if self.total == self.natural {
+            format!("rolled {} vs {}: {:?}", self.total, self.target, self.degree)
+        } else {
+            format!("rolled {} ({:+}) = {} vs {}: {:?}", self.natural, self.total - self.natural, self.total, self.target, self.degree)
+        }
+    }
+}
+
+pub struct Opposed {
+    pub attacker: Resolved,
+    pub defender: Resolved,
+    pub winner: Option<Side> // None on a tie or when neither side succeeds at a roll under
+}
+
+impl Opposed {
+    pub fn get_description(&self) -> String {
+        let winner = match self.winner {
+            Some(Side::Attacker) => "attacker wins",
+            Some(Side::Defender) => "defender wins",
+            None => "no winner",
+        };
+        format!("attacker {}, defender {}, {}", self.attacker.get_description(), self.defender.get_description(), winner)
+    }
+}
+
+impl Resolution {
+    // A d20 check that is a critical success on a natural `critical` or higher
+    pub fn d20(critical: u32) -> Result<Resolution, Error> {
+        if !(2..=20).contains(&critical) {
+            return Err(anyhow!("A critical success on a d20 needs a natural 2 to 20, not {}", critical));
+        }
+        Ok(Resolution::RollOver {dice: "1d20".to_string(), critical})
+    }
+    pub fn dice(&self) -> &str {
+        match self {
+            Resolution::RollOver {dice, ..} => dice,
+            Resolution::RollUnder {dice} => dice,
+            Resolution::Bands {dice, ..} => dice,
+        }
+    }
+    pub fn rolls_under(&self) -> bool {
+        matches!(self, Resolution::RollUnder {..})
+    }
+    // Grades a roll that has already been made. For roll under the modifier moves the target
+    // (a penalty of -20 makes a 60 skill need 40 or less); otherwise it is added to the dice.
+    pub fn degree(&self, natural: i32, modifier: i32, target: i32) -> (i32, i32, Degree) {
+        match self {
+            Resolution::RollOver {critical, ..} => {
+                let total = natural + modifier;
+                let degree = if natural >= *critical as i32 {
+                    Degree::CriticalSuccess
+                } else if natural <= 1 {
+                    Degree::CriticalFailure
+                } else if total >= target {
+                    Degree::Success
+                } else {
+                    Degree::Failure
+                };
+                (total, target, degree)
+            },
+            Resolution::RollUnder {..} => {
+                let target = target + modifier;
+                let fumble = if target < 50 {96} else {100};
+                let degree = if natural == 1 {
+                    Degree::CriticalSuccess
+                } else if natural >= fumble {
+                    Degree::CriticalFailure
+                } else if natural <= target / 5 {
+                    Degree::ExtremeSuccess
+                } else if natural <= target / 2 {
+                    Degree::HardSuccess
+                } else if natural <= target {
+                    Degree::Success
+                } else {
+                    Degree::Failure
+                };
+                (natural, target, degree)
+            },
+            Resolution::Bands {full, partial, ..} => {
+                let total = natural + modifier;
+                let degree = if total >= *full {
+                    Degree::Success
+                } else if total >= *partial {
+                    Degree::PartialSuccess
+                } else {
+                    Degree::Failure
+                };
+                (total, *full, degree)
+            },
+        }
+    }
+    // Advantage keeps the better of two rolls and disadvantage the worse. A roll under d100 instead
+    // follows the Call of Cthulhu bonus and penalty die and rerolls only the tens die
+    pub fn resolve_with<R: Rng>(&self, advantage: &Boon, modifier: i32, target: i32, rng: &mut R) -> Result<Resolved, Error> {
+        let expression = DiceExpression::parse(self.dice())?;
+        let percentile = expression.modifier == 0 && expression.rolls.len() == 1
+            && expression.rolls[0].0 > 0 && expression.rolls[0].1.dice == 100 && expression.rolls[0].1.amount == 1;
+        if self.rolls_under() && percentile && !matches!(advantage, Boon::Plain) {
+            return Ok(self.tens_die_with(advantage, modifier, target, rng));
+        }
+        let mut roll_once = || {
+            let (dice, natural) = expression.roll_with(rng);
+            let (total, target, degree) = self.degree(natural, modifier, target);
+            Resolved {dice, natural, total, target, degree}
+        };
+        let first = roll_once();
+        let resolved = match advantage {
+            Boon::Plain => first,
+            Boon::Advantage => {
+                let second = roll_once();
+                if self.better(&second, &first) {second} else {first}
+            },
+            Boon::Disadvantage => {
+                let second = roll_once();
+                if self.better(&first, &second) {second} else {first}
+            },
+        };
+        Ok(resolved)
+    }
+    // Both sides roll; a roll over or bands contest goes to the higher total, a roll under one to
+    // the better degree of success and then the higher skill
+    pub fn opposed_with<R: Rng>(&self, attacker_modifier: i32, attacker_target: i32, defender_modifier: i32, defender_target: i32, rng: &mut R) -> Result<Opposed, Error> {
+        let attacker = self.resolve_with(&Boon::Plain, attacker_modifier, attacker_target, rng)?;
+        let defender = self.resolve_with(&Boon::Plain, defender_modifier, defender_target, rng)?;
+        let winner = self.winner(&attacker, &defender);
+        Ok(Opposed {attacker, defender, winner})
+    }
+    pub fn winner(&self, attacker: &Resolved, defender: &Resolved) -> Option<Side> {
+        if self.rolls_under() && !attacker.degree.is_success() && !defender.degree.is_success() {
+            return None;
+        }
+        if self.better(attacker, defender) {
+            Some(Side::Attacker)
+        } else if self.better(defender, attacker) {
+            Some(Side::Defender)
+        } else {
+            None
+        }
+    }
+
+    // One ones die and two tens dice, a 00 with a 0 reads as 100. The bonus die keeps the lower
+    // tens die and the penalty die the higher one
+    fn tens_die_with<R: Rng>(&self, advantage: &Boon, modifier: i32, target: i32, rng: &mut R) -> Resolved {
+        let ones: u32 = rng.gen_range(0..10);
+        let read = |tens: u32| if tens == 0 && ones == 0 {100} else {tens * 10 + ones};
+        let dice = vec![read(rng.gen_range(0..10)), read(rng.gen_range(0..10))];
+        let natural = match advantage {
+            Boon::Disadvantage => dice[0].max(dice[1]),
+            _ => dice[0].min(dice[1]),
+        } as i32;
+        let (total, target, degree) = self.degree(natural, modifier, target);
+        Resolved {dice, natural, total, target, degree}
+    }
+    fn better(&self, a: &Resolved, b: &Resolved) -> bool {
+        if self.rolls_under() {
+            (a.degree, a.target) > (b.degree, b.target)
+        } else {
+            a.total > b.total
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use super::Roll;
+use anyhow::{anyhow, Error};
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use std::cell::Cell;
+
+// Spell slots per spell level (1st to 9th) for a full caster of each character level
+const SPELL_SLOTS: [[i32; 9]; 20] = [
+    [2, 0, 0, 0, 0, 0, 0, 0, 0],
+    [3, 0, 0, 0, 0, 0, 0, 0, 0],
+    [4, 2, 0, 0, 0, 0, 0, 0, 0],
+    [4, 3, 0, 0, 0, 0, 0, 0, 0],
+    [4, 3, 2, 0, 0, 0, 0, 0, 0],
+    [4, 3, 3, 0, 0, 0, 0, 0, 0],
+    [4, 3, 3, 1, 0, 0, 0, 0, 0],
+    [4, 3, 3, 2, 0, 0, 0, 0, 0],
+    [4, 3, 3, 3, 1, 0, 0, 0, 0],
+    [4, 3, 3, 3, 2, 0, 0, 0, 0],
+    [4, 3, 3, 3, 2, 1, 0, 0, 0],
+    [4, 3, 3, 3, 2, 1, 0, 0, 0],
+    [4, 3, 3, 3, 2, 1, 1, 0, 0],
+    [4, 3, 3, 3, 2, 1, 1, 0, 0],
+    [4, 3, 3, 3, 2, 1, 1, 1, 0],
+    [4, 3, 3, 3, 2, 1, 1, 1, 0],
+    [4, 3, 3, 3, 2, 1, 1, 1, 1],
+    [4, 3, 3, 3, 3, 1, 1, 1, 1],
+    [4, 3, 3, 3, 3, 2, 1, 1, 1],
+    [4, 3, 3, 3, 3, 2, 2, 1, 1],
+];
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Recharge {
+    ShortRest,
+    LongRest,
+    Dawn,
+    Roll {dice: u32, minimum: u32}, // e.g. recharge 5-6 is a d6 with a minimum of 5
+    Never
+}
+
+impl Recharge {
+    // A recharge roll needs a die to roll and a minimum it can reach
+    pub fn check(&self) -> Result<(), Error> {
+        if let Recharge::Roll {dice, minimum} = self {
+            if *dice == 0 {
+                return Err(anyhow!("A recharge roll needs a die with at least one side"));
+            }
+            if *minimum > *dice {
+                return Err(anyhow!("A recharge of {} can never be rolled on a d{}", minimum, dice));
+            }
+        }
+        Ok(())
+    }
+}
+
+// A limited use pool such as spell slots, ki points or a dragon's breath weapon
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct Resource {
+    pub edit: Cell<bool>,
+    pub id: u32,
+    pub order_num: u32,
+    pub label: String,
+    pub current: i32,
+    pub max: i32,
+    pub recharge: Recharge,
+    #[serde(default)]
+    pub die: Option<u32> // size of the die for pools of dice such as hit dice
+}
+
+impl Resource {
+    pub fn new(id: u32, order_num: u32, label: String, max: i32, recharge: Recharge) -> Result<Resource, Error> {
+        if max < 0 {
+            return Err(anyhow!("{} cannot have a negative maximum", label));
+        }
+        recharge.check().map_err(|e| anyhow!("{}: {}", label, e))?;
+        let resource = Resource {
+            edit: Cell::new(false),
+            id,
+            order_num,
+            label,
+            current: max,
+            max,
+            recharge,
+            die: None
+        };
+        Ok(resource)
+    }
+    // One resource per spell level the caster has slots for, labelled "Spell Slots 1" to "Spell Slots 9"
+    pub fn spell_slots(id: u32, order_num: u32, caster_level: u32) -> Vec<Resource> {
+        let mut slots = Vec::new();
+        let row = SPELL_SLOTS[(caster_level.clamp(1, 20) - 1) as usize];
+        for (index, max) in row.iter().enumerate() {
+            if *max > 0 {
+                let resource = Resource::new(id + index as u32, order_num + index as u32, format!("Spell Slots {}", index + 1), *max, Recharge::LongRest)
+                    .expect("spell slot maximums are positive");
+                slots.push(resource);
+            }
+        }
+        slots
+    }
+    // A character has one hit die per level, half of them come back after a long rest
+    pub fn hit_dice(id: u32, order_num: u32, level: i32, die: u32) -> Resource {
+        let mut resource = Resource::new(id, order_num, "Hit Dice".to_string(), level.max(1), Recharge::LongRest)
+            .expect("hit dice maximum is positive");
+        resource.die = Some(die);
+        resource
+    }
+    pub fn get_description(&self) -> String {
+        match self.die {
+            Some(die) => format!("{}: {}/{} d{}", self.label, self.current, self.max, die),
+            None => format!("{}: {}/{}", self.label, self.current, self.max),
+        }
+    }
+    pub fn spend(&mut self, amount: i32) -> Result<(), Error> {
+        if amount > self.current {
+            return Err(anyhow!("{} has only {} of {} left", self.label, self.current, self.max));
+        }
+        self.current -= amount.max(0);
+        Ok(())
+    }
+    // Returns how much was actually restored
+    pub fn restore(&mut self, amount: i32) -> i32 {
+        let restored = amount.max(0).min(self.max - self.current);
+        self.current += restored;
+        restored
+    }
+    pub fn restore_full(&mut self) -> i32 {
+        self.restore(self.max)
+    }
+    // Rolls for "recharge x-y" resources, restoring them on a high enough roll
+    pub fn roll_recharge<R: Rng>(&mut self, rng: &mut R) -> Option<u32> {
+        match self.recharge {
+            Recharge::Roll {dice, minimum} if dice > 0 && self.current < self.max => {
+                let rolled = Roll::new(dice, 1).roll_with(rng)[0];
+                if rolled >= minimum {
+                    self.restore_full();
+                }
+                Some(rolled)
+            },
+            _ => None,
+        }
+    }
+}
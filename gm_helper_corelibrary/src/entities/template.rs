@@ -0,0 +1,190 @@
+use super::{TtrpgEntity, Elements, DiceExpression};
+use anyhow::{anyhow, Error};
+use rand::{thread_rng, Rng};
+use serde::{Serialize, Deserialize};
+use sqlite::{Connection, OpenFlags, State};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// A saved entity that can be stamped out any number of times, e.g. ten goblins
+#[derive(Serialize, Deserialize)]
+#[derive(Clone)]
+pub struct Template {
+    pub name: String,
+    pub player_character: bool,
+    pub ruleset: String,
+    pub elements: HashMap<String, Elements>,
+    pub randomized: Vec<(String, String)>, // element label and the dice expression rolled for it in every copy
+    #[serde(skip)]
+    pub database: PathBuf // the campaign database the template was loaded from
+}
+
+impl Template {
+    pub fn from_entity(name: &str, entity: &TtrpgEntity) -> Result<Template, Error> {
+        if name.trim().is_empty() {
+            return Err(anyhow!("A template needs a name"));
+        }
+        let template = Template {
+            name: name.trim().to_string(),
+            player_character: entity.player_character.get(),
+            ruleset: entity.ruleset.clone(),
+            elements: entity.elements.clone(),
+            randomized: Vec::new(),
+            database: entity.database.clone()
+        };
+        Ok(template)
+    }
+    pub fn get_description(&self) -> String {
+        let mut description = format!("{}: {} elements", self.name, self.elements.len());
+        if !self.randomized.is_empty() {
+            let rolls: Vec<String> = self.randomized.iter().map(|(label, dice)| format!("{} {}", label, dice)).collect();
+            description += &format!(", rolls {}", rolls.join(", "));
+        }
+        description
+    }
+    // Rolls the attribute, counter or resource with the label again for every copy
+    pub fn randomize(&mut self, label: &str, expression: &str) -> Result<(), Error> {
+        let expression = DiceExpression::parse(expression)?;
+        let found = self.elements.values().any(|element| match element {
+            Elements::Attribute(a) => a.label.eq_ignore_ascii_case(label),
+            Elements::Counter(c) => c.label.eq_ignore_ascii_case(label),
+            Elements::Resource(r) => r.label.eq_ignore_ascii_case(label),
+            _ => false,
+        });
+        if !found {
+            return Err(anyhow!("{} has no attribute, counter or resource {}", self.name, label));
+        }
+        self.randomized.retain(|(l, _)| !l.eq_ignore_ascii_case(label));
+        self.randomized.push((label.to_string(), expression.expression));
+        Ok(())
+    }
+    pub fn instantiate(&self, count: usize, existing_names: &[String], overrides: &[HashMap<String, i32>]) -> Result<Vec<TtrpgEntity>, Error> {
+        self.instantiate_with(count, existing_names, overrides, &mut thread_rng())
+    }
+    // Copies are named "Goblin 1", "Goblin 2"... continuing after any numbered names already in use.
+    // Overrides are per copy, the first map applies to the first copy and so on. A "Max HP" counter
+    // follows a rolled or overridden "HP" unless it is set itself.
+    pub fn instantiate_with<R: Rng>(&self, count: usize, existing_names: &[String], overrides: &[HashMap<String, i32>], rng: &mut R) -> Result<Vec<TtrpgEntity>, Error> {
+        let mut highest = 0;
+        for name in existing_names.iter() {
+            if let Some(number) = name.strip_prefix(&format!("{} ", self.name)).and_then(|n| n.parse::<usize>().ok()) {
+                highest = highest.max(number);
+            }
+        }
+        let mut copies = Vec::new();
+        for index in 0..count {
+            let mut entity = TtrpgEntity::new(true, false, None, format!("{} {}", self.name, highest + index + 1), None);
+            entity.database = self.database.clone();
+            entity.player_character.set(self.player_character);
+            entity.ruleset = self.ruleset.clone();
+            entity.elements = self.elements.clone();
+            let mut values: Vec<(String, i32)> = Vec::new();
+            for (label, expression) in self.randomized.iter() {
+                values.push((label.clone(), DiceExpression::parse(expression)?.roll_with(rng).1));
+            }
+            if let Some(instance_overrides) = overrides.get(index) {
+                let mut labels: Vec<&String> = instance_overrides.keys().collect();
+                labels.sort();
+                for label in labels {
+                    values.push((label.clone(), instance_overrides[label]));
+                }
+            }
+            for (label, value) in values.iter() {
+                entity.set_value(label, *value)?;
+                let maximum = format!("Max {}", label);
+                if !values.iter().any(|(l, _)| l.eq_ignore_ascii_case(&maximum)) && entity.find_counter(&maximum).is_some() {
+                    entity.set_value(&maximum, *value)?;
+                }
+            }
+            entity.recalculate()?;
+            copies.push(entity);
+        }
+        Ok(copies)
+    }
+
+    // Stores the template in the campaign database, replacing one with the same name
+    pub fn save(&self, database: &Path) -> Result<(), Error> {
+        let connection = open_templates(database)?;
+        let mut statement = connection.prepare("INSERT OR REPLACE INTO templates (name, json_string) VALUES (?, ?)")?;
+        statement.bind((1, self.name.as_str()))?;
+        statement.bind((2, serde_json::to_string(self)?.as_str()))?;
+        statement.next()?;
+        Ok(())
+    }
+}
+
+impl TtrpgEntity {
+    // Sets an attribute score, a counter or both the current and maximum of a resource, then
+    // updates the derived values that read it
+    pub fn set_value(&mut self, label: &str, value: i32) -> Result<(), Error> {
+        let mut key = None;
+        for (k, element) in self.elements.iter() {
+            let matches = match element {
+                Elements::Attribute(a) => a.label.eq_ignore_ascii_case(label),
+                Elements::Counter(c) => c.label.eq_ignore_ascii_case(label),
+                Elements::Resource(r) => r.label.eq_ignore_ascii_case(label),
+                _ => false,
+            };
+            if matches {
+                key = Some(k.clone());
+            }
+        }
+        let key = key.ok_or_else(|| anyhow!("{} has no attribute, counter or resource {}", self.name, label))?;
+        if let Some(Elements::Attribute(_)) = self.elements.get(&key) {
+            self.set_attribute_score(label, value.max(0) as u32)?;
+            return Ok(());
+        }
+        match self.elements.get_mut(&key) {
+            Some(Elements::Counter(c)) => {
+                c.set(value);
+            },
+            Some(Elements::Resource(r)) => {
+                r.max = value.max(0);
+                r.current = r.max;
+            },
+            _ => {},
+        }
+        self.recalculate_dependents(label)?;
+        Ok(())
+    }
+}
+
+// Opens the campaign database, adding the templates table to databases created before it existed
+fn open_templates(database: &Path) -> Result<Connection, Error> {
+    let connection = Connection::open(database)?;
+    connection.execute("
+        CREATE TABLE IF NOT EXISTS templates (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            date DATETIME DEFAULT CURRENT_TIMESTAMP,
+            json_string TEXT NOT NULL
+        );
+    ")?;
+    Ok(connection)
+}
+
+// Only reads the database, one without a templates table has no templates
+pub fn load_templates(database: &Path) -> Result<Vec<Template>, Error> {
+    let connection = Connection::open_with_flags(database, OpenFlags::new().set_read_only())?;
+    let mut table = connection.prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'templates'")?;
+    if table.next()? != State::Row {
+        return Ok(Vec::new());
+    }
+    let mut statement = connection.prepare("SELECT json_string FROM templates ORDER BY name")?;
+    let mut templates = Vec::new();
+    while let State::Row = statement.next()? {
+        let json_string = statement.read::<String, _>("json_string")?;
+        let mut template: Template = serde_json::from_str(&json_string)?;
+        template.database = database.to_path_buf();
+        templates.push(template);
+    }
+    Ok(templates)
+}
+
+pub fn delete_template(database: &Path, name: &str) -> Result<(), Error> {
+    let connection = open_templates(database)?;
+    let mut statement = connection.prepare("DELETE FROM templates WHERE name = ?")?;
+    statement.bind((1, name))?;
+    statement.next()?;
+    Ok(())
+}
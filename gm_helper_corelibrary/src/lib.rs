@@ -2,7 +2,11 @@ mod entities;
 mod libtext;
 mod global_enums;
 mod dnd_tools;
+mod session_log;
+mod rulesets;
 pub use entities::*;
 pub use libtext::*;
 pub use global_enums::*;
 pub use dnd_tools::*;
+pub use session_log::*;
+pub use rulesets::*;
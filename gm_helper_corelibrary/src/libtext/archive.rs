@@ -0,0 +1,230 @@
+use super::{TranscriptionEngine, Transcript, read_wav, to_whisper_samples, WHISPER_SAMPLE_RATE};
+#[cfg(feature = "audio-devices")]
+use super::{read_wav_range, play_audio};
+use crate::entities::TtrpgEntity;
+use anyhow::{anyhow, Error};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Serialize, Deserialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "audio-devices")]
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_ARCHIVE_DIR: &str = "./sessions";
+const METADATA_FILE: &str = "session.json";
+const AUDIO_FILE: &str = "audio.wav";
+const COMPRESSED_AUDIO_FILE: &str = "audio.wav.gz";
+
+// One recording of a play session, kept in its own folder of the archive with its audio and transcript
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct SessionRecording {
+    pub id: String,
+    pub started: u64, // seconds since the unix epoch
+    #[serde(default)]
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub entity: Option<String>, // name of the entity the session is about
+    #[serde(default)]
+    pub compressed: bool,
+    #[serde(default)]
+    pub transcript: Option<Transcript>,
+    #[serde(default)]
+    pub model: Option<String>, // the whisper model the transcript came from
+    #[serde(skip)]
+    pub directory: PathBuf
+}
+
+impl SessionRecording {
+    // Where the recording writes its audio, or where the compressed audio ended up
+    pub fn audio_file(&self) -> PathBuf {
+        self.directory.join(if self.compressed {COMPRESSED_AUDIO_FILE} else {AUDIO_FILE})
+    }
+    pub fn get_description(&self) -> String {
+        let seconds = self.duration_ms / 1000;
+        let mut description = format!("{} ({}:{:02}:{:02})", utc_date(self.started), seconds / 3600, seconds / 60 % 60, seconds % 60);
+        if let Some(entity) = &self.entity {
+            description += &format!(" with {}", entity);
+        }
+        match (&self.transcript, &self.model) {
+            (Some(transcript), Some(model)) => description += &format!(", {} by {}", transcript.get_description(), model),
+            (Some(transcript), None) => description += &format!(", {}", transcript.get_description()),
+            _ => description += ", not transcribed",
+        }
+        description
+    }
+    pub fn save(&self) -> Result<(), Error> {
+        std::fs::write(self.directory.join(METADATA_FILE), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+    // Called once recording stopped, stores the transcript made while recording
+    pub fn finish(&mut self, transcript: Option<Transcript>, model: Option<String>, compress: bool) -> Result<(), Error> {
+        let reader = WavReader::open(self.audio_file())
+            .map_err(|e| anyhow!("Session {} has no readable audio: {}", self.id, e))?;
+        self.duration_ms = reader.duration() as u64 * 1000 / reader.spec().sample_rate.max(1) as u64;
+        drop(reader);
+        if compress {
+            self.compress()?;
+        }
+        self.transcript = transcript.map(|mut transcript| {
+            transcript.audio_file = Some(self.audio_file());
+            transcript
+        });
+        self.model = model;
+        self.save()
+    }
+    // Keeps the audio at whisper's 16KHz in mono and gzips it, plenty for speech at a fraction of the size
+    pub fn compress(&mut self) -> Result<(), Error> {
+        if self.compressed {
+            return Ok(());
+        }
+        let original = self.audio_file();
+        let (samples, spec) = read_wav(&original)?;
+        let converted = to_whisper_samples(&samples, spec.channels, spec.sample_rate);
+        let temporary = self.directory.join("audio_16k.wav");
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: WHISPER_SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&temporary, spec)?;
+        for sample in converted.iter() {
+            writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+        }
+        writer.finalize()?;
+        let mut encoder = GzEncoder::new(File::create(self.directory.join(COMPRESSED_AUDIO_FILE))?, Compression::default());
+        std::io::copy(&mut BufReader::new(File::open(&temporary)?), &mut encoder)?;
+        encoder.finish()?;
+        std::fs::remove_file(&temporary)?;
+        std::fs::remove_file(&original)?;
+        self.compressed = true;
+        let audio_file = self.audio_file();
+        if let Some(transcript) = self.transcript.as_mut() {
+            transcript.audio_file = Some(audio_file);
+        }
+        self.save()
+    }
+    // Transcribes the stored audio again, e.g. with a larger model or another language
+    pub fn retranscribe(&mut self, engine: &mut TranscriptionEngine) -> Result<(), Error> {
+        let (samples, spec) = read_wav(&self.audio_file())?;
+        let samples = to_whisper_samples(&samples, spec.channels, spec.sample_rate);
+        let mut transcript = Transcript::new(Some(self.audio_file()));
+        for segment in engine.transcribe_segments(&samples)? {
+            transcript.push(segment);
+        }
+        self.transcript = Some(transcript);
+        self.model = Some(engine.config().model_file().file_name().map_or(engine.config().model_name(), |n| n.to_string_lossy().to_string()));
+        self.save()
+    }
+    // Plays the audio from the time on, blocking until it ends or playing is set to false
+    #[cfg(feature = "audio-devices")]
+    pub fn replay(&self, from_ms: u64, playing: Arc<Mutex<bool>>) -> Result<(), Error> {
+        let (samples, spec) = read_wav_range(&self.audio_file(), from_ms, u64::MAX)?;
+        play_audio(&samples, spec.channels, spec.sample_rate, playing)
+    }
+}
+
+pub struct SessionArchive {
+    pub directory: PathBuf
+}
+
+impl SessionArchive {
+    pub fn open(directory: &Path) -> Result<SessionArchive, Error> {
+        std::fs::create_dir_all(directory)
+            .map_err(|e| anyhow!("Could not open the session archive {}: {}", directory.display(), e))?;
+        Ok(SessionArchive {directory: directory.to_path_buf()})
+    }
+    // A new session folder for a recording that starts now
+    pub fn start_session(&self, entity: Option<&TtrpgEntity>) -> Result<SessionRecording, Error> {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut id = String::new();
+        while id.is_empty() || self.directory.join(&id).exists() {
+            let suffix: String = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(6)
+                .map(char::from)
+                .collect();
+            id = format!("{}-{}", started, suffix.to_lowercase());
+        }
+        let directory = self.directory.join(&id);
+        std::fs::create_dir_all(&directory)?;
+        let session = SessionRecording {
+            id,
+            started,
+            duration_ms: 0,
+            entity: entity.map(|e| e.name.clone()),
+            compressed: false,
+            transcript: None,
+            model: None,
+            directory
+        };
+        session.save()?;
+        Ok(session)
+    }
+    pub fn load(&self, id: &str) -> Result<SessionRecording, Error> {
+        let directory = self.directory.join(id);
+        let json_string = std::fs::read_to_string(directory.join(METADATA_FILE))
+            .map_err(|e| anyhow!("No session {} in the archive: {}", id, e))?;
+        let mut session: SessionRecording = serde_json::from_str(&json_string)?;
+        session.directory = directory;
+        Ok(session)
+    }
+    // Newest first, along with the folders that could not be read
+    pub fn list(&self) -> (Vec<SessionRecording>, Vec<String>) {
+        let mut sessions = Vec::new();
+        let mut errors = Vec::new();
+        let entries = match std::fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(e) => return (sessions, vec![format!("{}: {}", self.directory.display(), e)]),
+        };
+        for entry in entries.flatten() {
+            if !entry.path().is_dir() {
+                continue;
+            }
+            let id = entry.file_name().to_string_lossy().to_string();
+            match self.load(&id) {
+                Ok(session) => sessions.push(session),
+                Err(e) => errors.push(format!("{}: {}", id, e)),
+            }
+        }
+        sessions.sort_by(|a, b| b.started.cmp(&a.started).then(b.id.cmp(&a.id)));
+        (sessions, errors)
+    }
+    pub fn sessions_with(&self, entity: &str) -> Vec<SessionRecording> {
+        self.list().0.into_iter().filter(|s| s.entity.as_deref() == Some(entity)).collect()
+    }
+    pub fn delete(&self, id: &str) -> Result<(), Error> {
+        let directory = self.directory.join(id);
+        if id.is_empty() || !directory.join(METADATA_FILE).is_file() {
+            return Err(anyhow!("No session {} in the archive", id));
+        }
+        std::fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+}
+
+// e.g. 2023-06-14 19:05 UTC
+fn utc_date(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let (hours, minutes) = (timestamp / 3600 % 24, timestamp / 60 % 60);
+    // days to a civil date, from Howard Hinnant's date algorithms
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {month_index + 3} else {month_index - 9};
+    let year = year_of_era + era * 400 + if month <= 2 {1} else {0};
+    format!("{}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, hours, minutes)
+}
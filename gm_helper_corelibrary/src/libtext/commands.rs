@@ -0,0 +1,533 @@
+use crate::dnd_tools::{Combat, HP_LABEL, MAX_HP_LABEL};
+use crate::entities::{DiceExpression, Elements, TtrpgEntity};
+use crate::session_log::{LogKind, SessionLog};
+use anyhow::{anyhow, Error};
+
+// How much damage or healing was said, a number or dice rolled when the command is carried out
+#[derive(Clone, Debug)]
+pub enum Amount {
+    Flat(i32),
+    Dice(DiceExpression)
+}
+
+impl Amount {
+    pub fn get_description(&self) -> String {
+        match self {
+            Amount::Flat(number) => number.to_string(),
+            Amount::Dice(dice) => dice.expression.clone(),
+        }
+    }
+    // The amount along with how it came about for the log
+    fn roll(&self) -> (i32, String) {
+        match self {
+            Amount::Flat(number) => (*number, number.to_string()),
+            Amount::Dice(dice) => {
+                let (rolled, total) = dice.roll();
+                let total = total.max(0);
+                (total, format!("{} {:?} = {}", dice.expression, rolled, total))
+            },
+        }
+    }
+}
+
+// What a spoken sentence asks for. Targets are names of combatants or entities, looked up again
+// when the command is carried out since the fight may have changed since it was heard.
+#[derive(Clone, Debug)]
+pub enum VoiceCommand {
+    Roll(DiceExpression), // "roll two d six plus three"
+    RollInitiative, // "roll for initiative"
+    NextTurn, // "advance initiative", "next turn"
+    Damage {target: String, amount: Amount}, // "goblin two takes seven damage"
+    Heal {target: String, amount: Amount}, // "heal Aria for five"
+    AdjustCounter {entity: String, counter: String, change: i32}, // "Aria spends two ki"
+    Confirm,
+    Cancel
+}
+
+impl VoiceCommand {
+    pub fn get_description(&self) -> String {
+        match self {
+            VoiceCommand::Roll(dice) => format!("Roll {}", dice.expression),
+            VoiceCommand::RollInitiative => "Roll initiative".to_string(),
+            VoiceCommand::NextTurn => "Advance initiative to the next turn".to_string(),
+            VoiceCommand::Damage {target, amount} => format!("{} takes {} damage", target, amount.get_description()),
+            VoiceCommand::Heal {target, amount} => format!("{} heals {}", target, amount.get_description()),
+            VoiceCommand::AdjustCounter {entity, counter, change} if *change < 0 => format!("{} {} -{}", entity, counter, -change),
+            VoiceCommand::AdjustCounter {entity, counter, change} => format!("{} {} +{}", entity, counter, change),
+            VoiceCommand::Confirm => "Confirm".to_string(),
+            VoiceCommand::Cancel => "Cancel".to_string(),
+        }
+    }
+    // Commands that change the fight or an entity wait for a confirmation, rolls happen right away
+    pub fn mutates(&self) -> bool {
+        !matches!(self, VoiceCommand::Roll(_) | VoiceCommand::Confirm | VoiceCommand::Cancel)
+    }
+    // Carries the command out and records it in the log, returning what happened
+    pub fn apply(&self, combat: &mut Combat, entities: &mut [TtrpgEntity], log: &mut SessionLog) -> Result<String, Error> {
+        let outcome = match self {
+            VoiceCommand::Roll(dice) => {
+                let (rolled, total) = dice.roll();
+                let outcome = format!("Rolled {} {:?} = {}", dice.expression, rolled, total);
+                log.record(LogKind::Roll, &outcome);
+                return Ok(outcome);
+            },
+            VoiceCommand::RollInitiative => {
+                if combat.combatants.is_empty() {
+                    return Err(anyhow!("Add combatants before rolling initiative"));
+                }
+                combat.roll_initiative();
+                "Rolled initiative".to_string()
+            },
+            VoiceCommand::NextTurn => {
+                combat.next_turn()?;
+                combat.current().map_or("Next turn".to_string(), |c| format!("{}'s turn", c.name))
+            },
+            VoiceCommand::Damage {target, amount} => {
+                let (amount, rolled) = amount.roll();
+                if amount <= 0 {
+                    return Err(anyhow!("{} takes no damage from {}", target, rolled));
+                }
+                if let Some(index) = combat.combatants.iter().position(|c| &c.name == target) {
+                    combat.damage(index, amount)?;
+                } else {
+                    let hit_points = change_hit_points(find_entity(entities, target)?, -amount)?;
+                    log.record(LogKind::Note, &format!("{} took {} damage, {} {}", target, amount, HP_LABEL, hit_points));
+                }
+                format!("{} takes {} damage", target, rolled)
+            },
+            VoiceCommand::Heal {target, amount} => {
+                let (amount, rolled) = amount.roll();
+                if amount <= 0 {
+                    return Err(anyhow!("{} heals nothing from {}", target, rolled));
+                }
+                if let Some(index) = combat.combatants.iter().position(|c| &c.name == target) {
+                    combat.heal(index, amount)?;
+                } else {
+                    let hit_points = change_hit_points(find_entity(entities, target)?, amount)?;
+                    log.record(LogKind::Note, &format!("{} healed {}, {} {}", target, amount, HP_LABEL, hit_points));
+                }
+                format!("{} heals {}", target, rolled)
+            },
+            VoiceCommand::AdjustCounter {entity, counter, change} => {
+                let found = find_entity(entities, entity)?.find_counter_mut(counter)
+                    .ok_or_else(|| anyhow!("{} has no {} counter", entity, counter))?;
+                if *change < 0 {
+                    found.decrement(-change);
+                } else {
+                    found.increment(*change);
+                }
+                let outcome = format!("{} {} is now {}", entity, found.label, found.number);
+                log.record(LogKind::Note, &outcome);
+                outcome
+            },
+            VoiceCommand::Confirm | VoiceCommand::Cancel => return Err(anyhow!("Nothing to carry out for {}", self.get_description())),
+        };
+        combat.log_events(log);
+        Ok(outcome)
+    }
+}
+
+// A command that was heard and waits for the GM to confirm it
+#[derive(Clone, Debug)]
+pub struct PendingCommand {
+    pub command: VoiceCommand,
+    pub heard: String // the transcribed sentence
+}
+
+impl PendingCommand {
+    pub fn get_description(&self) -> String {
+        format!("{}? (heard \"{}\")", self.command.get_description(), self.heard.trim())
+    }
+}
+
+// Listens to transcribed sentences for commands. Anything that changes state is held until it is
+// confirmed, by voice ("confirm", "cancel") or through confirm and cancel.
+#[derive(Clone, Debug, Default)]
+pub struct VoiceControl {
+    pub pending: Option<PendingCommand>
+}
+
+impl VoiceControl {
+    pub fn new() -> VoiceControl {
+        VoiceControl::default()
+    }
+    // Returns what happened to show the GM, None when the sentence held no command
+    pub fn hear(&mut self, text: &str, combat: &mut Combat, entities: &mut [TtrpgEntity], log: &mut SessionLog) -> Option<Result<String, Error>> {
+        let command = recognize_command(text, combat, entities)?;
+        match command {
+            VoiceCommand::Confirm => Some(self.confirm(combat, entities, log)),
+            VoiceCommand::Cancel => Some(self.cancel()),
+            command if !command.mutates() => Some(command.apply(combat, entities, log)),
+            command => {
+                let pending = PendingCommand {command, heard: text.to_string()};
+                let description = pending.get_description();
+                self.pending = Some(pending);
+                Some(Ok(description))
+            },
+        }
+    }
+    pub fn confirm(&mut self, combat: &mut Combat, entities: &mut [TtrpgEntity], log: &mut SessionLog) -> Result<String, Error> {
+        let pending = self.pending.take().ok_or_else(|| anyhow!("No command is waiting to be confirmed"))?;
+        pending.command.apply(combat, entities, log)
+    }
+    pub fn cancel(&mut self) -> Result<String, Error> {
+        let pending = self.pending.take().ok_or_else(|| anyhow!("No command is waiting to be cancelled"))?;
+        Ok(format!("Cancelled {}", pending.command.get_description()))
+    }
+}
+
+// Finds the first command in the sentence, names are matched against the combatants and entities
+pub fn recognize_command(text: &str, combat: &Combat, entities: &[TtrpgEntity]) -> Option<VoiceCommand> {
+    let tokens = tokenize(text);
+    let mut targets: Vec<String> = combat.combatants.iter().map(|c| c.name.clone()).collect();
+    for entity in entities.iter() {
+        if !targets.contains(&entity.name) {
+            targets.push(entity.name.clone());
+        }
+    }
+    let entity_names: Vec<String> = entities.iter().map(|e| e.name.clone()).collect();
+    // a lone "confirm" or "cancel" answers a pending command, in longer sentences it is table talk.
+    // "yes" and "no" are said at the table all the time so they never count.
+    if tokens.len() <= 3 {
+        if tokens.iter().any(|t| matches!(t.as_str(), "confirm" | "confirmed")) {
+            return Some(VoiceCommand::Confirm);
+        }
+        if tokens.iter().any(|t| matches!(t.as_str(), "cancel" | "cancelled" | "canceled")) {
+            return Some(VoiceCommand::Cancel);
+        }
+    }
+    for index in 0..tokens.len() {
+        let (before, after) = (&tokens[..index], &tokens[index + 1..]);
+        let command = match tokens[index].as_str() {
+            "roll" | "rolling" => match after {
+                [initiative, ..] | [_, initiative, ..] if initiative == "initiative" => Some(VoiceCommand::RollInitiative),
+                _ => parse_dice(after).and_then(|(expression, _)| DiceExpression::parse(&expression).ok()).map(VoiceCommand::Roll),
+            },
+            "advance" if matches!(after.first().map(|t| t.as_str()), Some("initiative") | Some("turn") | Some("the")) => Some(VoiceCommand::NextTurn),
+            "next" if after.first().map_or(false, |t| t == "turn") => Some(VoiceCommand::NextTurn),
+            "takes" | "take" | "took" => {
+                let target = match_suffix(before, &targets);
+                let amount = parse_amount(after).map(|(amount, _)| amount);
+                target.zip(amount).map(|(target, amount)| VoiceCommand::Damage {target, amount})
+            },
+            "heals" | "regains" => {
+                let target = match_suffix(before, &targets);
+                let amount = parse_amount(after).map(|(amount, _)| amount);
+                target.zip(amount).map(|(target, amount)| VoiceCommand::Heal {target, amount})
+            },
+            "heal" => match_prefix(after, &targets).and_then(|(target, used)| {
+                let rest = skip_words(&after[used..], &["for", "by"]);
+                parse_amount(rest).map(|(amount, _)| VoiceCommand::Heal {target, amount})
+            }),
+            "deal" | "deals" | "does" | "do" => parse_amount(after).and_then(|(amount, used)| {
+                let rest = skip_words(&after[used..], &["points", "point", "of", "damage", "to"]);
+                match_prefix(rest, &targets).map(|(target, _)| VoiceCommand::Damage {target, amount})
+            }),
+            "gains" | "gets" | "loses" | "spends" | "uses" => match_suffix(before, &entity_names).and_then(|entity| {
+                let sign = if matches!(tokens[index].as_str(), "gains" | "gets") {1} else {-1};
+                let (number, used) = parse_count(after)?;
+                let counter = match_counter(&after[used..], entities, &entity)?;
+                Some(VoiceCommand::AdjustCounter {entity, counter, change: sign * number})
+            }),
+            "increase" | "raise" | "decrease" | "lower" | "reduce" => match_prefix(after, &entity_names).and_then(|(entity, used)| {
+                let sign = if matches!(tokens[index].as_str(), "increase" | "raise") {1} else {-1};
+                let rest = &after[used..];
+                let counter = match_counter(rest, entities, &entity)?;
+                let rest = skip_words(&rest[tokenize(&counter).len()..], &["by"]);
+                let (number, _) = parse_number(rest)?;
+                Some(VoiceCommand::AdjustCounter {entity, counter, change: sign * number})
+            }),
+            "add" | "give" => parse_count(after).and_then(|(number, used)| {
+                let counter_words = &after[used..];
+                let to = counter_words.iter().position(|t| t == "to")?;
+                let (entity, _) = match_prefix(&counter_words[to + 1..], &entity_names)?;
+                let counter = match_counter(&counter_words[..to], entities, &entity)?;
+                Some(VoiceCommand::AdjustCounter {entity, counter, change: number})
+            }),
+            _ => None,
+        };
+        if command.is_some() {
+            return command;
+        }
+    }
+    None
+}
+
+// Lowercase words with punctuation dropped. "+" and a "-" before a number are read as plus and
+// minus, and dice shorthand like "2d6" is split into "2 d 6" so it reads like the spoken form.
+fn tokenize(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.to_lowercase().chars().collect();
+    let mut spaced = String::new();
+    for (index, c) in chars.iter().enumerate() {
+        match c {
+            '+' => spaced += " plus ",
+            '-' if chars.get(index + 1).map_or(false, |n| n.is_ascii_digit()) => spaced += " minus ",
+            c if c.is_alphanumeric() || *c == '\'' => spaced.push(*c),
+            _ => spaced.push(' '),
+        }
+    }
+    let mut tokens = Vec::new();
+    for word in spaced.split_whitespace() {
+        let word = word.trim_end_matches("'s").trim_matches('\'');
+        match word.split_once('d') {
+            Some((amount, size)) if amount.chars().all(|c| c.is_ascii_digit()) && size.chars().all(|c| c.is_ascii_digit()) && !(amount.is_empty() && size.is_empty()) => {
+                if !amount.is_empty() {
+                    tokens.push(amount.to_string());
+                }
+                tokens.push("d".to_string());
+                if !size.is_empty() {
+                    tokens.push(size.to_string());
+                }
+            },
+            _ if !word.is_empty() => tokens.push(word.to_string()),
+            _ => {},
+        }
+    }
+    tokens
+}
+
+fn number_word(word: &str) -> Option<i32> {
+    const UNITS: [&str; 20] = ["zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+        "eleven", "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen", "nineteen"];
+    const TENS: [&str; 8] = ["twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety"];
+    if let Some(units) = UNITS.iter().position(|u| *u == word) {
+        return Some(units as i32);
+    }
+    TENS.iter().position(|t| *t == word).map(|tens| (tens as i32 + 2) * 10)
+}
+
+// Numbers under a hundred, said like "seven", "twenty one" or written like "21"
+fn parse_small_number(tokens: &[String]) -> Option<(i32, usize)> {
+    let first = tokens.first()?;
+    if let Ok(number) = first.parse::<i32>() {
+        return Some((number, 1));
+    }
+    let number = number_word(first)?;
+    if number >= 20 {
+        if let Some(units) = tokens.get(1).and_then(|t| number_word(t)).filter(|u| (1..10).contains(u)) {
+            return Some((number + units, 2));
+        }
+    }
+    Some((number, 1))
+}
+
+// A number and how many words it took, up to "nine hundred and ninety nine"
+fn parse_number(tokens: &[String]) -> Option<(i32, usize)> {
+    let said_a_hundred = matches!(tokens.first().map(|t| t.as_str()), Some("a") | Some("an")) && tokens.get(1).map_or(false, |t| t == "hundred");
+    let (mut number, mut used) = if said_a_hundred {(1, 1)} else {parse_small_number(tokens)?};
+    if tokens.get(used).map_or(false, |t| t == "hundred") && number < 10 {
+        number *= 100;
+        used += 1;
+        let rest = if tokens.get(used).map_or(false, |t| t == "and") {used + 1} else {used};
+        if let Some((small, small_used)) = parse_small_number(&tokens[rest.min(tokens.len())..]) {
+            number += small;
+            used = rest + small_used;
+        }
+    }
+    Some((number, used))
+}
+
+// A number where "a" or "an" also means one, as in "spends a ki point"
+fn parse_count(tokens: &[String]) -> Option<(i32, usize)> {
+    parse_number(tokens).or_else(|| match tokens.first().map(|t| t.as_str()) {
+        Some("a") | Some("an") => Some((1, 1)),
+        _ => None,
+    })
+}
+
+// Dice sizes are often said in the plural, "two d sixes"
+fn parse_dice_size(tokens: &[String]) -> Option<(i32, usize)> {
+    parse_number(tokens).or_else(|| {
+        let word = tokens.first()?;
+        let singular = word.strip_suffix("ies").map(|w| format!("{}y", w))
+            .or_else(|| word.strip_suffix("es").map(|w| w.to_string()))
+            .or_else(|| word.strip_suffix('s').map(|w| w.to_string()))?;
+        number_word(&singular).or_else(|| number_word(word.strip_suffix('s')?)).map(|number| (number, 1))
+    })
+}
+
+// Spoken dice like "a d twenty plus five" as a dice expression, with how many words it took
+fn parse_dice(tokens: &[String]) -> Option<(String, usize)> {
+    let mut expression = String::new();
+    let mut used = 0;
+    let mut sign = "";
+    let mut index = 0;
+    loop {
+        let rest = &tokens[index.min(tokens.len())..];
+        let (count, count_used) = match rest.first().map(|t| t.as_str()) {
+            Some("a") | Some("an") if rest.get(1).map_or(false, |t| t == "d") => (1, 1),
+            _ => parse_number(rest).unwrap_or((1, 0)),
+        };
+        let term = if rest.get(count_used).map_or(false, |t| t == "d") {
+            let (size, size_used) = parse_dice_size(&rest[count_used + 1..])?;
+            index += count_used + 1 + size_used;
+            format!("{}d{}", count, size)
+        } else if count_used > 0 {
+            index += count_used;
+            count.to_string()
+        } else {
+            break;
+        };
+        expression += &format!("{}{}", sign, term);
+        used = index;
+        sign = match tokens.get(index).map(|t| t.as_str()) {
+            Some("plus") | Some("and") => "+",
+            Some("minus") => "-",
+            _ => break,
+        };
+        index += 1;
+    }
+    if expression.is_empty() {None} else {Some((expression, used))}
+}
+
+// Amounts without dice, "seven plus three", add up to a flat number
+fn parse_amount(tokens: &[String]) -> Option<(Amount, usize)> {
+    let (expression, used) = parse_dice(tokens)?;
+    let dice = DiceExpression::parse(&expression).ok()?;
+    if dice.rolls.is_empty() {
+        Some((Amount::Flat(dice.modifier), used))
+    } else {
+        Some((Amount::Dice(dice), used))
+    }
+}
+
+fn skip_words<'a>(tokens: &'a [String], words: &[&str]) -> &'a [String] {
+    let skipped = tokens.iter().take_while(|t| words.contains(&t.as_str())).count();
+    &tokens[skipped..]
+}
+
+// Heard words match a name when they are the same, numbers may be said or written
+fn words_match(heard: &[String], name: &[String]) -> bool {
+    heard.len() == name.len() && heard.iter().zip(name.iter()).all(|(h, n)| {
+        h == n || h.trim_end_matches('s') == n.trim_end_matches('s') || single_number(h).map_or(false, |number| single_number(n) == Some(number))
+    })
+}
+
+fn single_number(word: &str) -> Option<i32> {
+    word.parse::<i32>().ok().or_else(|| number_word(word))
+}
+
+// The longest name the words start with and how many words it took
+fn match_prefix(tokens: &[String], names: &[String]) -> Option<(String, usize)> {
+    names.iter()
+        .map(|name| (name, tokenize(name)))
+        .filter(|(_, words)| !words.is_empty() && words.len() <= tokens.len() && words_match(&tokens[..words.len()], words))
+        .max_by_key(|(_, words)| words.len())
+        .map(|(name, words)| (name.clone(), words.len()))
+}
+
+// The longest name the words end with
+fn match_suffix(tokens: &[String], names: &[String]) -> Option<String> {
+    names.iter()
+        .map(|name| (name, tokenize(name)))
+        .filter(|(_, words)| !words.is_empty() && words.len() <= tokens.len() && words_match(&tokens[tokens.len() - words.len()..], words))
+        .max_by_key(|(_, words)| words.len())
+        .map(|(name, _)| name.clone())
+}
+
+// A counter of the entity named at the start of the words, "ki points" finds "Ki Points" or "Ki"
+fn match_counter(tokens: &[String], entities: &[TtrpgEntity], entity: &str) -> Option<String> {
+    let entity = entities.iter().find(|e| e.name == entity)?;
+    let labels: Vec<String> = entity.elements.values()
+        .filter_map(|element| match element {
+            Elements::Counter(c) => Some(c.label.clone()),
+            _ => None,
+        })
+        .collect();
+    match_prefix(tokens, &labels).map(|(label, _)| label)
+}
+
+// Outside of combat hit points stay between 0 and the "Max HP" counter, when there is one
+fn change_hit_points(entity: &mut TtrpgEntity, change: i32) -> Result<i32, Error> {
+    let max_hit_points = entity.find_counter(MAX_HP_LABEL).map(|c| c.number);
+    let name = entity.name.clone();
+    let counter = entity.find_counter_mut(HP_LABEL)
+        .ok_or_else(|| anyhow!("{} has no {} counter", name, HP_LABEL))?;
+    let mut hit_points = (counter.number + change).max(0);
+    if let Some(max_hit_points) = max_hit_points {
+        hit_points = hit_points.min(max_hit_points.max(counter.number));
+    }
+    counter.set(hit_points);
+    Ok(counter.number)
+}
+
+fn find_entity<'a>(entities: &'a mut [TtrpgEntity], name: &str) -> Result<&'a mut TtrpgEntity, Error> {
+    entities.iter_mut().find(|e| e.name == name)
+        .ok_or_else(|| anyhow!("No combatant or entity called {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dnd_tools::StatBlock;
+    use crate::entities::Counter;
+
+    // Two goblins in the fight and Aria, hurt and outside of it
+    fn table() -> (Combat, Vec<TtrpgEntity>) {
+        let mut combat = Combat::new();
+        let goblin = StatBlock::new("Goblin", 15, 7, 4, "1d6+2", 2).unwrap();
+        combat.add_stat_block(&goblin);
+        combat.add_stat_block(&goblin);
+        let mut aria = TtrpgEntity::new(true, false, None, "Aria".to_string(), None);
+        aria.add_element(Elements::Counter(Counter::new(1, 1, HP_LABEL.to_string(), 15)));
+        aria.add_element(Elements::Counter(Counter::new(2, 2, MAX_HP_LABEL.to_string(), 20)));
+        (combat, vec![aria])
+    }
+
+    fn hit_points(entities: &[TtrpgEntity]) -> i32 {
+        entities[0].find_counter(HP_LABEL).unwrap().number
+    }
+
+    #[test]
+    fn dice_are_rolled_from_number_words() {
+        let (combat, entities) = table();
+        match recognize_command("Okay, roll two d six plus three.", &combat, &entities) {
+            Some(VoiceCommand::Roll(dice)) => assert_eq!(dice.expression, "2d6+3"),
+            other => panic!("Heard {:?}", other),
+        }
+    }
+
+    #[test]
+    fn damage_waits_for_confirmation() {
+        let (mut combat, mut entities) = table();
+        let mut log = SessionLog::new();
+        let mut voice_control = VoiceControl::new();
+        voice_control.hear("goblin two takes seven damage", &mut combat, &mut entities, &mut log).unwrap().unwrap();
+        assert_eq!(combat.combatants[1].hit_points.number, 7);
+        assert!(voice_control.hear("yes", &mut combat, &mut entities, &mut log).is_none());
+        voice_control.hear("confirm", &mut combat, &mut entities, &mut log).unwrap().unwrap();
+        assert_eq!(combat.combatants[0].hit_points.number, 7);
+        assert_eq!(combat.combatants[1].hit_points.number, 0);
+        assert!(voice_control.pending.is_none());
+    }
+
+    #[test]
+    fn advance_initiative_moves_to_the_next_turn() {
+        let (combat, entities) = table();
+        assert!(matches!(recognize_command("advance initiative", &combat, &entities), Some(VoiceCommand::NextTurn)));
+        assert!(matches!(recognize_command("next turn please", &combat, &entities), Some(VoiceCommand::NextTurn)));
+    }
+
+    #[test]
+    fn hit_points_outside_of_combat_stay_between_zero_and_max() {
+        let (mut combat, mut entities) = table();
+        let mut log = SessionLog::new();
+        let heal = recognize_command("heal Aria for ten", &combat, &entities).unwrap();
+        heal.apply(&mut combat, &mut entities, &mut log).unwrap();
+        assert_eq!(hit_points(&entities), 20);
+        let damage = recognize_command("Aria takes thirty damage", &combat, &entities).unwrap();
+        damage.apply(&mut combat, &mut entities, &mut log).unwrap();
+        assert_eq!(hit_points(&entities), 0);
+    }
+
+    #[test]
+    fn nothing_to_deal_or_heal_is_refused() {
+        let (mut combat, mut entities) = table();
+        let mut log = SessionLog::new();
+        let damage = VoiceCommand::Damage {target: "Aria".to_string(), amount: Amount::Flat(0)};
+        assert!(damage.apply(&mut combat, &mut entities, &mut log).is_err());
+        let heal = VoiceCommand::Heal {target: "Aria".to_string(), amount: Amount::Flat(-3)};
+        assert!(heal.apply(&mut combat, &mut entities, &mut log).is_err());
+        assert_eq!(hit_points(&entities), 15);
+    }
+}
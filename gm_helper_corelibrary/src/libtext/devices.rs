@@ -0,0 +1,179 @@
+use anyhow::{anyhow, Error};
+#[cfg(feature = "audio-devices")]
+use cpal::traits::{DeviceTrait, HostTrait};
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_AUDIO_SETTINGS_FILE: &str = "./audio_settings.json";
+const LEVEL_RELEASE_SECONDS: f32 = 0.3; // how long the meter takes to fall back after a loud sound
+const CLIPPING_LEVEL: f32 = 0.99;
+
+// A config range the device can record with, as cpal reports it
+#[derive(Clone, Debug)]
+pub struct InputConfigRange {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    pub sample_format: String
+}
+
+impl InputConfigRange {
+    pub fn get_description(&self) -> String {
+        if self.min_sample_rate == self.max_sample_rate {
+            format!("{} channels at {}Hz ({})", self.channels, self.min_sample_rate, self.sample_format)
+        } else {
+            format!("{} channels at {}-{}Hz ({})", self.channels, self.min_sample_rate, self.max_sample_rate, self.sample_format)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct InputDeviceInfo {
+    pub name: String,
+    pub is_default: bool,
+    pub default_config: Option<(u16, u32)>, // channels and sample rate a recording would use
+    pub configs: Vec<InputConfigRange>
+}
+
+impl InputDeviceInfo {
+    pub fn get_description(&self) -> String {
+        let mut description = self.name.clone();
+        if self.is_default {
+            description += " (default)";
+        }
+        if let Some((channels, sample_rate)) = self.default_config {
+            description += &format!(", {} channels at {}Hz", channels, sample_rate);
+        }
+        description
+    }
+}
+
+// Every input device of the host with the configs it supports
+#[cfg(feature = "audio-devices")]
+pub fn list_input_devices() -> Result<Vec<InputDeviceInfo>, Error> {
+    let host = cpal::default_host();
+    let default_name = host.default_input_device().and_then(|d| d.name().ok());
+    let mut devices = Vec::new();
+    for device in host.input_devices()? {
+        // devices that went away while listing have no name
+        let name = match device.name() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        let default_config = device.default_input_config()
+            .ok()
+            .map(|c| (c.channels(), c.sample_rate().0));
+        let configs = device.supported_input_configs()
+            .map(|configs| configs.map(|c| InputConfigRange {
+                channels: c.channels(),
+                min_sample_rate: c.min_sample_rate().0,
+                max_sample_rate: c.max_sample_rate().0,
+                sample_format: c.sample_format().to_string()
+            }).collect())
+            .unwrap_or_default();
+        devices.push(InputDeviceInfo {is_default: default_name.as_ref() == Some(&name), name, default_config, configs});
+    }
+    Ok(devices)
+}
+
+// The input device with the name, or the default one for None
+#[cfg(feature = "audio-devices")]
+pub fn find_input_device(name: Option<&str>) -> Result<cpal::Device, Error> {
+    let host = cpal::default_host();
+    match name {
+        None => host.default_input_device()
+            .ok_or_else(|| anyhow!("No default input device to record from, choose one of the input devices")),
+        Some(name) => {
+            let mut available = Vec::new();
+            for device in host.input_devices()? {
+                match device.name() {
+                    Ok(device_name) if device_name == name => return Ok(device),
+                    Ok(device_name) => available.push(device_name),
+                    Err(_) => {},
+                }
+            }
+            Err(anyhow!("No input device called {}, available are: {}", name, available.join(", ")))
+        },
+    }
+}
+
+// Audio choices kept between runs
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, Default)]
+pub struct AudioSettings {
+    #[serde(default)]
+    pub input_device: Option<String> // None records from the default device
+}
+
+impl AudioSettings {
+    // Settings that were never saved are the defaults
+    pub fn load(path: &Path) -> Result<AudioSettings, Error> {
+        if !path.is_file() {
+            return Ok(AudioSettings::default());
+        }
+        let json_string = std::fs::read_to_string(path)?;
+        serde_json::from_str(&json_string)
+            .map_err(|e| anyhow!("Could not read the audio settings {}: {}", path.display(), e))
+    }
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LevelReading {
+    pub rms: f32, // 0.0 to 1.0, falls back slowly
+    pub peak: f32, // loudest sample, falls back slowly
+    pub clipping: bool // the last audio hit full scale
+}
+
+impl LevelReading {
+    pub fn rms_db(&self) -> f32 {
+        20.0 * self.rms.max(0.00001).log10()
+    }
+    // 0.0 to 1.0 over -60dB to 0dB, for drawing a meter
+    pub fn meter(&self) -> f32 {
+        ((self.rms_db() + 60.0) / 60.0).clamp(0.0, 1.0)
+    }
+    pub fn get_description(&self) -> String {
+        if self.clipping {
+            format!("{:.0} dB, too loud", self.rms_db())
+        } else {
+            format!("{:.0} dB", self.rms_db())
+        }
+    }
+}
+
+// The live level of the input, updated by the recording and read by whoever shows it
+#[derive(Clone, Debug, Default)]
+pub struct InputLevel {
+    reading: Arc<Mutex<LevelReading>>
+}
+
+impl InputLevel {
+    pub fn new() -> InputLevel {
+        InputLevel::default()
+    }
+    // Loud audio shows at once and falls back over the release time
+    pub fn update(&self, samples: &[f32], channels: u16, sample_rate: u32) {
+        if samples.is_empty() || sample_rate == 0 {
+            return;
+        }
+        let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+        let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        let seconds = samples.len() as f32 / (channels.max(1) as u32 * sample_rate) as f32;
+        let release = (-seconds / LEVEL_RELEASE_SECONDS).exp();
+        let mut reading = self.reading.lock().unwrap();
+        reading.rms = rms.max(reading.rms * release);
+        reading.peak = peak.max(reading.peak * release);
+        reading.clipping = peak >= CLIPPING_LEVEL;
+    }
+    pub fn reading(&self) -> LevelReading {
+        *self.reading.lock().unwrap()
+    }
+    pub fn reset(&self) {
+        *self.reading.lock().unwrap() = LevelReading::default();
+    }
+}
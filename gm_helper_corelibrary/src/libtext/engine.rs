@@ -0,0 +1,231 @@
+use super::{VadConfig, Segment, Transcript, WHISPER_SAMPLE_RATE};
+use anyhow::{anyhow, Error};
+use std::path::{Path, PathBuf};
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext};
+
+pub const DEFAULT_MODELS_DIR: &str = "whisper.cpp/models";
+const MIN_WHISPER_MS: usize = 1_000; // whisper.cpp hears nothing in shorter audio
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModelSize {
+    Tiny,
+    Base,
+    Small,
+    Medium
+}
+
+impl ModelSize {
+    pub fn all() -> [ModelSize; 4] {
+        [ModelSize::Tiny, ModelSize::Base, ModelSize::Small, ModelSize::Medium]
+    }
+    pub fn name(&self) -> &str {
+        match self {
+            ModelSize::Tiny => "tiny",
+            ModelSize::Base => "base",
+            ModelSize::Small => "small",
+            ModelSize::Medium => "medium",
+        }
+    }
+}
+
+// Which whisper model to load and how to run it. The model file is found from the size in the
+// models directory unless a path to a model file is given.
+#[derive(Clone, Debug)]
+pub struct EngineConfig {
+    pub models_dir: PathBuf,
+    pub model_path: Option<PathBuf>,
+    pub size: ModelSize,
+    pub english_only: bool,
+    pub language: Option<String>, // None lets whisper detect the language
+    pub threads: u32,
+    pub vad: Option<VadConfig> // only the speech it detects is transcribed, None transcribes everything
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        let threads = std::thread::available_parallelism().map(|n| n.get() as u32).unwrap_or(1).min(4);
+        EngineConfig {
+            models_dir: PathBuf::from(DEFAULT_MODELS_DIR),
+            model_path: None,
+            size: ModelSize::Base,
+            english_only: true,
+            language: Some("en".to_string()),
+            threads,
+            vad: Some(VadConfig::default())
+        }
+    }
+}
+
+impl EngineConfig {
+    // The name whisper.cpp's download script gives the model, e.g. base.en
+    pub fn model_name(&self) -> String {
+        if self.english_only {
+            format!("{}.en", self.size.name())
+        } else {
+            self.size.name().to_string()
+        }
+    }
+    pub fn model_file(&self) -> PathBuf {
+        match &self.model_path {
+            Some(path) => path.clone(),
+            None => self.models_dir.join(format!("ggml-{}.bin", self.model_name())),
+        }
+    }
+    // Accepts a language code such as "en" or "de", empty or "auto" for detection
+    pub fn set_language(&mut self, language: &str) -> Result<(), Error> {
+        let language = language.trim().to_lowercase();
+        if language.is_empty() || language == "auto" {
+            self.language = None;
+            return Ok(());
+        }
+        if language.len() > 3 || !language.chars().all(|c| c.is_ascii_lowercase()) {
+            return Err(anyhow!("{} is not a language code, use one like en or de, or auto", language));
+        }
+        self.language = Some(language);
+        Ok(())
+    }
+    pub fn get_description(&self) -> String {
+        let language = self.language.clone().unwrap_or("auto".to_string());
+        format!("{} ({}, {} threads)", self.model_file().display(), language, self.threads)
+    }
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.threads == 0 {
+            return Err(anyhow!("Transcription needs at least one thread"));
+        }
+        if let Some(vad) = &self.vad {
+            vad.validate()?;
+        }
+        if self.english_only && self.model_path.is_none() {
+            if let Some(language) = &self.language {
+                if language != "en" {
+                    return Err(anyhow!("The {} model only understands english, not {}", self.model_name(), language));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// A loaded whisper model, kept around so every transcription does not read the model from disk again
+pub struct TranscriptionEngine {
+    context: WhisperContext,
+    config: EngineConfig
+}
+
+impl TranscriptionEngine {
+    pub fn new(config: EngineConfig) -> Result<TranscriptionEngine, Error> {
+        config.validate()?;
+        let model = config.model_file();
+        if !model.is_file() {
+            return Err(anyhow!("No whisper model at {}, download it with models/download-ggml-model.sh {}", model.display(), config.model_name()));
+        }
+        let context = WhisperContext::new(&model.to_string_lossy())
+            .map_err(|e| anyhow!("Could not load the whisper model {}, it may be corrupt: {:?}", model.display(), e))?;
+        if let Some(language) = &config.language {
+            if language != "en" && !context.is_multilingual() {
+                return Err(anyhow!("{} is an english only model and cannot transcribe {}", model.display(), language));
+            }
+        }
+        Ok(TranscriptionEngine {context, config})
+    }
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+    // Language and threads can change between transcriptions, a different model needs a new engine
+    pub fn set_language(&mut self, language: &str) -> Result<(), Error> {
+        let mut config = self.config.clone();
+        config.set_language(language)?;
+        config.validate()?;
+        if let Some(language) = &config.language {
+            if language != "en" && !self.context.is_multilingual() {
+                return Err(anyhow!("The loaded model is english only and cannot transcribe {}", language));
+            }
+        }
+        self.config = config;
+        Ok(())
+    }
+    pub fn set_threads(&mut self, threads: u32) -> Result<(), Error> {
+        if threads == 0 {
+            return Err(anyhow!("Transcription needs at least one thread"));
+        }
+        self.config.threads = threads;
+        Ok(())
+    }
+    pub fn set_vad(&mut self, vad: Option<VadConfig>) -> Result<(), Error> {
+        if let Some(vad) = &vad {
+            vad.validate()?;
+        }
+        self.config.vad = vad;
+        Ok(())
+    }
+    // Transcribes 16KHz mono samples in the -1.0 to 1.0 range
+    pub fn transcribe_samples(&mut self, samples: &[f32]) -> Result<String, Error> {
+        let segments = self.transcribe_segments(samples)?;
+        Ok(segments.iter().map(|s| s.text.as_str()).collect())
+    }
+    // With voice detection on, silence is skipped and each utterance is transcribed on its own,
+    // the segment times still count from the start of the samples
+    pub fn transcribe_segments(&mut self, samples: &[f32]) -> Result<Vec<Segment>, Error> {
+        let vad = match self.config.vad {
+            Some(vad) => vad,
+            None => return self.run_whisper(samples),
+        };
+        let mut segments = Vec::new();
+        for speech in vad.detect(samples, WHISPER_SAMPLE_RATE) {
+            let offset_ms = speech.start_ms(WHISPER_SAMPLE_RATE);
+            for segment in self.run_whisper(&samples[speech.start..speech.end])? {
+                segments.push(Segment {start_ms: segment.start_ms + offset_ms, end_ms: segment.end_ms + offset_ms, ..segment});
+            }
+        }
+        Ok(segments)
+    }
+    // Short utterances such as "yes" or "next turn" are padded with silence up to a second so
+    // whisper transcribes them, the segments still end where the audio does
+    fn run_whisper(&mut self, samples: &[f32]) -> Result<Vec<Segment>, Error> {
+        let audio_ms = (samples.len() * 1000 / WHISPER_SAMPLE_RATE as usize) as u64;
+        let min_samples = WHISPER_SAMPLE_RATE as usize * MIN_WHISPER_MS / 1000;
+        let mut padded = Vec::new();
+        let samples = if samples.len() < min_samples {
+            padded.extend_from_slice(samples);
+            padded.resize(min_samples, 0.0);
+            &padded[..]
+        } else {
+            samples
+        };
+        let mut params = FullParams::new(SamplingStrategy::default());
+        params.set_n_threads(self.config.threads as i32);
+        params.set_language(Some(self.config.language.as_deref().unwrap_or("auto")));
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        params.set_print_timestamps(false);
+        self.context.full(params, samples)
+            .map_err(|e| anyhow!("Whisper could not transcribe the audio: {:?}", e))?;
+        let mut segments = Vec::new();
+        for i in 0..self.context.full_n_segments() {
+            let text = self.context.full_get_segment_text(i)
+                .map_err(|e| anyhow!("Could not read transcribed segment {}: {:?}", i, e))?;
+            // whisper counts time in hundredths of a second
+            let start_ms = (self.context.full_get_segment_t0(i).max(0) as u64 * 10).min(audio_ms);
+            let end_ms = (self.context.full_get_segment_t1(i).max(0) as u64 * 10).min(audio_ms);
+            // special tokens such as timestamps have ids from the end of text token up
+            let mut probabilities = Vec::new();
+            for token in 0..self.context.full_n_tokens(i) {
+                if self.context.full_get_token_id(i, token) < self.context.token_eot() {
+                    probabilities.push(self.context.full_get_token_prob(i, token));
+                }
+            }
+            let confidence = if probabilities.is_empty() {0.0} else {probabilities.iter().sum::<f32>() / probabilities.len() as f32};
+            segments.push(Segment {start_ms, end_ms, text, confidence});
+        }
+        Ok(segments)
+    }
+    // The times in the transcript match the recording so segments can be played back from it
+    pub fn transcribe_file(&mut self, audio_file: &Path) -> Result<Transcript, Error> {
+        let samples = super::load_whisper_samples(audio_file)?;
+        let mut transcript = Transcript::new(Some(audio_file.to_path_buf()));
+        for segment in self.transcribe_segments(&samples)? {
+            transcript.push(segment);
+        }
+        Ok(transcript)
+    }
+}
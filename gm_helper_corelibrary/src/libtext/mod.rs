@@ -1,168 +1,106 @@
-use cpal::traits::{DeviceTrait, HostTrait};
-use cpal::{FromSample, Sample};
 use hound::WavSpec;
-use std::fs::File;
-use std::io::BufWriter;
 use std::sync::{Arc, Mutex};
 use std::path::Path;
 use std::i16;
-use whisper_rs::{FullParams, SamplingStrategy, WhisperContext};
-use hound::{SampleFormat, WavReader};
+use anyhow::{anyhow, Error};
+
+mod archive;
+mod commands;
+mod devices;
+mod engine;
+#[cfg(feature = "audio-devices")]
+mod playback;
+mod resample;
+mod source;
+mod speakers;
+mod story;
+mod streaming;
+mod transcript;
+mod vad;
+pub use archive::*;
+pub use commands::*;
+pub use devices::*;
+pub use engine::*;
+#[cfg(feature = "audio-devices")]
+pub use playback::*;
+pub use resample::*;
+pub use source::*;
+pub use speakers::*;
+pub use story::*;
+pub use streaming::*;
+pub use transcript::*;
+pub use vad::*;
 
-// types
-type WavWriterHandle = Arc<Mutex<Option<hound::WavWriter<BufWriter<File>>>>>;
 // Recording of audio
+#[cfg(feature = "audio-devices")]
 pub fn record_audio(file_name: &str, recording_bool: Arc<Mutex<bool>>) -> Result<(), anyhow::Error> {
-    let host = cpal::default_host();
+    record_audio_streaming(file_name, recording_bool, None, None, None)
+}
 
-    // Set up the input device and stream with the default input config.
-    let device = host.default_input_device();
+// Records like record_audio while also sending the audio on for streaming transcription, from the
+// named input device or the default one, updating the level meter if there is one
+#[cfg(feature = "audio-devices")]
+pub fn record_audio_streaming(file_name: &str, recording_bool: Arc<Mutex<bool>>, transcription: Option<AudioSender>, device_name: Option<&str>, level: Option<InputLevel>) -> Result<(), anyhow::Error> {
+    let mut source = CpalSource::open(device_name)?;
+    record_from_source(&mut source, file_name, recording_bool, transcription, level)
+}
 
-    let config = device.as_ref()
-        .unwrap()
-        .default_input_config()
-        .expect("Failed to get default input config");
+// Records whatever the source hands on until it ends or recording_bool is set to false, so files
+// and generated audio go through the same recording as a microphone
+pub fn record_from_source(source: &mut dyn AudioSource, file_name: &str, recording_bool: Arc<Mutex<bool>>, transcription: Option<AudioSender>, level: Option<InputLevel>) -> Result<(), anyhow::Error> {
+    let (channels, sample_rate) = (source.channels(), source.sample_rate());
 
-    // The WAV file we're recording to.
+    // The WAV file we're recording to, 16 bit at the source's own rate and channels. It is
+    // resampled when transcribed.
     let spec = WavSpec {
-        channels: 1,
-        sample_rate: 44_100,
+        channels,
+        sample_rate,
         bits_per_sample: 16,
         sample_format: hound::SampleFormat::Int
     };
 
-    let writer = hound::WavWriter::create(file_name, spec)?;
-    let writer = Arc::new(Mutex::new(Some(writer)));
-
-    // A flag to indicate that recording is in progress.
-    println!("Begin recording...");
-
-    let err_fn = move |err| {
-        eprintln!("an error occurred on stream: {}", err);
-    };
+    let mut writer = hound::WavWriter::create(file_name, spec)?;
+    let mut write_error: Option<hound::Error> = None;
 
-    let writer_2 = writer.clone();
-
-    let thread_handle = std::thread::spawn(move || {
-        let stream = device.unwrap().build_input_stream(
-            &config.into(),
-            move |data, _: &_| write_input_data::<i16, i16>(data, &writer_2),
-            err_fn,
-            None,
-        )?;
-
-        loop {
-            let is_recording = *recording_bool.lock().unwrap();
-            if !is_recording {
-                break;
+    println!("Begin recording from {}...", source.get_description());
+    source.run(recording_bool, &mut |samples: &[f32]| {
+        for sample in samples.iter() {
+            if let Err(e) = writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16) {
+                write_error.get_or_insert(e);
             }
-            std::thread::sleep(std::time::Duration::from_millis(100));
         }
-
-        drop(stream);
-        writer.lock().unwrap().take().unwrap().finalize()?;
-        Ok::<(), anyhow::Error>(())
-    });
-
-    thread_handle.join().unwrap()?;
+        if let Some(level) = level.as_ref() {
+            level.update(samples, channels, sample_rate);
+        }
+        if let Some(sender) = transcription.as_ref() {
+            sender.send(AudioChunk {samples: samples.to_vec(), channels, sample_rate}).ok();
+        }
+    })?;
+    if let Some(e) = write_error {
+        return Err(anyhow!("Could not write the recording {}: {}", file_name, e));
+    }
+    writer.finalize()?;
     println!("Recording {} complete!", file_name);
 
     Ok(())
 }
 
-pub fn transcribe_audio_file(audio_file: &str) -> String {
-    let path_string = audio_file.to_string();
-    let path_len = &path_string.len();
-    let output_string = format!("{}_output.wav", &path_string[..path_len - 4]);
-
-    convert_sample_rate(&audio_file).expect("unable to convert .wav file"); 
-    
-    let audio_output_path = Path::new(&output_string);
-    let whisper_path = Path::new("whisper.cpp/models/ggml-base.en.bin");
-    let original_samples = parse_wav_file(audio_output_path);
-    let samples = whisper_rs::convert_integer_to_float_audio(&original_samples);
-
-    let mut ctx =
-        WhisperContext::new(&whisper_path.to_string_lossy()).expect("failed to open model");
-    let params = FullParams::new(SamplingStrategy::default());
-    ctx.full(params, &samples)
-        .expect("failed to convert samples");
-    let num_segments = ctx.full_n_segments();
-    let mut result_text: String = String::from("");
-    for i in 0..num_segments {
-        let segment = ctx.full_get_segment_text(i).expect("failed to get segment");
-        result_text += segment.as_str();
-    }
-    result_text
-}
-
-
-fn write_input_data<T, U>(input: &[T], writer: &WavWriterHandle)
-where
-    T: Sample,
-    U: Sample + hound::Sample + FromSample<T>,
-{
-    if let Ok(mut guard) = writer.try_lock() {
-        if let Some(writer) = guard.as_mut() {
-            for &sample in input.iter() {
-                let sample: U = U::from_sample(sample);
-                writer.write_sample(sample).ok();
-            }
-        }
-    }
+// Listens to the input device without recording, so the level meter shows whether the mic hears
+// the table. Blocks until listening is set to false.
+#[cfg(feature = "audio-devices")]
+pub fn monitor_input_level(device_name: Option<&str>, listening: Arc<Mutex<bool>>, level: InputLevel) -> Result<(), anyhow::Error> {
+    let mut source = CpalSource::open(device_name)?;
+    let (channels, sample_rate) = (source.channels(), source.sample_rate());
+    source.run(listening, &mut |samples: &[f32]| level.update(samples, channels, sample_rate))
 }
 
 // Transcription of audio
-fn parse_wav_file(path: &Path) -> Vec<i16> {
-    let reader = WavReader::open(path).expect("failed to read file");
-
-    if reader.spec().channels != 1 {
-        panic!("expected mono audio file");
-    }
-    if reader.spec().sample_format != SampleFormat::Int {
-        panic!("expected integer sample format");
+// Reads a recording of any rate and channel count as whisper's 16KHz mono samples
+fn load_whisper_samples(audio_file: &Path) -> Result<Vec<f32>, Error> {
+    let audio_file = &existing_audio_file(audio_file);
+    if !audio_file.is_file() {
+        return Err(anyhow!("No recording at {}", audio_file.display()));
     }
-    if reader.spec().sample_rate != 16000 {
-        panic!("expected 16KHz sample rate");
-    }
-    if reader.spec().bits_per_sample != 16 {
-        panic!("expected 16 bits per sample");
-    }
-
-    reader
-        .into_samples::<i16>()
-        .map(|x| x.expect("sample"))
-        .collect::<Vec<_>>()
+    let (samples, spec) = read_wav(audio_file)?;
+    Ok(to_whisper_samples(&samples, spec.channels, spec.sample_rate))
 }
-
-
-fn convert_sample_rate(file_name: &str) -> Result<(), hound::Error> {
-    // Open the input WAV file
-    let mut reader = hound::WavReader::open(file_name)?;
-    assert_eq!(reader.spec().channels, 1);
-    assert_eq!(reader.spec().sample_format, hound::SampleFormat::Int);
-
-    // Set up the output WAV file with the new sample rate store in test_wavs for now
-    let output_file_name = format!("whisper_tmp/{}_output.wav", Path::new(file_name).file_stem().unwrap().to_str().unwrap());
-    let spec = hound::WavSpec {
-        channels: 1,
-        sample_rate: 16000,
-        bits_per_sample: 16,
-        sample_format: hound::SampleFormat::Int,
-    };
-    let mut writer = hound::WavWriter::create(output_file_name, spec)?;
-
-    // Read samples from the input file and write them to the output file
-    // Convert to correct sampleby only writting every nth sample, where n
-    // is the ratio of the input and output sample rates
-    const N: usize = 5;
-    let ratio = reader.spec().sample_rate as f32 / writer.spec().sample_rate as f32;
-    for (i, sample) in reader.samples::<i16>().enumerate() {
-        if i % N == 0 {
-            let sample = sample.unwrap();
-            let resampled_sample = (sample as f32 * ratio) as i16;
-            writer.write_sample(resampled_sample).unwrap();
-        }
-    }
-    Ok(())
-}
\ No newline at end of file
@@ -0,0 +1,70 @@
+use super::{downmix, resample};
+use anyhow::{anyhow, Error};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Plays interleaved samples on the default output device until they run out or playing is set to
+// false. Recordings are speech so they are played in mono on every channel of the device.
+pub fn play_audio(samples: &[f32], channels: u16, sample_rate: u32, playing: Arc<Mutex<bool>>) -> Result<(), Error> {
+    let host = cpal::default_host();
+    let device = host.default_output_device()
+        .ok_or_else(|| anyhow!("No output device to play on"))?;
+    let config = device.default_output_config()?;
+    let audio = Arc::new(resample(&downmix(samples, channels), sample_rate, config.sample_rate().0));
+    let position = Arc::new(AtomicUsize::new(0));
+
+    let sample_format = config.sample_format();
+    let config: cpal::StreamConfig = config.into();
+    let stream = match sample_format {
+        cpal::SampleFormat::I8 => build_output_stream::<i8>(&device, &config, audio.clone(), position.clone())?,
+        cpal::SampleFormat::I16 => build_output_stream::<i16>(&device, &config, audio.clone(), position.clone())?,
+        cpal::SampleFormat::I32 => build_output_stream::<i32>(&device, &config, audio.clone(), position.clone())?,
+        cpal::SampleFormat::U8 => build_output_stream::<u8>(&device, &config, audio.clone(), position.clone())?,
+        cpal::SampleFormat::U16 => build_output_stream::<u16>(&device, &config, audio.clone(), position.clone())?,
+        cpal::SampleFormat::U32 => build_output_stream::<u32>(&device, &config, audio.clone(), position.clone())?,
+        cpal::SampleFormat::F32 => build_output_stream::<f32>(&device, &config, audio.clone(), position.clone())?,
+        cpal::SampleFormat::F64 => build_output_stream::<f64>(&device, &config, audio.clone(), position.clone())?,
+        format => return Err(anyhow!("Playing on {} devices is not supported", format)),
+    };
+    stream.play()?;
+
+    loop {
+        let is_playing = *playing.lock().unwrap();
+        if !is_playing || position.load(Ordering::Relaxed) >= audio.len() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    drop(stream);
+    *playing.lock().unwrap() = false;
+    Ok(())
+}
+
+fn build_output_stream<T>(device: &cpal::Device, config: &cpal::StreamConfig, audio: Arc<Vec<f32>>, position: Arc<AtomicUsize>) -> Result<cpal::Stream, Error>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let err_fn = move |err| {
+        eprintln!("an error occurred on stream: {}", err);
+    };
+    let channels = config.channels.max(1) as usize;
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &_| {
+            let mut index = position.load(Ordering::Relaxed);
+            for frame in data.chunks_mut(channels) {
+                let value = T::from_sample(audio.get(index).copied().unwrap_or(0.0));
+                for sample in frame.iter_mut() {
+                    *sample = value;
+                }
+                index += 1;
+            }
+            position.store(index, Ordering::Relaxed);
+        },
+        err_fn,
+        None,
+    )?;
+    Ok(stream)
+}
@@ -0,0 +1,172 @@
+use anyhow::{anyhow, Error};
+use hound::{SampleFormat, WavReader, WavSpec};
+use std::f64::consts::PI;
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+pub const WHISPER_SAMPLE_RATE: u32 = 16_000;
+
+// Sinc lobes on each side of a sample, more is a sharper filter and slower resampling
+const ZERO_CROSSINGS: usize = 16;
+// Kernel steps stored per input sample, values in between are interpolated
+const TABLE_RESOLUTION: usize = 128;
+// Where the low pass starts relative to the lower nyquist frequency, leaving room for the filter to roll off
+const ROLL_OFF: f64 = 0.94;
+
+// Any readable wav as samples between -1.0 and 1.0, still interleaved, with its spec. Gzipped
+// wavs from a compressed session archive are read the same way.
+pub fn read_wav(path: &Path) -> Result<(Vec<f32>, WavSpec), Error> {
+    read_wav_range(path, 0, u64::MAX)
+}
+
+// Transcripts and stories keep the path the audio was recorded to, once a session is compressed
+// the wav is gone and its gzipped copy sits next to it
+pub fn existing_audio_file(path: &Path) -> PathBuf {
+    let mut compressed = path.as_os_str().to_owned();
+    compressed.push(".gz");
+    let compressed = PathBuf::from(compressed);
+    if !path.is_file() && compressed.is_file() {compressed} else {path.to_path_buf()}
+}
+
+// The part of a wav from start_ms up to end_ms, e.g. what a transcript segment heard
+pub fn read_wav_range(path: &Path, start_ms: u64, end_ms: u64) -> Result<(Vec<f32>, WavSpec), Error> {
+    let path = &existing_audio_file(path);
+    let file = BufReader::new(File::open(path).map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?);
+    if path.extension().map_or(false, |e| e == "gz") {
+        // a gzip stream cannot seek, the samples before the start are read and skipped
+        let mut reader = WavReader::new(GzDecoder::new(file))
+            .map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+        let spec = reader.spec();
+        let (start, count) = sample_range(&spec, reader.duration(), start_ms, end_ms);
+        skip_samples(&mut reader, start as usize * spec.channels as usize)?;
+        return Ok((read_samples(&mut reader, count)?, spec));
+    }
+    let mut reader = WavReader::new(file)
+        .map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+    let spec = reader.spec();
+    let (start, count) = sample_range(&spec, reader.duration(), start_ms, end_ms);
+    reader.seek(start as u32)?;
+    Ok((read_samples(&mut reader, count)?, spec))
+}
+
+// The first frame and the number of interleaved samples between two times
+fn sample_range(spec: &WavSpec, frames: u32, start_ms: u64, end_ms: u64) -> (u64, usize) {
+    let frames = frames as u64;
+    let start = (start_ms.saturating_mul(spec.sample_rate as u64) / 1000).min(frames);
+    let end = (end_ms.saturating_mul(spec.sample_rate as u64) / 1000).min(frames);
+    (start, end.saturating_sub(start) as usize * spec.channels as usize)
+}
+
+fn skip_samples<R: Read>(reader: &mut WavReader<R>, count: usize) -> Result<(), Error> {
+    match reader.spec().sample_format {
+        SampleFormat::Float => for sample in reader.samples::<f32>().take(count) {
+            sample?;
+        },
+        SampleFormat::Int => for sample in reader.samples::<i32>().take(count) {
+            sample?;
+        },
+    }
+    Ok(())
+}
+
+fn read_samples<R: Read>(reader: &mut WavReader<R>, count: usize) -> Result<Vec<f32>, Error> {
+    let spec = reader.spec();
+    let samples = match spec.sample_format {
+        SampleFormat::Float => reader.samples::<f32>().take(count).collect::<Result<Vec<_>, _>>()?,
+        SampleFormat::Int => {
+            let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader.samples::<i32>()
+                .take(count)
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<Vec<_>, _>>()?
+        },
+    };
+    Ok(samples)
+}
+
+// Averages the channels of interleaved samples into one
+pub fn downmix(samples: &[f32], channels: u16) -> Vec<f32> {
+    if channels <= 1 {
+        return samples.to_vec();
+    }
+    samples.chunks(channels as usize)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+// Band limited resampling of mono samples with a blackman windowed sinc. Frequencies above the
+// lower of the two nyquist frequencies are filtered out instead of folding back as noise.
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || samples.is_empty() || from_rate == 0 || to_rate == 0 {
+        return samples.to_vec();
+    }
+    let ratio = to_rate as f64 / from_rate as f64;
+    // cutoff as a fraction of the input nyquist frequency, lowered when downsampling
+    let cutoff = ratio.min(1.0) * ROLL_OFF;
+    let half_width = ZERO_CROSSINGS as f64 / cutoff; // in input samples
+    let table = kernel_table(cutoff, half_width);
+
+    let output_len = (samples.len() as f64 * ratio).round() as usize;
+    let mut output = Vec::with_capacity(output_len);
+    for n in 0..output_len {
+        let time = n as f64 / ratio; // position in input samples
+        let first = (time - half_width).ceil().max(0.0) as usize;
+        let last = ((time + half_width).floor() as usize).min(samples.len() - 1);
+        let mut value = 0.0;
+        for k in first..=last {
+            value += samples[k] as f64 * lookup(&table, (time - k as f64).abs());
+        }
+        output.push(value as f32);
+    }
+    output
+}
+
+// Mono 16KHz samples as whisper expects them, from interleaved samples of any rate and channel count
+pub fn to_whisper_samples(samples: &[f32], channels: u16, sample_rate: u32) -> Vec<f32> {
+    resample(&downmix(samples, channels), sample_rate, WHISPER_SAMPLE_RATE)
+}
+
+// Writes a 16KHz mono 16 bit copy of any wav file
+pub fn convert_sample_rate(input: &Path, output: &Path) -> Result<(), Error> {
+    let (samples, spec) = read_wav(input)?;
+    let converted = to_whisper_samples(&samples, spec.channels, spec.sample_rate);
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: WHISPER_SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(output, spec)?;
+    for sample in converted.iter() {
+        writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+// The windowed sinc from 0 to half_width input samples away from the output sample
+fn kernel_table(cutoff: f64, half_width: f64) -> Vec<f64> {
+    let steps = (half_width * TABLE_RESOLUTION as f64).ceil() as usize + 2;
+    (0..steps).map(|i| {
+        let x = i as f64 / TABLE_RESOLUTION as f64;
+        if x > half_width {
+            return 0.0;
+        }
+        let sinc = if x == 0.0 {1.0} else {(PI * cutoff * x).sin() / (PI * cutoff * x)};
+        let phase = PI * (x / half_width + 1.0); // 0 to 2pi across the window
+        let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+        cutoff * sinc * window
+    }).collect()
+}
+
+fn lookup(table: &[f64], distance: f64) -> f64 {
+    let position = distance * TABLE_RESOLUTION as f64;
+    let index = position as usize;
+    if index + 1 >= table.len() {
+        return 0.0;
+    }
+    let fraction = position - index as f64;
+    table[index] + (table[index + 1] - table[index]) * fraction
+}
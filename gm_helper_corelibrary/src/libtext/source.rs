@@ -0,0 +1,326 @@
+use super::read_wav;
+#[cfg(feature = "audio-devices")]
+use super::find_input_device;
+use anyhow::{anyhow, Error};
+#[cfg(feature = "audio-devices")]
+use cpal::traits::{DeviceTrait, StreamTrait};
+#[cfg(feature = "audio-devices")]
+use cpal::{FromSample, Sample, SizedSample};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::f32::consts::PI;
+use std::path::Path;
+#[cfg(feature = "audio-devices")]
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const CHUNK_MS: u64 = 20; // audio handed on at a time by the sources that are not a device
+
+// Where recorded audio comes from. The source hands interleaved samples to the sink until it runs
+// out or running is set to false, so the same recording code works with a microphone, a file or
+// generated audio.
+pub trait AudioSource {
+    fn channels(&self) -> u16;
+    fn sample_rate(&self) -> u32;
+    fn get_description(&self) -> String;
+    fn run(&mut self, running: Arc<Mutex<bool>>, sink: &mut dyn FnMut(&[f32])) -> Result<(), Error>;
+}
+
+// How fast audio that is not live is handed on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pace {
+    RealTime,
+    Accelerated(f32), // times faster than real time
+    Unthrottled // as fast as the sink takes it
+}
+
+impl Pace {
+    fn speed(&self) -> Option<f32> {
+        match self {
+            Pace::RealTime => Some(1.0),
+            Pace::Accelerated(speed) => Some(speed.max(0.001)),
+            Pace::Unthrottled => None,
+        }
+    }
+}
+
+// An input device through cpal, at its default config
+#[cfg(feature = "audio-devices")]
+pub struct CpalSource {
+    device: cpal::Device,
+    config: cpal::SupportedStreamConfig,
+    name: String
+}
+
+#[cfg(feature = "audio-devices")]
+impl CpalSource {
+    // The named input device, or the default one for None
+    pub fn open(device_name: Option<&str>) -> Result<CpalSource, Error> {
+        let device = find_input_device(device_name)?;
+        let config = device.default_input_config()?;
+        let name = device.name().unwrap_or("unknown device".to_string());
+        Ok(CpalSource {device, config, name})
+    }
+}
+
+#[cfg(feature = "audio-devices")]
+impl AudioSource for CpalSource {
+    fn channels(&self) -> u16 {
+        self.config.channels()
+    }
+    fn sample_rate(&self) -> u32 {
+        self.config.sample_rate().0
+    }
+    fn get_description(&self) -> String {
+        format!("{}, {} channels at {}Hz", self.name, self.channels(), self.sample_rate())
+    }
+    fn run(&mut self, running: Arc<Mutex<bool>>, sink: &mut dyn FnMut(&[f32])) -> Result<(), Error> {
+        // the device calls back on its own thread, the audio is handed to the sink on this one
+        let (sender, chunks) = channel::<Vec<f32>>();
+        let config: cpal::StreamConfig = self.config.clone().into();
+        let stream = match self.config.sample_format() {
+            cpal::SampleFormat::I8 => build_input_stream::<i8>(&self.device, &config, sender)?,
+            cpal::SampleFormat::I16 => build_input_stream::<i16>(&self.device, &config, sender)?,
+            cpal::SampleFormat::I32 => build_input_stream::<i32>(&self.device, &config, sender)?,
+            cpal::SampleFormat::U8 => build_input_stream::<u8>(&self.device, &config, sender)?,
+            cpal::SampleFormat::U16 => build_input_stream::<u16>(&self.device, &config, sender)?,
+            cpal::SampleFormat::U32 => build_input_stream::<u32>(&self.device, &config, sender)?,
+            cpal::SampleFormat::F32 => build_input_stream::<f32>(&self.device, &config, sender)?,
+            cpal::SampleFormat::F64 => build_input_stream::<f64>(&self.device, &config, sender)?,
+            format => return Err(anyhow!("Recording from {} devices is not supported", format)),
+        };
+        stream.play()?;
+        while *running.lock().unwrap() {
+            match chunks.recv_timeout(Duration::from_millis(100)) {
+                Ok(chunk) => sink(&chunk),
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return Err(anyhow!("{} stopped sending audio", self.name)),
+            }
+        }
+        drop(stream);
+        // what the device sent before it stopped
+        for chunk in chunks.try_iter() {
+            sink(&chunk);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "audio-devices")]
+fn build_input_stream<T>(device: &cpal::Device, config: &cpal::StreamConfig, sender: Sender<Vec<f32>>) -> Result<cpal::Stream, Error>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let err_fn = move |err| {
+        eprintln!("an error occurred on stream: {}", err);
+    };
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &_| {
+            sender.send(data.iter().map(|s| f32::from_sample(*s)).collect()).ok();
+        },
+        err_fn,
+        None,
+    )?;
+    Ok(stream)
+}
+
+// Recorded audio played back as if it was heard live
+pub struct WavSource {
+    samples: Vec<f32>,
+    channels: u16,
+    sample_rate: u32,
+    pace: Pace,
+    name: String
+}
+
+impl WavSource {
+    pub fn open(path: &Path, pace: Pace) -> Result<WavSource, Error> {
+        let (samples, spec) = read_wav(path)?;
+        let name = path.display().to_string();
+        Ok(WavSource {samples, channels: spec.channels, sample_rate: spec.sample_rate, pace, name})
+    }
+    pub fn from_samples(samples: Vec<f32>, channels: u16, sample_rate: u32, pace: Pace) -> Result<WavSource, Error> {
+        if channels == 0 || sample_rate == 0 {
+            return Err(anyhow!("Audio needs at least one channel and a sample rate"));
+        }
+        Ok(WavSource {samples, channels, sample_rate, pace, name: "samples".to_string()})
+    }
+}
+
+impl AudioSource for WavSource {
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    fn get_description(&self) -> String {
+        format!("{}, {} channels at {}Hz", self.name, self.channels, self.sample_rate)
+    }
+    fn run(&mut self, running: Arc<Mutex<bool>>, sink: &mut dyn FnMut(&[f32])) -> Result<(), Error> {
+        hand_on_paced(&self.samples, self.channels, self.sample_rate, self.pace, running, sink)
+    }
+}
+
+// A part of generated audio
+#[derive(Clone, Copy, Debug)]
+pub enum Signal {
+    Silence,
+    Tone {frequency: f32, amplitude: f32},
+    Noise {amplitude: f32} // white noise, the same every run
+}
+
+// Audio made up of signals one after another, e.g. tones standing in for speech between silences
+pub struct SyntheticSource {
+    parts: Vec<(Signal, u64)>, // each signal with how many ms it lasts
+    channels: u16,
+    sample_rate: u32,
+    pace: Pace
+}
+
+impl SyntheticSource {
+    pub fn new(parts: Vec<(Signal, u64)>, channels: u16, sample_rate: u32, pace: Pace) -> Result<SyntheticSource, Error> {
+        if channels == 0 || sample_rate == 0 {
+            return Err(anyhow!("Audio needs at least one channel and a sample rate"));
+        }
+        Ok(SyntheticSource {parts, channels, sample_rate, pace})
+    }
+    pub fn duration_ms(&self) -> u64 {
+        self.parts.iter().map(|(_, ms)| ms).sum()
+    }
+    // All of the audio at once, the same as running the source
+    pub fn generate(&self) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut samples = Vec::new();
+        for (signal, ms) in self.parts.iter() {
+            let frames = (*ms * self.sample_rate as u64 / 1000) as usize;
+            for frame in 0..frames {
+                let value = match signal {
+                    Signal::Silence => 0.0,
+                    Signal::Tone {frequency, amplitude} => amplitude * (2.0 * PI * frequency * frame as f32 / self.sample_rate as f32).sin(),
+                    Signal::Noise {amplitude} => amplitude * rng.gen_range(-1.0..=1.0),
+                };
+                samples.extend(std::iter::repeat(value).take(self.channels as usize));
+            }
+        }
+        samples
+    }
+}
+
+impl AudioSource for SyntheticSource {
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    fn get_description(&self) -> String {
+        format!("{} generated signals over {}ms, {} channels at {}Hz", self.parts.len(), self.duration_ms(), self.channels, self.sample_rate)
+    }
+    fn run(&mut self, running: Arc<Mutex<bool>>, sink: &mut dyn FnMut(&[f32])) -> Result<(), Error> {
+        hand_on_paced(&self.generate(), self.channels, self.sample_rate, self.pace, running, sink)
+    }
+}
+
+// Hands the audio to the sink a chunk at a time no faster than the pace, measured from the start
+// so waiting never drifts
+fn hand_on_paced(samples: &[f32], channels: u16, sample_rate: u32, pace: Pace, running: Arc<Mutex<bool>>, sink: &mut dyn FnMut(&[f32])) -> Result<(), Error> {
+    let frames_per_chunk = ((CHUNK_MS * sample_rate as u64 / 1000) as usize).max(1);
+    let started = Instant::now();
+    let mut frames_sent: u64 = 0;
+    for chunk in samples.chunks(frames_per_chunk * channels as usize) {
+        if !*running.lock().unwrap() {
+            break;
+        }
+        sink(chunk);
+        frames_sent += (chunk.len() / channels as usize) as u64;
+        if let Some(speed) = pace.speed() {
+            let due = Duration::from_secs_f64(frames_sent as f64 / sample_rate as f64 / speed as f64);
+            if let Some(wait) = due.checked_sub(started.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libtext::{record_from_source, to_whisper_samples, VadConfig, WHISPER_SAMPLE_RATE};
+    use std::path::PathBuf;
+
+    fn temporary_wav(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("gm_helper_{}_{}.wav", name, std::process::id()))
+    }
+
+    fn collect(source: &mut dyn AudioSource) -> Vec<f32> {
+        let mut samples = Vec::new();
+        source.run(Arc::new(Mutex::new(true)), &mut |chunk| samples.extend_from_slice(chunk)).unwrap();
+        samples
+    }
+
+    #[test]
+    fn synthetic_speech_is_recorded_and_detected() {
+        let parts = vec![
+            (Signal::Silence, 1000),
+            (Signal::Tone {frequency: 220.0, amplitude: 0.5}, 1500),
+            (Signal::Noise {amplitude: 0.001}, 1000),
+            (Signal::Tone {frequency: 300.0, amplitude: 0.4}, 800),
+            (Signal::Silence, 700)
+        ];
+        let mut source = SyntheticSource::new(parts, 2, 44100, Pace::Unthrottled).unwrap();
+        let path = temporary_wav("synthetic");
+        let (sender, chunks) = std::sync::mpsc::channel();
+        record_from_source(&mut source, &path.to_string_lossy(), Arc::new(Mutex::new(true)), Some(sender), None).unwrap();
+        assert!(chunks.try_iter().count() > 0);
+
+        let (samples, spec) = read_wav(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!((spec.channels, spec.sample_rate), (2, 44100));
+        assert_eq!(samples.len(), source.generate().len());
+
+        let speech = VadConfig::default().detect(&to_whisper_samples(&samples, spec.channels, spec.sample_rate), WHISPER_SAMPLE_RATE);
+        assert_eq!(speech.len(), 2);
+        // the padding around each utterance reaches a little into the silence
+        let first = (speech[0].start_ms(WHISPER_SAMPLE_RATE), speech[0].end_ms(WHISPER_SAMPLE_RATE));
+        let second = (speech[1].start_ms(WHISPER_SAMPLE_RATE), speech[1].end_ms(WHISPER_SAMPLE_RATE));
+        assert!(first.0 >= 500 && first.0 <= 1000 && first.1 >= 2500 && first.1 <= 3000, "first utterance at {:?}", first);
+        assert!(second.0 >= 3000 && second.0 <= 3500 && second.1 >= 4300 && second.1 <= 4800, "second utterance at {:?}", second);
+    }
+
+    #[test]
+    fn wav_source_plays_back_what_was_recorded() {
+        let tone = SyntheticSource::new(vec![(Signal::Tone {frequency: 440.0, amplitude: 0.8}, 250)], 1, 16000, Pace::Unthrottled).unwrap();
+        let samples = tone.generate();
+        let mut source = WavSource::from_samples(samples.clone(), 1, 16000, Pace::Unthrottled).unwrap();
+        let path = temporary_wav("round_trip");
+        record_from_source(&mut source, &path.to_string_lossy(), Arc::new(Mutex::new(true)), None, None).unwrap();
+
+        let mut played = WavSource::open(&path, Pace::Unthrottled).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!((played.channels(), played.sample_rate()), (1, 16000));
+        let heard = collect(&mut played);
+        assert_eq!(heard.len(), samples.len());
+        // 16 bit samples are off by no more than a step
+        assert!(heard.iter().zip(samples.iter()).all(|(a, b)| (a - b).abs() <= 2.0 / i16::MAX as f32));
+    }
+
+    #[test]
+    fn stopping_ends_a_paced_source() {
+        let mut source = SyntheticSource::new(vec![(Signal::Silence, 10_000)], 1, 16000, Pace::RealTime).unwrap();
+        let running = Arc::new(Mutex::new(true));
+        let stopper = running.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            *stopper.lock().unwrap() = false;
+        });
+        let started = Instant::now();
+        let mut frames = 0;
+        source.run(running, &mut |chunk| frames += chunk.len()).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(frames < 16000);
+    }
+}
@@ -0,0 +1,155 @@
+use super::{read_wav, to_whisper_samples, WHISPER_SAMPLE_RATE};
+use anyhow::{anyhow, Error};
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+
+pub const DEFAULT_SPEAKERS_FILE: &str = "./speakers.json";
+const FRAME_MS: usize = 40;
+const HOP_MS: usize = 20;
+const VOICED_LEVEL: f32 = 0.01; // quieter frames are left out of the features
+const MIN_PITCH_HZ: f32 = 70.0;
+const MAX_PITCH_HZ: f32 = 400.0;
+const MIN_PERIODICITY: f32 = 0.3; // how alike a frame has to be to itself one period later to have a pitch
+
+// What a voice sounds like, in a few numbers that tell the people at one table apart well enough.
+// Not a real speaker model, just enough to label who was talking in a transcript.
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct VoiceProfile {
+    pub speaker: String,
+    pub pitch_hz: f32, // median pitch of the voiced frames
+    pub zero_crossing_rate: f32, // crossings per sample, higher for hissing and breathy voices
+    pub brightness: f32 // how much of the energy is in the highs, 0.0 to 2.0
+}
+
+impl VoiceProfile {
+    // Mono samples of the speaker talking, a few seconds are enough
+    pub fn enroll(speaker: &str, samples: &[f32], sample_rate: u32) -> Result<VoiceProfile, Error> {
+        if speaker.trim().is_empty() {
+            return Err(anyhow!("A voice needs the name of its speaker"));
+        }
+        let (pitch_hz, zero_crossing_rate, brightness) = voice_features(samples, sample_rate)
+            .ok_or_else(|| anyhow!("Could not hear a voice to enroll {}", speaker))?;
+        Ok(VoiceProfile {speaker: speaker.trim().to_string(), pitch_hz, zero_crossing_rate, brightness})
+    }
+    pub fn enroll_file(speaker: &str, audio_file: &Path) -> Result<VoiceProfile, Error> {
+        let (samples, spec) = read_wav(audio_file)?;
+        VoiceProfile::enroll(speaker, &to_whisper_samples(&samples, spec.channels, spec.sample_rate), WHISPER_SAMPLE_RATE)
+    }
+    pub fn get_description(&self) -> String {
+        format!("{} ({:.0}Hz)", self.speaker, self.pitch_hz)
+    }
+    // Pitch counts in octaves, an octave apart is as far as the other features get
+    pub fn distance(&self, other: &VoiceProfile) -> f32 {
+        let pitch = (self.pitch_hz / other.pitch_hz).log2().abs();
+        let zero_crossings = (self.zero_crossing_rate - other.zero_crossing_rate).abs() * 10.0;
+        let brightness = (self.brightness - other.brightness).abs();
+        pitch + zero_crossings * 0.5 + brightness * 0.5
+    }
+}
+
+// The enrolled voices, kept between runs
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct SpeakerEnrollment {
+    pub profiles: Vec<VoiceProfile>, // a speaker can have several, the closest one counts
+    pub max_distance: f32 // voices further from every profile stay unlabeled
+}
+
+impl Default for SpeakerEnrollment {
+    fn default() -> Self {
+        SpeakerEnrollment {profiles: Vec::new(), max_distance: 0.5}
+    }
+}
+
+impl SpeakerEnrollment {
+    pub fn load(path: &Path) -> Result<SpeakerEnrollment, Error> {
+        if !path.is_file() {
+            return Ok(SpeakerEnrollment::default());
+        }
+        let json_string = std::fs::read_to_string(path)?;
+        serde_json::from_str(&json_string)
+            .map_err(|e| anyhow!("Could not read the enrolled speakers {}: {}", path.display(), e))
+    }
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+    pub fn enroll(&mut self, profile: VoiceProfile) {
+        self.profiles.push(profile);
+    }
+    pub fn remove_speaker(&mut self, speaker: &str) {
+        self.profiles.retain(|p| p.speaker != speaker);
+    }
+    pub fn speakers(&self) -> Vec<String> {
+        let mut speakers: Vec<String> = Vec::new();
+        for profile in self.profiles.iter() {
+            if !speakers.contains(&profile.speaker) {
+                speakers.push(profile.speaker.clone());
+            }
+        }
+        speakers
+    }
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+    // The enrolled speaker who sounds most like the mono samples, if anyone does
+    pub fn identify(&self, samples: &[f32], sample_rate: u32) -> Option<String> {
+        let heard = VoiceProfile::enroll("heard", samples, sample_rate).ok()?;
+        self.profiles.iter()
+            .map(|profile| (profile, profile.distance(&heard)))
+            .filter(|(_, distance)| *distance <= self.max_distance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(profile, _)| profile.speaker.clone())
+    }
+}
+
+// Median pitch, mean zero crossing rate and brightness over the frames loud enough to be a voice
+fn voice_features(samples: &[f32], sample_rate: u32) -> Option<(f32, f32, f32)> {
+    let frame_len = sample_rate as usize * FRAME_MS / 1000;
+    let hop = (sample_rate as usize * HOP_MS / 1000).max(1);
+    let min_lag = (sample_rate as f32 / MAX_PITCH_HZ) as usize;
+    let max_lag = ((sample_rate as f32 / MIN_PITCH_HZ) as usize).min(frame_len.saturating_sub(1));
+    if frame_len == 0 || samples.len() < frame_len || min_lag == 0 || min_lag >= max_lag {
+        return None;
+    }
+    let mut pitches: Vec<f32> = Vec::new();
+    let (mut zero_crossings, mut brightness, mut voiced) = (0.0, 0.0, 0);
+    let mut start = 0;
+    while start + frame_len <= samples.len() {
+        let frame = &samples[start..start + frame_len];
+        start += hop;
+        let energy: f32 = frame.iter().map(|s| s * s).sum();
+        if (energy / frame_len as f32).sqrt() < VOICED_LEVEL {
+            continue;
+        }
+        voiced += 1;
+        zero_crossings += frame.windows(2).filter(|w| (w[0] >= 0.0) != (w[1] >= 0.0)).count() as f32 / frame_len as f32;
+        let difference: f32 = frame.windows(2).map(|w| (w[1] - w[0]).powi(2)).sum();
+        brightness += (difference / energy).sqrt();
+        // one period of the pitch is the shortest lag where the frame is about as much like itself
+        // as it gets, longer lags would find the same pitch an octave lower
+        let periodicity: Vec<f32> = (min_lag..=max_lag)
+            .map(|lag| {
+                let overlap = &frame[..frame_len - lag];
+                let shifted = &frame[lag..];
+                let product: f32 = overlap.iter().zip(shifted.iter()).map(|(a, b)| a * b).sum();
+                let norm = (overlap.iter().map(|s| s * s).sum::<f32>() * shifted.iter().map(|s| s * s).sum::<f32>()).sqrt();
+                if norm > 0.0 {product / norm} else {0.0}
+            })
+            .collect();
+        // short lags are alike only because the wave has not changed much yet, skip to where it comes back
+        let rising = periodicity.windows(2).position(|w| w[1] > w[0]).unwrap_or(periodicity.len());
+        let most = periodicity[rising..].iter().cloned().fold(0.0f32, f32::max);
+        if most >= MIN_PERIODICITY {
+            if let Some(offset) = periodicity[rising..].iter().position(|p| *p >= most * 0.9) {
+                pitches.push(sample_rate as f32 / (min_lag + rising + offset) as f32);
+            }
+        }
+    }
+    if voiced == 0 || pitches.is_empty() {
+        return None;
+    }
+    pitches.sort_by(|a, b| a.total_cmp(b));
+    Some((pitches[pitches.len() / 2], zero_crossings / voiced as f32, brightness / voiced as f32))
+}
@@ -0,0 +1,146 @@
+use super::{Transcript, AudioSpan, SpeakerEnrollment, load_whisper_samples, clock, WHISPER_SAMPLE_RATE};
+use crate::entities::Story;
+use anyhow::{anyhow, Error};
+
+// A line of a scene, with who said it when the voices are enrolled
+#[derive(Clone, Debug)]
+pub struct SceneLine {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub speaker: Option<String>,
+    pub text: String
+}
+
+impl SceneLine {
+    pub fn get_description(&self) -> String {
+        match &self.speaker {
+            Some(speaker) => format!("[{}] {}: {}", clock(self.start_ms), speaker, self.text),
+            None => format!("[{}] {}", clock(self.start_ms), self.text),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TranscriptScene {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub lines: Vec<SceneLine>
+}
+
+// Splits a transcript into scenes where the table went quiet for a while or someone said one of the
+// scene markers, each scene becomes a Story that keeps where it is in the recording
+#[derive(Clone, Debug)]
+pub struct StorySplitter {
+    pub min_pause_ms: u64,
+    pub scene_markers: Vec<String>, // said out loud to start a new scene, dropped from the text
+    pub label: String // scenes are labeled "Scene 1", "Scene 2"...
+}
+
+impl Default for StorySplitter {
+    fn default() -> Self {
+        StorySplitter {
+            min_pause_ms: 8_000,
+            scene_markers: vec!["new scene".to_string(), "next scene".to_string(), "scene change".to_string()],
+            label: "Scene".to_string()
+        }
+    }
+}
+
+impl StorySplitter {
+    // Labels each line with the enrolled speaker it sounds like when there is audio to listen to.
+    // The recording is read once and each line is cut from it, a compressed one can only be read
+    // from the start.
+    pub fn scenes(&self, transcript: &Transcript, speakers: Option<&SpeakerEnrollment>) -> Result<Vec<TranscriptScene>, Error> {
+        let speakers = speakers.filter(|s| !s.is_empty());
+        let audio = match (speakers, transcript.audio_file.as_ref()) {
+            (Some(_), Some(audio_file)) => Some(load_whisper_samples(audio_file)
+                .map_err(|e| anyhow!("Could not listen for speakers: {}", e))?),
+            _ => None,
+        };
+        let to_sample = |ms: u64| (ms * WHISPER_SAMPLE_RATE as u64 / 1000) as usize;
+        let mut scenes: Vec<TranscriptScene> = Vec::new();
+        let mut current: Vec<SceneLine> = Vec::new();
+        let mut last_end: Option<u64> = None;
+        for segment in transcript.segments.iter() {
+            if last_end.map_or(false, |end| segment.start_ms.saturating_sub(end) >= self.min_pause_ms) {
+                push_scene(&mut scenes, &mut current);
+            }
+            last_end = Some(segment.end_ms);
+            let speaker = speakers.zip(audio.as_ref()).and_then(|(speakers, audio)| {
+                let start = to_sample(segment.start_ms).min(audio.len());
+                let end = to_sample(segment.end_ms).clamp(start, audio.len());
+                speakers.identify(&audio[start..end], WHISPER_SAMPLE_RATE)
+            });
+            let parts = self.split_on_markers(&segment.text);
+            for (part_index, part) in parts.iter().enumerate() {
+                if part_index > 0 {
+                    push_scene(&mut scenes, &mut current);
+                }
+                if !part.is_empty() {
+                    current.push(SceneLine {start_ms: segment.start_ms, end_ms: segment.end_ms, speaker: speaker.clone(), text: part.clone()});
+                }
+            }
+        }
+        push_scene(&mut scenes, &mut current);
+        Ok(scenes)
+    }
+    // Stories numbered from first_id, in the order the scenes were played
+    pub fn to_stories(&self, transcript: &Transcript, speakers: Option<&SpeakerEnrollment>, first_id: u32) -> Result<Vec<Story>, Error> {
+        let scenes = self.scenes(transcript, speakers)?;
+        if scenes.is_empty() {
+            return Err(anyhow!("Nothing was transcribed to make stories from"));
+        }
+        let mut stories = Vec::new();
+        for (index, scene) in scenes.iter().enumerate() {
+            let id = first_id + index as u32;
+            let label = format!("{} {} ({} - {})", self.label, index + 1, clock(scene.start_ms), clock(scene.end_ms));
+            let lines: Vec<String> = scene.lines.iter().map(|line| line.get_description()).collect();
+            let mut story = Story::new(id, id, &label, &lines.join("\n"))?;
+            story.edit.set(false);
+            story.audio = transcript.audio_file.as_ref().map(|audio_file| AudioSpan {
+                audio_file: audio_file.clone(),
+                start_ms: scene.start_ms,
+                end_ms: scene.end_ms
+            });
+            stories.push(story);
+        }
+        Ok(stories)
+    }
+    // The text before and after each marker, markers are matched by their words so punctuation and
+    // case do not matter
+    fn split_on_markers(&self, text: &str) -> Vec<String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let normalized: Vec<String> = words.iter()
+            .map(|w| w.chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_lowercase())
+            .collect();
+        let markers: Vec<Vec<String>> = self.scene_markers.iter()
+            .map(|m| m.split_whitespace().map(|w| w.to_lowercase()).collect::<Vec<String>>())
+            .filter(|m| !m.is_empty())
+            .collect();
+        let mut parts = Vec::new();
+        let mut part_start = 0;
+        let mut index = 0;
+        while index < words.len() {
+            match markers.iter().find(|m| normalized[index..].starts_with(m)) {
+                Some(marker) => {
+                    parts.push(words[part_start..index].join(" "));
+                    index += marker.len();
+                    part_start = index;
+                },
+                None => index += 1,
+            }
+        }
+        parts.push(words[part_start..].join(" "));
+        parts.into_iter().map(|p| p.trim_matches(|c: char| c.is_whitespace() || c == ',').to_string()).collect()
+    }
+}
+
+fn push_scene(scenes: &mut Vec<TranscriptScene>, lines: &mut Vec<SceneLine>) {
+    if lines.is_empty() {
+        return;
+    }
+    let lines = std::mem::take(lines);
+    let start_ms = lines.iter().map(|l| l.start_ms).min().unwrap_or(0);
+    let end_ms = lines.iter().map(|l| l.end_ms).max().unwrap_or(0);
+    scenes.push(TranscriptScene {start_ms, end_ms, lines});
+}
@@ -0,0 +1,200 @@
+use super::{TranscriptionEngine, EngineConfig, Segment, downmix, resample, WHISPER_SAMPLE_RATE};
+use anyhow::{anyhow, Error};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread::JoinHandle;
+
+// Audio as it comes from the input device, interleaved
+pub struct AudioChunk {
+    pub samples: Vec<f32>,
+    pub channels: u16,
+    pub sample_rate: u32
+}
+
+pub type AudioSender = Sender<AudioChunk>;
+
+pub enum StreamEvent {
+    Partial(Segment), // the text heard so far in the open window, replaced by the next partial
+    Final(Segment), // text that will not change anymore
+    Error(String),
+    Finished // all audio is transcribed, the engine can be taken back
+}
+
+// Windows are transcribed every step while they fill up. A full window commits the segments
+// that start before the overlap, the audio after the last committed segment opens the next
+// window so words cut off at the edge are heard again in full.
+#[derive(Clone, Copy, Debug)]
+pub struct StreamConfig {
+    pub window_ms: u64,
+    pub overlap_ms: u64,
+    pub step_ms: u64
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        StreamConfig {window_ms: 10_000, overlap_ms: 2_000, step_ms: 2_000}
+    }
+}
+
+impl StreamConfig {
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.step_ms == 0 || self.window_ms == 0 {
+            return Err(anyhow!("Streaming needs a window and step longer than zero"));
+        }
+        if self.overlap_ms >= self.window_ms {
+            return Err(anyhow!("An overlap of {}ms leaves nothing of a {}ms window to commit", self.overlap_ms, self.window_ms));
+        }
+        Ok(())
+    }
+}
+
+// Transcribes audio on a background thread while it is being recorded
+pub struct StreamingTranscriber {
+    input: Option<AudioSender>,
+    events: Receiver<StreamEvent>,
+    handle: Option<JoinHandle<Option<TranscriptionEngine>>>
+}
+
+impl StreamingTranscriber {
+    // Uses the engine if one is loaded, otherwise loads one from the config on the background thread
+    pub fn start(engine: Option<TranscriptionEngine>, engine_config: EngineConfig, config: StreamConfig) -> Result<StreamingTranscriber, Error> {
+        config.validate()?;
+        let (input, chunks) = channel::<AudioChunk>();
+        let (events, receiver) = channel::<StreamEvent>();
+        let handle = std::thread::spawn(move || {
+            let mut engine = match engine {
+                Some(engine) => engine,
+                None => match TranscriptionEngine::new(engine_config) {
+                    Ok(engine) => engine,
+                    Err(e) => {
+                        events.send(StreamEvent::Error(e.to_string())).ok();
+                        events.send(StreamEvent::Finished).ok();
+                        return None;
+                    }
+                },
+            };
+            stream_chunks(&mut engine, &config, &chunks, &events);
+            events.send(StreamEvent::Finished).ok();
+            Some(engine)
+        });
+        Ok(StreamingTranscriber {input: Some(input), events: receiver, handle: Some(handle)})
+    }
+    // The sender for the recording to push audio through. It is handed out only once so transcription
+    // ends when the recording drops it, also when the recording fails.
+    pub fn take_input(&mut self) -> Option<AudioSender> {
+        self.input.take()
+    }
+    // Stops taking new audio, what was already sent is still transcribed
+    pub fn stop(&mut self) {
+        self.input = None;
+    }
+    // Never blocks, returns the events published since the last poll
+    pub fn poll(&self) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        loop {
+            match self.events.try_recv() {
+                Ok(event) => events.push(event),
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+            }
+        }
+        events
+    }
+    // Waits for the background thread and hands back its engine, call after the Finished event
+    // to avoid blocking
+    pub fn into_engine(mut self) -> Option<TranscriptionEngine> {
+        self.input = None;
+        self.handle.take().and_then(|handle| handle.join().ok()).flatten()
+    }
+}
+
+fn stream_chunks(engine: &mut TranscriptionEngine, config: &StreamConfig, chunks: &Receiver<AudioChunk>, events: &Sender<StreamEvent>) {
+    let mut window: Vec<f32> = Vec::new(); // mono at the input rate
+    let mut window_start_ms: u64 = 0; // where the window starts in the whole recording
+    let mut sample_rate: u32 = 0;
+    let mut unheard: usize = 0; // samples added since the last transcription
+    while let Ok(chunk) = chunks.recv() {
+        // catch up on everything that queued while whisper was busy
+        let mut pending = vec![chunk];
+        loop {
+            match chunks.try_recv() {
+                Ok(chunk) => pending.push(chunk),
+                Err(_) => break,
+            }
+        }
+        for chunk in pending {
+            if sample_rate == 0 {
+                sample_rate = chunk.sample_rate;
+            }
+            if chunk.sample_rate != sample_rate {
+                events.send(StreamEvent::Error(format!("Audio changed from {}Hz to {}Hz while streaming", sample_rate, chunk.sample_rate))).ok();
+                continue;
+            }
+            let mono = downmix(&chunk.samples, chunk.channels);
+            unheard += mono.len();
+            window.extend(mono);
+        }
+        if sample_rate == 0 {
+            continue;
+        }
+        let to_samples = |ms: u64| (ms * sample_rate as u64 / 1000) as usize;
+        while window.len() >= to_samples(config.window_ms) {
+            let full = window[..to_samples(config.window_ms)].to_vec();
+            match transcribe_window(engine, &full, sample_rate) {
+                Ok(segments) => {
+                    let commit_ms = config.window_ms - config.overlap_ms;
+                    let committed: Vec<Segment> = segments.into_iter().filter(|s| s.start_ms < commit_ms).collect();
+                    let cut_ms = committed.last()
+                        .map(|s| s.end_ms.min(config.window_ms))
+                        .filter(|end| *end > 0)
+                        .unwrap_or(commit_ms);
+                    for segment in committed {
+                        events.send(StreamEvent::Final(offset(segment, window_start_ms))).ok();
+                    }
+                    window.drain(..to_samples(cut_ms).min(window.len()));
+                    window_start_ms += cut_ms;
+                },
+                Err(e) => {
+                    // skip the window rather than failing on it again
+                    events.send(StreamEvent::Error(e.to_string())).ok();
+                    window.drain(..to_samples(config.window_ms - config.overlap_ms));
+                    window_start_ms += config.window_ms - config.overlap_ms;
+                },
+            }
+            unheard = window.len();
+        }
+        if unheard >= to_samples(config.step_ms) && !window.is_empty() {
+            match transcribe_window(engine, &window, sample_rate) {
+                Ok(segments) => {
+                    let end_ms = window_start_ms + (window.len() as u64 * 1000 / sample_rate as u64);
+                    let text: String = segments.iter().map(|s| s.text.as_str()).collect();
+                    let confidence = segments.iter().map(|s| s.confidence).sum::<f32>() / segments.len().max(1) as f32;
+                    events.send(StreamEvent::Partial(Segment {start_ms: window_start_ms, end_ms, text, confidence})).ok();
+                },
+                Err(e) => {
+                    events.send(StreamEvent::Error(e.to_string())).ok();
+                },
+            }
+            unheard = 0;
+        }
+    }
+    // the recording stopped, whatever is left in the window is final
+    if sample_rate > 0 && !window.is_empty() {
+        match transcribe_window(engine, &window, sample_rate) {
+            Ok(segments) => {
+                for segment in segments {
+                    events.send(StreamEvent::Final(offset(segment, window_start_ms))).ok();
+                }
+            },
+            Err(e) => {
+                events.send(StreamEvent::Error(e.to_string())).ok();
+            },
+        }
+    }
+}
+
+fn transcribe_window(engine: &mut TranscriptionEngine, window: &[f32], sample_rate: u32) -> Result<Vec<Segment>, Error> {
+    engine.transcribe_segments(&resample(window, sample_rate, WHISPER_SAMPLE_RATE))
+}
+
+fn offset(segment: Segment, start_ms: u64) -> Segment {
+    Segment {start_ms: segment.start_ms + start_ms, end_ms: segment.end_ms + start_ms, ..segment}
+}
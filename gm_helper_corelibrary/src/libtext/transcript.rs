@@ -0,0 +1,145 @@
+use super::read_wav_range;
+use crate::entities::TtrpgEntity;
+use anyhow::{anyhow, Error};
+use hound::WavSpec;
+use serde::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// A stretch of transcribed speech, times are from the start of the transcribed audio
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+    #[serde(default)]
+    pub confidence: f32 // average probability whisper gave the words, 0.0 to 1.0
+}
+
+impl Segment {
+    pub fn get_description(&self) -> String {
+        format!("[{}] {}", clock(self.start_ms), self.text.trim())
+    }
+}
+
+// Transcribed segments in the order they were spoken, along with the recording they came from
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, Default)]
+pub struct Transcript {
+    pub created: u64,
+    pub audio_file: Option<PathBuf>,
+    pub segments: Vec<Segment>
+}
+
+impl Transcript {
+    pub fn new(audio_file: Option<PathBuf>) -> Transcript {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Transcript {created, audio_file, segments: Vec::new()}
+    }
+    // Segments arriving out of order are put in their place
+    pub fn push(&mut self, segment: Segment) {
+        let index = self.segments.iter().position(|s| s.start_ms > segment.start_ms).unwrap_or(self.segments.len());
+        self.segments.insert(index, segment);
+    }
+    pub fn is_empty(&self) -> bool {
+        self.segments.iter().all(|s| s.text.trim().is_empty())
+    }
+    pub fn duration_ms(&self) -> u64 {
+        self.segments.iter().map(|s| s.end_ms).max().unwrap_or(0)
+    }
+    pub fn get_description(&self) -> String {
+        let words: usize = self.segments.iter().map(|s| s.text.split_whitespace().count()).sum();
+        format!("{} segments, {} words over {}", self.segments.len(), words, clock(self.duration_ms()))
+    }
+    // The segment being spoken at the time, for following along with the audio
+    pub fn segment_at(&self, ms: u64) -> Option<usize> {
+        self.segments.iter().position(|s| s.start_ms <= ms && ms < s.end_ms)
+    }
+
+    pub fn to_plain_text(&self) -> String {
+        let lines: Vec<&str> = self.segments.iter().map(|s| s.text.trim()).filter(|t| !t.is_empty()).collect();
+        lines.join("\n")
+    }
+    pub fn to_srt(&self) -> String {
+        let mut srt = String::new();
+        for (index, segment) in self.cues().iter().enumerate() {
+            srt += &format!("{}\n{} --> {}\n{}\n\n", index + 1, timestamp(segment.start_ms, ','), timestamp(segment.end_ms, ','), segment.text.trim());
+        }
+        srt
+    }
+    pub fn to_webvtt(&self) -> String {
+        let mut vtt = String::from("WEBVTT\n\n");
+        for segment in self.cues() {
+            vtt += &format!("{} --> {}\n{}\n\n", timestamp(segment.start_ms, '.'), timestamp(segment.end_ms, '.'), segment.text.trim());
+        }
+        vtt
+    }
+    // Picks the format from the extension: srt, vtt or anything else as plain text
+    pub fn export(&self, path: &Path) -> Result<(), Error> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        let contents = match extension.as_str() {
+            "srt" => self.to_srt(),
+            "vtt" => self.to_webvtt(),
+            _ => self.to_plain_text(),
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+    // The recorded audio of a segment, to hear what was actually said
+    pub fn audio_clip(&self, index: usize) -> Result<(Vec<f32>, WavSpec), Error> {
+        let segment = self.segments.get(index)
+            .ok_or_else(|| anyhow!("The transcript has {} segments, there is no segment {}", self.segments.len(), index + 1))?;
+        let audio_file = self.audio_file.as_ref()
+            .ok_or_else(|| anyhow!("The transcript has no recording"))?;
+        read_wav_range(audio_file, segment.start_ms, segment.end_ms)
+    }
+
+    // Subtitle players need cues with text that last at least a moment
+    fn cues(&self) -> Vec<&Segment> {
+        self.segments.iter().filter(|s| !s.text.trim().is_empty() && s.end_ms > s.start_ms).collect()
+    }
+}
+
+// Where in a recording something was said, to hear it again
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct AudioSpan {
+    pub audio_file: PathBuf,
+    pub start_ms: u64,
+    pub end_ms: u64
+}
+
+impl AudioSpan {
+    pub fn get_description(&self) -> String {
+        format!("{} from {} to {}", self.audio_file.display(), clock(self.start_ms), clock(self.end_ms))
+    }
+    pub fn audio_clip(&self) -> Result<(Vec<f32>, WavSpec), Error> {
+        read_wav_range(&self.audio_file, self.start_ms, self.end_ms)
+    }
+}
+
+impl TtrpgEntity {
+    pub fn attach_transcript(&mut self, transcript: Transcript) -> Result<(), Error> {
+        if transcript.is_empty() {
+            return Err(anyhow!("Nothing was transcribed to attach to {}", self.name));
+        }
+        self.transcripts.push(transcript);
+        Ok(())
+    }
+}
+
+pub(crate) fn clock(ms: u64) -> String {
+    format!("{:02}:{:02}:{:02}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60)
+}
+
+// The clock with milliseconds, srt separates them with a comma and webvtt with a dot
+fn timestamp(ms: u64, separator: char) -> String {
+    format!("{}{}{:03}", clock(ms), separator, ms % 1000)
+}
@@ -0,0 +1,143 @@
+use super::{read_wav, downmix};
+use anyhow::{anyhow, Error};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use std::path::{Path, PathBuf};
+
+// Thresholds for telling speech from silence. A frame is speech when it is louder than both the
+// energy threshold and the noise floor times the noise ratio, unless it crosses zero so often
+// that it is more likely hiss than a voice. Very loud frames count as speech regardless.
+#[derive(Clone, Copy, Debug)]
+pub struct VadConfig {
+    pub frame_ms: u32,
+    pub energy_threshold: f32, // root mean square, samples go from -1.0 to 1.0
+    pub noise_ratio: f32, // above the quietest tenth of the recording
+    pub max_zero_crossing_rate: f32, // crossings per sample
+    pub min_speech_ms: u32, // shorter bursts are dropped as clicks and bumps
+    pub min_silence_ms: u32, // shorter pauses do not split an utterance
+    pub padding_ms: u32 // kept around each utterance so word edges are not clipped
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        VadConfig {
+            frame_ms: 20,
+            energy_threshold: 0.01,
+            noise_ratio: 3.0,
+            max_zero_crossing_rate: 0.35,
+            min_speech_ms: 250,
+            min_silence_ms: 600,
+            padding_ms: 200
+        }
+    }
+}
+
+// An utterance in samples of the audio it was detected in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpeechSegment {
+    pub start: usize,
+    pub end: usize
+}
+
+impl SpeechSegment {
+    pub fn start_ms(&self, sample_rate: u32) -> u64 {
+        self.start as u64 * 1000 / sample_rate as u64
+    }
+    pub fn end_ms(&self, sample_rate: u32) -> u64 {
+        self.end as u64 * 1000 / sample_rate as u64
+    }
+}
+
+impl VadConfig {
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.frame_ms == 0 {
+            return Err(anyhow!("Voice detection needs frames longer than zero"));
+        }
+        if self.energy_threshold < 0.0 || self.noise_ratio < 0.0 {
+            return Err(anyhow!("Voice detection thresholds cannot be negative"));
+        }
+        Ok(())
+    }
+    // The utterances in mono samples, in order and without overlaps
+    pub fn detect(&self, samples: &[f32], sample_rate: u32) -> Vec<SpeechSegment> {
+        let frame_len = (sample_rate as usize * self.frame_ms as usize / 1000).max(1);
+        let frames: Vec<(f32, f32)> = samples.chunks(frame_len).map(frame_features).collect();
+        if frames.is_empty() {
+            return Vec::new();
+        }
+        let mut energies: Vec<f32> = frames.iter().map(|(rms, _)| *rms).collect();
+        energies.sort_by(|a, b| a.total_cmp(b));
+        let noise_floor = energies[energies.len() / 10];
+        let threshold = self.energy_threshold.max(noise_floor * self.noise_ratio);
+        let speech: Vec<bool> = frames.iter()
+            .map(|(rms, zcr)| *rms >= threshold && (*zcr <= self.max_zero_crossing_rate || *rms >= threshold * 4.0))
+            .collect();
+
+        // runs of speech frames, in frames
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        for (i, is_speech) in speech.iter().enumerate() {
+            if !*is_speech {
+                continue;
+            }
+            match runs.last_mut() {
+                Some(run) if run.1 == i => run.1 = i + 1,
+                _ => runs.push((i, i + 1)),
+            }
+        }
+        let to_frames = |ms: u32| (ms / self.frame_ms) as usize;
+        let mut merged: Vec<(usize, usize)> = Vec::new();
+        for run in runs {
+            match merged.last_mut() {
+                Some(last) if run.0 - last.1 < to_frames(self.min_silence_ms) => last.1 = run.1,
+                _ => merged.push(run),
+            }
+        }
+        merged.retain(|(start, end)| end - start >= to_frames(self.min_speech_ms).max(1));
+
+        let padding = sample_rate as usize * self.padding_ms as usize / 1000;
+        let mut segments: Vec<SpeechSegment> = Vec::new();
+        for (start, end) in merged {
+            let start = (start * frame_len).saturating_sub(padding);
+            let end = (end * frame_len + padding).min(samples.len());
+            match segments.last_mut() {
+                Some(last) if start <= last.end => last.end = end,
+                _ => segments.push(SpeechSegment {start, end}),
+            }
+        }
+        segments
+    }
+}
+
+// Root mean square and zero crossings per sample of a frame
+fn frame_features(frame: &[f32]) -> (f32, f32) {
+    let rms = (frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32).sqrt();
+    let crossings = frame.windows(2).filter(|w| (w[0] >= 0.0) != (w[1] >= 0.0)).count();
+    (rms, crossings as f32 / frame.len() as f32)
+}
+
+// Writes every detected utterance of the recording to its own wav in the directory, named after
+// the recording with the utterance number and where it starts, e.g. record_003_41520ms.wav
+pub fn export_speech_segments(audio_file: &Path, config: &VadConfig, directory: &Path) -> Result<Vec<PathBuf>, Error> {
+    config.validate()?;
+    let (samples, spec) = read_wav(audio_file)?;
+    let mono = downmix(&samples, spec.channels);
+    let segments = config.detect(&mono, spec.sample_rate);
+    std::fs::create_dir_all(directory)?;
+    let stem = audio_file.file_stem().and_then(|s| s.to_str()).unwrap_or("recording");
+    let output_spec = WavSpec {
+        channels: 1,
+        sample_rate: spec.sample_rate,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut paths = Vec::new();
+    for (index, segment) in segments.iter().enumerate() {
+        let path = directory.join(format!("{}_{:03}_{}ms.wav", stem, index + 1, segment.start_ms(spec.sample_rate)));
+        let mut writer = WavWriter::create(&path, output_spec)?;
+        for sample in mono[segment.start..segment.end].iter() {
+            writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+        }
+        writer.finalize()?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
@@ -0,0 +1,382 @@
+#![allow(dead_code)]
+use crate::dnd_tools::ClassProgression;
+use crate::entities::{TtrpgEntity, Elements, Attribute, Skill, Counter, Table, Resource, Recharge, Derived, Inventory, DiceExpression, Formula, Resolution, Resolved, Boon};
+use anyhow::{anyhow, Error};
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+// Packs that ship with the library, user packs are json files in ./rulesets/
+const BUILTIN_RULESETS: [&str; 3] = [
+    include_str!("dnd5e.json"),
+    include_str!("coc.json"),
+    include_str!("pbta.json")
+];
+// In a template's list of labels, takes every definition of that kind from the pack
+const ALL: &str = "*";
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct AttributeDefinition {
+    pub label: String,
+    #[serde(default)]
+    pub description: String,
+    pub roll: String, // dice expression such as "4d6" or "2d6+6"
+    #[serde(default)]
+    pub drop_lowest: bool,
+    #[serde(default = "one")]
+    pub multiplier: u32 // Call of Cthulhu characteristics are rolled and multiplied by 5
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct SkillDefinition {
+    pub label: String,
+    #[serde(default)]
+    pub attribute: Option<String>, // the attribute the skill is rolled with
+    #[serde(default)]
+    pub value: u32,
+    #[serde(default)]
+    pub proficient: bool,
+    #[serde(default)]
+    pub formula: Option<String> // replaces `value` once the attributes are rolled
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct DerivedDefinition {
+    pub label: String,
+    pub formula: String
+}
+
+// Counters start at `value` or at the result of `formula` once the attributes are rolled
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct CounterDefinition {
+    pub label: String,
+    #[serde(default)]
+    pub value: i32,
+    #[serde(default)]
+    pub formula: Option<String>
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct ResourceDefinition {
+    pub label: String,
+    pub max: i32,
+    pub recharge: Recharge,
+    #[serde(default)]
+    pub die: Option<u32>
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct TableRow {
+    pub low: u32,
+    pub high: u32,
+    pub text: String
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct TableDefinition {
+    pub label: String,
+    pub rows: Vec<TableRow>
+}
+
+// A starting point for new entities such as "5e PC", listing the labels it takes from the pack
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct EntityTemplate {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub player_character: bool,
+    #[serde(default)]
+    pub attributes: Vec<String>,
+    #[serde(default)]
+    pub skills: Vec<String>,
+    #[serde(default)]
+    pub derived: Vec<String>,
+    #[serde(default)]
+    pub counters: Vec<String>,
+    #[serde(default)]
+    pub resources: Vec<String>,
+    #[serde(default)]
+    pub tables: Vec<String>,
+    #[serde(default)]
+    pub inventory: bool
+}
+
+impl EntityTemplate {
+    pub fn get_description(&self) -> String {
+        format!("{}: {}", self.name, self.description)
+    }
+}
+
+// A game system described as data: what its characters are made of and how its rolls are resolved
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct Ruleset {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub resolution: Resolution,
+    #[serde(default)]
+    pub attributes: Vec<AttributeDefinition>,
+    #[serde(default)]
+    pub skills: Vec<SkillDefinition>,
+    #[serde(default)]
+    pub derived: Vec<DerivedDefinition>,
+    #[serde(default)]
+    pub counters: Vec<CounterDefinition>,
+    #[serde(default)]
+    pub resources: Vec<ResourceDefinition>,
+    #[serde(default)]
+    pub tables: Vec<TableDefinition>,
+    #[serde(default)]
+    pub templates: Vec<EntityTemplate>,
+    #[serde(default)]
+    pub classes: Vec<ClassProgression>
+}
+
+impl Ruleset {
+    pub fn from_json(json: &str) -> Result<Ruleset, Error> {
+        let ruleset: Ruleset = serde_json::from_str(json).map_err(|e| anyhow!("Invalid ruleset: {}", e))?;
+        ruleset.validate()?;
+        Ok(ruleset)
+    }
+    pub fn load(path: &Path) -> Result<Ruleset, Error> {
+        let json = std::fs::read_to_string(path).map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+        Ruleset::from_json(&json).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+    pub fn builtin() -> Vec<Ruleset> {
+        BUILTIN_RULESETS.iter()
+            .map(|json| Ruleset::from_json(json).expect("built in rulesets are valid"))
+            .collect()
+    }
+    pub fn get_description(&self) -> String {
+        format!("{}: {} ({} templates)", self.name, self.description, self.templates.len())
+    }
+    pub fn template(&self, name: &str) -> Option<&EntityTemplate> {
+        self.templates.iter().find(|t| t.name.eq_ignore_ascii_case(name))
+    }
+    pub fn class(&self, name: &str) -> Option<&ClassProgression> {
+        self.classes.iter().find(|c| c.name.eq_ignore_ascii_case(name))
+    }
+    // Checks an entity's skill with this ruleset's resolution mechanic
+    pub fn check_skill_with<R: Rng>(&self, entity: &TtrpgEntity, label: &str, advantage: &Boon, difficulty: i32, rng: &mut R) -> Result<Resolved, Error> {
+        let (resolved, _applied) = entity.check_skill_with(label, &self.resolution, advantage, difficulty, rng)?;
+        Ok(resolved)
+    }
+
+    // Checks that labels are unique, every reference resolves, dice and formulas parse
+    // and each template can build an entity whose derived values calculate
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.name.trim().is_empty() {
+            return Err(anyhow!("A ruleset needs a name"));
+        }
+        DiceExpression::parse(self.resolution.dice()).map_err(|e| anyhow!("{} resolution: {}", self.name, e))?;
+        let mut labels: Vec<String> = Vec::new();
+        let all_labels = self.attributes.iter().map(|a| &a.label)
+            .chain(self.skills.iter().map(|s| &s.label))
+            .chain(self.derived.iter().map(|d| &d.label))
+            .chain(self.counters.iter().map(|c| &c.label))
+            .chain(self.resources.iter().map(|r| &r.label))
+            .chain(self.tables.iter().map(|t| &t.label));
+        for label in all_labels {
+            if label.trim().is_empty() {
+                return Err(anyhow!("{} has an element without a label", self.name));
+            }
+            if labels.contains(&label.to_lowercase()) {
+                return Err(anyhow!("{} defines {} more than once", self.name, label));
+            }
+            labels.push(label.to_lowercase());
+        }
+        for attribute in self.attributes.iter() {
+            DiceExpression::parse(&attribute.roll).map_err(|e| anyhow!("{} {}: {}", self.name, attribute.label, e))?;
+        }
+        for skill in self.skills.iter() {
+            if let Some(attribute) = &skill.attribute {
+                if !self.attributes.iter().any(|a| a.label.eq_ignore_ascii_case(attribute)) {
+                    return Err(anyhow!("{} skill {} uses unknown attribute {}", self.name, skill.label, attribute));
+                }
+            }
+        }
+        let formulas = self.derived.iter().map(|d| (&d.label, &d.formula))
+            .chain(self.counters.iter().filter_map(|c| c.formula.as_ref().map(|f| (&c.label, f))))
+            .chain(self.skills.iter().filter_map(|s| s.formula.as_ref().map(|f| (&s.label, f))));
+        for (label, formula) in formulas {
+            let formula = Formula::parse(formula).map_err(|e| anyhow!("{} {}: {}", self.name, label, e))?;
+            for dependency in formula.dependencies() {
+                if !labels.contains(&dependency) {
+                    return Err(anyhow!("{} {} reads @{} which the ruleset does not define", self.name, label, dependency));
+                }
+            }
+        }
+        for resource in self.resources.iter() {
+            if resource.max < 0 {
+                return Err(anyhow!("{} {} cannot have a negative maximum", self.name, resource.label));
+            }
+            resource.recharge.check().map_err(|e| anyhow!("{} {}: {}", self.name, resource.label, e))?;
+        }
+        for table in self.tables.iter() {
+            for (index, row) in table.rows.iter().enumerate() {
+                if row.low > row.high {
+                    return Err(anyhow!("{} table {} has a row from {} down to {}", self.name, table.label, row.low, row.high));
+                }
+                if table.rows[..index].iter().any(|other| row.low <= other.high && other.low <= row.high) {
+                    return Err(anyhow!("{} table {} has overlapping rows at {}-{}", self.name, table.label, row.low, row.high));
+                }
+            }
+        }
+        for class in self.classes.iter() {
+            class.validate().map_err(|e| anyhow!("{}: {}", self.name, e))?;
+        }
+        let mut template_names: Vec<String> = Vec::new();
+        for template in self.templates.iter() {
+            if template_names.contains(&template.name.to_lowercase()) {
+                return Err(anyhow!("{} defines template {} more than once", self.name, template.name));
+            }
+            template_names.push(template.name.to_lowercase());
+            // the same rolls every time so a pack passes or fails the same way on every run
+            self.create_entity_with(&template.name, template.name.clone(), None, &mut StdRng::seed_from_u64(0))
+                .map_err(|e| anyhow!("{} template {}: {}", self.name, template.name, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn create_entity(&self, template: &str, name: String, database: Option<&str>) -> Result<TtrpgEntity, Error> {
+        self.create_entity_with(template, name, database, &mut thread_rng())
+    }
+    // Rolls the template's attributes and fills in everything else it lists
+    pub fn create_entity_with<R: Rng>(&self, template: &str, name: String, database: Option<&str>, rng: &mut R) -> Result<TtrpgEntity, Error> {
+        let template = self.template(template)
+            .ok_or_else(|| anyhow!("{} has no template {}", self.name, template))?;
+        let mut entity = TtrpgEntity::new(true, false, None, name, database);
+        entity.player_character.set(template.player_character);
+        entity.ruleset = self.name.clone();
+        let mut id: u32 = 0;
+        let mut next_id = || {
+            id += 1;
+            id
+        };
+
+        for definition in select(&self.attributes, &template.attributes, |a| &a.label, "attribute")? {
+            let expression = DiceExpression::parse(&definition.roll)?;
+            let (rolled, total) = expression.roll_with(rng);
+            let lowest = if definition.drop_lowest {rolled.iter().min().copied().unwrap_or(0) as i32} else {0};
+            let score = ((total - lowest).max(0) as u32) * definition.multiplier;
+            let id = next_id();
+            let attribute = Attribute::from_score(id, id, definition.label.clone(), definition.description.clone(), score, format!("Roll: {}", expression.expression));
+            entity.add_element(Elements::Attribute(attribute));
+        }
+        for definition in select(&self.counters, &template.counters, |c| &c.label, "counter")? {
+            let id = next_id();
+            entity.add_element(Elements::Counter(Counter::new(id, id, definition.label.clone(), definition.value)));
+        }
+        let level = entity.find_counter("Level").map(|c| c.number.max(1) as u32).unwrap_or(1);
+        for definition in select(&self.skills, &template.skills, |s| &s.label, "skill")? {
+            let id = next_id();
+            let mut skill = Skill::new(id, id, definition.label.clone(), level, definition.value, definition.proficient)?;
+            skill.attribute = definition.attribute.clone();
+            if let Some(formula) = &definition.formula {
+                skill.set_formula(formula)?;
+            }
+            entity.add_element(Elements::Skill(skill));
+        }
+        for definition in select(&self.resources, &template.resources, |r| &r.label, "resource")? {
+            let id = next_id();
+            let mut resource = Resource::new(id, id, definition.label.clone(), definition.max, definition.recharge)?;
+            resource.die = definition.die;
+            entity.add_element(Elements::Resource(resource));
+        }
+        for definition in select(&self.tables, &template.tables, |t| &t.label, "table")? {
+            let rows = definition.rows.iter().map(|r| ((r.low, r.high), r.text.clone())).collect();
+            let id = next_id();
+            entity.add_element(Elements::Table(Table::new(id, id, definition.label.clone(), rows)?));
+        }
+        for definition in select(&self.derived, &template.derived, |d| &d.label, "derived value")? {
+            let id = next_id();
+            entity.add_element(Elements::Derived(Derived::new(id, id, definition.label.clone(), &definition.formula)?));
+        }
+        if template.inventory {
+            let id = next_id();
+            entity.add_element(Elements::Inventory(Inventory::new(id, id)));
+        }
+        entity.recalculate()?;
+
+        // counters with formulas start from the rolled attributes, e.g. hit points from constitution
+        let mut starting_values: HashMap<String, i32> = HashMap::new();
+        for definition in select(&self.counters, &template.counters, |c| &c.label, "counter")? {
+            if let Some(formula) = &definition.formula {
+                let value = Formula::parse(formula)?.evaluate(&|l: &str| entity.value_of(l))
+                    .map_err(|e| anyhow!("{}: {}", definition.label, e))?;
+                starting_values.insert(definition.label.clone(), value.floor() as i32);
+            }
+        }
+        for (label, value) in starting_values {
+            if let Some(counter) = entity.find_counter_mut(&label) {
+                *counter = Counter::new(counter.id, counter.order_num, counter.label.clone(), value);
+            }
+        }
+        entity.recalculate()?;
+        Ok(entity)
+    }
+}
+
+// The definitions a template asks for, in the order the pack lists them
+fn select<'a, T, F: Fn(&T) -> &String>(definitions: &'a [T], wanted: &[String], label: F, kind: &str) -> Result<Vec<&'a T>, Error> {
+    if wanted.iter().any(|w| w == ALL) {
+        return Ok(definitions.iter().collect());
+    }
+    let mut selected = Vec::new();
+    for name in wanted.iter() {
+        let definition = definitions.iter().find(|d| label(d).eq_ignore_ascii_case(name))
+            .ok_or_else(|| anyhow!("unknown {} {}", kind, name))?;
+        selected.push(definition);
+    }
+    Ok(selected)
+}
+
+// The built in packs followed by every valid pack in the directory; a pack with the name
+// of a built in one replaces it. Also returns why any file could not be loaded.
+pub fn load_rulesets(directory: &Path) -> (Vec<Ruleset>, Vec<String>) {
+    let mut rulesets = Ruleset::builtin();
+    let mut errors: Vec<String> = Vec::new();
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return (rulesets, errors),
+    };
+    let mut paths: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.path())
+        .filter(|p| p.extension().map_or(false, |e| e == "json"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        match Ruleset::load(&path) {
+            Ok(ruleset) => {
+                rulesets.retain(|r| !r.name.eq_ignore_ascii_case(&ruleset.name));
+                rulesets.push(ruleset);
+            },
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+    (rulesets, errors)
+}
+
+// Finds a template such as "CoC Investigator" in any of the loaded packs
+pub fn find_template<'a>(rulesets: &'a [Ruleset], name: &str) -> Option<(&'a Ruleset, &'a EntityTemplate)> {
+    for ruleset in rulesets.iter() {
+        if let Some(template) = ruleset.template(name) {
+            return Some((ruleset, template));
+        }
+    }
+    None
+}
@@ -0,0 +1,55 @@
+#![allow(dead_code)]
+use serde::{Serialize, Deserialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogKind {
+    Roll,
+    Combat,
+    Note
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct LogEntry {
+    pub timestamp: u64, // seconds since the unix epoch
+    pub kind: LogKind,
+    pub text: String
+}
+
+impl LogEntry {
+    pub fn get_description(&self) -> String {
+        format!("[{:?}] {}", self.kind, self.text)
+    }
+}
+
+// Running history of the rolls and events of a play session, shown in the bottom panel
+#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct SessionLog {
+    pub entries: Vec<LogEntry>
+}
+
+impl SessionLog {
+    pub fn new() -> SessionLog {
+        SessionLog {entries: Vec::new()}
+    }
+    pub fn record(&mut self, kind: LogKind, text: &str) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.entries.push(LogEntry {timestamp, kind, text: text.to_string()});
+    }
+    pub fn entries_of(&self, kind: LogKind) -> Vec<&LogEntry> {
+        self.entries.iter().filter(|e| e.kind == kind).collect()
+    }
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+    pub fn to_text(&self) -> String {
+        let lines: Vec<String> = self.entries.iter().map(|e| e.get_description()).collect();
+        lines.join("\n")
+    }
+}
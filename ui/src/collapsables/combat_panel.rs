@@ -0,0 +1,92 @@
+use gm_helper_corelibrary::{TtrpgEntity, Combat, SessionLog, LogKind};
+use eframe::egui::{Vec2, Ui, ScrollArea, DragValue, RichText};
+
+// returns the ui height and width as a egui::Vec2 in order to calculate ui sizes
+pub fn combat_panel(ui: &mut Ui, combat: &mut Combat, ttrpgs: &mut Vec<TtrpgEntity>, session_log: &mut SessionLog, amount: &mut i32) -> Vec2 {
+    let combat_ui = ui.group(|ui| {
+        ui.horizontal_wrapped(|ui| {
+            if ui.button("Add active entities").clicked() {
+                for (index, ttrpg) in ttrpgs.iter().enumerate() {
+                    if ttrpg.active.get() {
+                        if let Err(e) = combat.add_entity(ttrpgs, index) {
+                            session_log.record(LogKind::Note, &e.to_string());
+                        }
+                    }
+                }
+            }
+            if ui.button("Roll initiative").clicked() {
+                combat.roll_initiative();
+            }
+            if ui.button("Next turn").clicked() {
+                if let Err(e) = combat.next_turn() {
+                    session_log.record(LogKind::Note, &e.to_string());
+                }
+            }
+            if ui.button("End combat").clicked() {
+                combat.write_hit_points_to_entities(ttrpgs);
+                combat.write_conditions_to_entities(ttrpgs);
+                combat.end();
+                combat.combatants.clear();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Amount: ");
+            ui.add(DragValue::new(amount).clamp_range(0..=1000));
+        });
+        if combat.started {
+            ui.strong(format!("Round {}", combat.round));
+        }
+        let mut actions: Vec<(usize, &str)> = Vec::new();
+        ScrollArea::vertical().show(ui, |ui| {
+            for (index, combatant) in combat.combatants.iter().enumerate() {
+                ui.group(|ui| {
+                    let description = combatant.get_description();
+                    if combat.started && index == combat.turn {
+                        ui.label(RichText::new(description).strong());
+                    } else {
+                        ui.label(description);
+                    }
+                    ui.horizontal_wrapped(|ui| {
+                        if ui.small_button("Damage").clicked() {
+                            actions.push((index, "damage"));
+                        }
+                        if ui.small_button("Heal").clicked() {
+                            actions.push((index, "heal"));
+                        }
+                        if ui.small_button("Temp HP").clicked() {
+                            actions.push((index, "temporary"));
+                        }
+                        if combatant.is_dying() {
+                            ui.label(format!("Death saves {}/{}", combatant.death_saves.successes, combatant.death_saves.failures));
+                            if ui.small_button("Death save").clicked() {
+                                actions.push((index, "death_save"));
+                            }
+                        }
+                        if combatant.concentrating_on.is_some() && ui.small_button("Drop concentration").clicked() {
+                            actions.push((index, "concentration"));
+                        }
+                        if ui.small_button("Remove").clicked() {
+                            actions.push((index, "remove"));
+                        }
+                    });
+                });
+            }
+        });
+        // actions are applied after drawing so the combatant list isn't borrowed while changing
+        for (index, action) in actions.iter() {
+            let result = match *action {
+                "damage" => combat.damage(*index, *amount),
+                "heal" => combat.heal(*index, *amount),
+                "temporary" => combat.set_temporary_hit_points(*index, *amount),
+                "death_save" => combat.roll_death_save(*index),
+                "concentration" => combat.break_concentration(*index),
+                _ => combat.remove_combatant(*index).map(|_| ()),
+            };
+            if let Err(e) = result {
+                session_log.record(LogKind::Note, &e.to_string());
+            }
+        }
+        combat.log_events(session_log);
+    });
+    combat_ui.response.rect.size()
+}
@@ -1,5 +1,9 @@
 mod edit_view;
 mod ui_sides;
+mod combat_panel;
+mod recording_panel;
 
 pub use edit_view::*;
-pub use ui_sides::*;
\ No newline at end of file
+pub use ui_sides::*;
+pub use combat_panel::*;
+pub use recording_panel::*;
\ No newline at end of file
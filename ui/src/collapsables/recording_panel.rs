@@ -0,0 +1,568 @@
+use gm_helper_corelibrary::{TtrpgEntity, Elements, record_audio_streaming, EngineConfig, ModelSize, TranscriptionEngine, StreamingTranscriber, StreamConfig, StreamEvent, VadConfig, export_speech_segments, Transcript, SessionArchive, SessionRecording, DEFAULT_ARCHIVE_DIR, AudioSettings, InputDeviceInfo, InputLevel, list_input_devices, monitor_input_level, DEFAULT_AUDIO_SETTINGS_FILE, VoiceControl, Combat, SessionLog, SpeakerEnrollment, VoiceProfile, to_whisper_samples, WHISPER_SAMPLE_RATE, DEFAULT_SPEAKERS_FILE, Story, StorySplitter};
+use eframe::egui::{Vec2, Ui, ComboBox, ScrollArea, DragValue, Button, CollapsingHeader, ProgressBar, Checkbox, TextEdit};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+type Retranscription = JoinHandle<(Result<SessionRecording, String>, Option<TranscriptionEngine>)>;
+type Compression = JoinHandle<Result<SessionRecording, String>>;
+type SpeechExport = JoinHandle<Result<(usize, PathBuf), String>>;
+type StoryBuilding = JoinHandle<Result<Vec<Story>, String>>;
+
+// Everything about recording the table, transcribing it and the archive of past sessions
+pub struct RecordingState {
+    pub recording: Arc<Mutex<bool>>,
+    pub engine_config: EngineConfig,
+    pub engine: Option<TranscriptionEngine>, // loaded on the first transcription and kept
+    pub transcriber: Option<StreamingTranscriber>,
+    pub partial_transcription: String,
+    pub transcript: Transcript, // of the latest recording
+    pub archive: Option<SessionArchive>,
+    pub session: Option<SessionRecording>, // being recorded
+    pub session_entity: String, // name of the entity the next recording is linked to
+    pub compress: bool,
+    pub sessions: Vec<SessionRecording>,
+    pub playing: Arc<Mutex<bool>>,
+    pub retranscription: Option<Retranscription>,
+    pub compression: Option<Compression>,
+    pub speech_export: Option<SpeechExport>,
+    pub story_building: Option<((usize, String, String), StoryBuilding)>, // index, id and name of the entity the stories are for
+    pub audio_settings: AudioSettings,
+    pub input_devices: Vec<InputDeviceInfo>,
+    pub level: InputLevel,
+    pub monitoring: Arc<Mutex<bool>>, // listening to the mic without recording
+    pub voice_commands: bool,
+    pub heard: Vec<String>, // sentences transcribed since the last poll, for voice commands
+    pub speakers: SpeakerEnrollment,
+    pub speaker_name: String // who the next enrolled voice belongs to
+}
+
+impl Default for RecordingState {
+    fn default() -> Self {
+        let archive = SessionArchive::open(Path::new(DEFAULT_ARCHIVE_DIR))
+            .map_err(|e| println!("{}", e))
+            .ok();
+        let audio_settings = AudioSettings::load(Path::new(DEFAULT_AUDIO_SETTINGS_FILE))
+            .unwrap_or_else(|e| {
+                println!("{}", e);
+                AudioSettings::default()
+            });
+        let speakers = SpeakerEnrollment::load(Path::new(DEFAULT_SPEAKERS_FILE))
+            .unwrap_or_else(|e| {
+                println!("{}", e);
+                SpeakerEnrollment::default()
+            });
+        let mut state = Self {
+            recording: Arc::new(Mutex::new(false)),
+            engine_config: EngineConfig::default(),
+            engine: None,
+            transcriber: None,
+            partial_transcription: String::from(""),
+            transcript: Transcript::default(),
+            archive,
+            session: None,
+            session_entity: String::from(""),
+            compress: false,
+            sessions: Vec::new(),
+            playing: Arc::new(Mutex::new(false)),
+            retranscription: None,
+            compression: None,
+            speech_export: None,
+            story_building: None,
+            audio_settings,
+            input_devices: Vec::new(),
+            level: InputLevel::new(),
+            monitoring: Arc::new(Mutex::new(false)),
+            voice_commands: false,
+            heard: Vec::new(),
+            speakers,
+            speaker_name: String::from("")
+        };
+        state.refresh_sessions();
+        state.refresh_input_devices();
+        state
+    }
+}
+
+impl RecordingState {
+    pub fn refresh_sessions(&mut self) {
+        if let Some(archive) = self.archive.as_ref() {
+            let (sessions, errors) = archive.list();
+            for error in errors.iter() {
+                println!("Could not load session {}", error);
+            }
+            self.sessions = sessions;
+        }
+    }
+    pub fn refresh_input_devices(&mut self) {
+        match list_input_devices() {
+            Ok(devices) => self.input_devices = devices,
+            Err(e) => println!("Could not list the input devices: {}", e),
+        }
+    }
+    // Collects what the background threads finished, returns whether any are still working
+    pub fn poll(&mut self, transcribed_audio: &mut String) -> bool {
+        let mut transcription_finished = false;
+        if let Some(transcriber) = self.transcriber.as_ref() {
+            for event in transcriber.poll() {
+                match event {
+                    StreamEvent::Partial(segment) => self.partial_transcription = segment.text,
+                    StreamEvent::Final(segment) => {
+                        transcribed_audio.push_str(&segment.text);
+                        self.partial_transcription.clear();
+                        if self.voice_commands {
+                            self.heard.push(segment.text.clone());
+                        }
+                        self.transcript.push(segment);
+                    },
+                    StreamEvent::Error(e) => println!("Transcription error: {}", e),
+                    StreamEvent::Finished => transcription_finished = true,
+                }
+            }
+        }
+        if transcription_finished {
+            self.partial_transcription.clear();
+            let engine = self.transcriber.take().and_then(|transcriber| transcriber.into_engine());
+            let model = engine.as_ref().map(|engine| engine.config().model_name());
+            self.keep_engine(engine);
+            if let Some(mut session) = self.session.take() {
+                let transcript = if self.transcript.is_empty() {None} else {Some(self.transcript.clone())};
+                match session.finish(transcript, model, false) {
+                    Ok(()) if self.compress => self.start_compression(session.clone()),
+                    Ok(()) => {},
+                    Err(e) => println!("Could not archive the session: {}", e),
+                }
+                self.transcript.audio_file = Some(session.audio_file());
+            }
+            self.refresh_sessions();
+        }
+        if self.retranscription.as_ref().map_or(false, |handle| handle.is_finished()) {
+            if let Some(Ok((result, engine))) = self.retranscription.take().map(|handle| handle.join()) {
+                if let Err(e) = result {
+                    println!("Could not transcribe the session again: {}", e);
+                }
+                self.keep_engine(engine);
+            }
+            self.refresh_sessions();
+        }
+        if self.compression.as_ref().map_or(false, |handle| handle.is_finished()) {
+            match self.compression.take().map(|handle| handle.join()) {
+                Some(Ok(Ok(session))) => {
+                    // the latest transcript follows its audio into the compressed file
+                    if self.transcript.audio_file.as_ref().map_or(false, |file| file.starts_with(&session.directory)) {
+                        self.transcript.audio_file = Some(session.audio_file());
+                    }
+                },
+                Some(Ok(Err(e))) => println!("Could not compress the session: {}", e),
+                _ => {},
+            }
+            self.refresh_sessions();
+        }
+        if self.speech_export.as_ref().map_or(false, |handle| handle.is_finished()) {
+            match self.speech_export.take().map(|handle| handle.join()) {
+                Some(Ok(Ok((count, directory)))) => println!("Exported {} utterances to {}", count, directory.display()),
+                Some(Ok(Err(e))) => println!("Could not export speech: {}", e),
+                _ => {},
+            }
+        }
+        self.busy() || self.story_building.is_some() || *self.playing.lock().unwrap() || *self.monitoring.lock().unwrap()
+    }
+    // Listening for speakers reads the whole recording, so stories are made on their own thread
+    pub fn start_stories(&mut self, transcript: Transcript, entity_index: usize, entity: &TtrpgEntity) {
+        let speakers = self.speakers.clone();
+        let target = (entity_index, entity.id.clone(), entity.name.clone());
+        self.story_building = Some((target, std::thread::spawn(move || {
+            StorySplitter::default().to_stories(&transcript, Some(&speakers), 1).map_err(|e| e.to_string())
+        })));
+    }
+    // Adds finished stories to the entity they were made for, numbered after its elements. Saved
+    // entities are found by their id, unsaved ones by where they were in the entities.
+    pub fn finish_stories(&mut self, entities: &mut [TtrpgEntity]) {
+        if !self.story_building.as_ref().map_or(false, |(_target, handle)| handle.is_finished()) {
+            return;
+        }
+        let ((index, id, name), handle) = match self.story_building.take() {
+            Some(building) => building,
+            None => return,
+        };
+        let stories = match handle.join() {
+            Ok(Ok(stories)) => stories,
+            Ok(Err(e)) => {
+                println!("Could not make stories for {}: {}", name, e);
+                return;
+            },
+            Err(_) => return,
+        };
+        let entity = if id.is_empty() {
+            entities.get_mut(index).filter(|e| e.id.is_empty() && e.name == name)
+        } else {
+            entities.iter_mut().find(|e| e.id == id)
+        };
+        match entity {
+            Some(entity) => {
+                let first_id = entity.elements.len() as u32 + 1;
+                for (offset, mut story) in stories.into_iter().enumerate() {
+                    story.id = first_id + offset as u32;
+                    story.order_num = story.id;
+                    entity.add_element(Elements::Story(story));
+                }
+            },
+            None => println!("{} is no longer open to add stories to", name),
+        }
+    }
+    // Settings changed while the engine was busy apply now, a different model is loaded next time
+    fn keep_engine(&mut self, engine: Option<TranscriptionEngine>) {
+        let language = self.engine_config.language.clone().unwrap_or("auto".to_string());
+        let (threads, vad) = (self.engine_config.threads, self.engine_config.vad);
+        self.engine = engine
+            .filter(|engine| engine.config().model_file() == self.engine_config.model_file())
+            .filter(|engine| engine.config().english_only == self.engine_config.english_only)
+            .and_then(|mut engine| engine.set_language(&language).and_then(|_| engine.set_threads(threads)).and_then(|_| engine.set_vad(vad)).ok().map(|_| engine));
+    }
+    // Resampling and gzipping a long session takes a while, so it runs on its own thread
+    fn start_compression(&mut self, mut session: SessionRecording) {
+        self.compression = Some(std::thread::spawn(move || {
+            session.compress().map(|_| session).map_err(|e| e.to_string())
+        }));
+    }
+    fn busy(&self) -> bool {
+        self.transcriber.is_some() || self.retranscription.is_some() || self.compression.is_some() || self.speech_export.is_some()
+    }
+}
+
+//Handling the recording and transcription of audio to text with whisper.cpp
+pub fn recording_controls(ui: &mut Ui, state: &mut RecordingState, ttrpgs: &Vec<TtrpgEntity>) {
+    ui.horizontal_wrapped(|ui| {
+        if !*state.recording.lock().unwrap() {
+            // a finished recording is still being transcribed until the transcriber is taken back
+            let can_record = !state.busy() && state.archive.is_some();
+            if ui.add_enabled(can_record, Button::new("record")).clicked() {
+                let entity = ttrpgs.iter().find(|t| t.name == state.session_entity);
+                let session = match state.archive.as_ref().map(|archive| archive.start_session(entity)) {
+                    Some(Ok(session)) => session,
+                    Some(Err(e)) => {
+                        println!("Could not start a session: {}", e);
+                        return;
+                    },
+                    None => return,
+                };
+                // Transcription runs alongside the recording, with the loaded model if there is one
+                match StreamingTranscriber::start(state.engine.take(), state.engine_config.clone(), StreamConfig::default()) {
+                    Ok(mut started) => {
+                        let audio_file = session.audio_file();
+                        state.transcript = Transcript::new(Some(audio_file.clone()));
+                        state.session = Some(session);
+                        *state.monitoring.lock().unwrap() = false;
+                        *state.recording.lock().unwrap() = true;
+                        let recording_bool_clone = state.recording.clone();
+                        let input = started.take_input();
+                        let device_name = state.audio_settings.input_device.clone();
+                        let level = state.level.clone();
+                        state.transcriber = Some(started);
+                        std::thread::spawn(move || {
+                            let path = audio_file.to_string_lossy().to_string();
+                            if let Err(e) = record_audio_streaming(&path, recording_bool_clone.clone(), input, device_name.as_deref(), Some(level)) {
+                                println!("Could not record: {}", e);
+                                *recording_bool_clone.lock().unwrap() = false;
+                            }
+                        });
+                    },
+                    Err(e) => println!("Could not start transcription: {}", e),
+                }
+            }
+        } else {
+            if ui.button("stop recording").clicked() {
+                *state.recording.lock().unwrap() = false;
+                if let Some(transcriber) = state.transcriber.as_mut() {
+                    transcriber.stop();
+                }
+            }
+        }
+        let selected_text = if state.session_entity.is_empty() {"No entity".to_string()} else {state.session_entity.clone()};
+        ComboBox::from_id_source("session_entity")
+            .selected_text(selected_text)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut state.session_entity, "".to_string(), "No entity");
+                for ttrpg in ttrpgs.iter() {
+                    ui.selectable_value(&mut state.session_entity, ttrpg.name.clone(), ttrpg.name.clone());
+                }
+            });
+        ui.checkbox(&mut state.compress, "Compress");
+        ui.checkbox(&mut state.voice_commands, "Voice commands")
+            .on_hover_text("\"roll two d six plus three\", \"goblin two takes seven damage\", \"advance initiative\", then \"confirm\" or \"cancel\"");
+    });
+    ui.horizontal_wrapped(|ui| {
+        // the device is kept for the next runs, a missing one is reported when recording starts
+        let selected_device = state.audio_settings.input_device.clone();
+        ComboBox::from_id_source("input_device")
+            .selected_text(selected_device.clone().unwrap_or("Default input".to_string()))
+            .show_ui(ui, |ui| {
+                let mut device = selected_device.clone();
+                ui.selectable_value(&mut device, None, "Default input");
+                for info in state.input_devices.iter() {
+                    let configs: Vec<String> = info.configs.iter().map(|c| c.get_description()).collect();
+                    ui.selectable_value(&mut device, Some(info.name.clone()), info.get_description())
+                        .on_hover_text(configs.join("\n"));
+                }
+                if device != selected_device {
+                    state.audio_settings.input_device = device;
+                    *state.monitoring.lock().unwrap() = false;
+                    state.audio_settings.save(Path::new(DEFAULT_AUDIO_SETTINGS_FILE)).unwrap_or_else(|e| println!("Could not save the audio settings: {}", e));
+                }
+            });
+        if ui.small_button("refresh").clicked() {
+            state.refresh_input_devices();
+        }
+        let recording = *state.recording.lock().unwrap();
+        let mut monitoring = *state.monitoring.lock().unwrap();
+        if ui.add_enabled(!recording, Checkbox::new(&mut monitoring, "Test mic")).changed() {
+            *state.monitoring.lock().unwrap() = monitoring;
+            if monitoring {
+                state.level.reset();
+                let (listening, level) = (state.monitoring.clone(), state.level.clone());
+                let device_name = state.audio_settings.input_device.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = monitor_input_level(device_name.as_deref(), listening.clone(), level) {
+                        println!("Could not listen to the input: {}", e);
+                        *listening.lock().unwrap() = false;
+                    }
+                });
+            }
+        }
+        if recording || monitoring {
+            let reading = state.level.reading();
+            ui.add(ProgressBar::new(reading.meter()).desired_width(120.0).text(reading.get_description()));
+        }
+    });
+    if state.transcriber.is_some() {
+        ui.label(format!("Hearing: {}", state.partial_transcription));
+    }
+    ui.horizontal(|ui| {
+        let engine_config = &mut state.engine_config;
+        let engine = &mut state.engine;
+        // A different model is loaded on the next transcription, language and threads apply to the loaded one
+        let (size, english_only) = (engine_config.size, engine_config.english_only);
+        ComboBox::from_id_source("whisper_model")
+            .selected_text(engine_config.model_name())
+            .show_ui(ui, |ui| {
+                for model_size in ModelSize::all() {
+                    ui.selectable_value(&mut engine_config.size, model_size, model_size.name());
+                }
+            });
+        ui.checkbox(&mut engine_config.english_only, "English only");
+        if size != engine_config.size || english_only != engine_config.english_only {
+            *engine = None;
+        }
+        let language = engine_config.language.clone().unwrap_or("auto".to_string());
+        ComboBox::from_id_source("whisper_language")
+            .selected_text(language.clone())
+            .show_ui(ui, |ui| {
+                for code in ["auto", "en", "de", "es", "fr", "it", "ja", "nl", "pt"] {
+                    if ui.selectable_label(language == code, code).clicked() {
+                        let mut config = engine_config.clone();
+                        let result = config.set_language(code)
+                            .and_then(|_| config.validate())
+                            .and_then(|_| engine.as_mut().map_or(Ok(()), |engine| engine.set_language(code)));
+                        match result {
+                            Err(e) => println!("{}", e),
+                            _ => *engine_config = config,
+                        }
+                    }
+                }
+            });
+        if ui.add(DragValue::new(&mut engine_config.threads).clamp_range(1..=16).prefix("threads ")).changed() {
+            if let Some(engine) = engine.as_mut() {
+                engine.set_threads(engine_config.threads).unwrap_or_else(|e| println!("{}", e));
+            }
+        }
+        let mut skip_silence = engine_config.vad.is_some();
+        if ui.checkbox(&mut skip_silence, "Skip silence").changed() {
+            engine_config.vad = if skip_silence {Some(VadConfig::default())} else {None};
+            if let Some(engine) = engine.as_mut() {
+                engine.set_vad(engine_config.vad).unwrap_or_else(|e| println!("{}", e));
+            }
+        }
+    });
+}
+
+// Carries out what was said while recording, showing commands that wait to be confirmed
+pub fn voice_command_bar(ui: &mut Ui, state: &mut RecordingState, voice_control: &mut VoiceControl, feedback: &mut String, combat: &mut Combat, ttrpgs: &mut Vec<TtrpgEntity>, session_log: &mut SessionLog) {
+    for sentence in std::mem::take(&mut state.heard) {
+        match voice_control.hear(&sentence, combat, ttrpgs, session_log) {
+            Some(Ok(outcome)) => *feedback = outcome,
+            Some(Err(e)) => *feedback = e.to_string(),
+            None => {},
+        }
+    }
+    if !state.voice_commands && voice_control.pending.is_none() {
+        return;
+    }
+    ui.horizontal_wrapped(|ui| {
+        if let Some(pending) = voice_control.pending.as_ref() {
+            ui.label(pending.get_description());
+            if ui.button("confirm").clicked() {
+                *feedback = voice_control.confirm(combat, ttrpgs, session_log).unwrap_or_else(|e| e.to_string());
+            }
+            if ui.button("cancel").clicked() {
+                *feedback = voice_control.cancel().unwrap_or_else(|e| e.to_string());
+            }
+        } else {
+            ui.label(feedback.as_str());
+        }
+    });
+}
+
+// returns the ui height and width as a egui::Vec2 in order to calculate ui sizes
+pub fn sessions_panel(ui: &mut Ui, state: &mut RecordingState) -> Vec2 {
+    let sessions_ui = ui.group(|ui| {
+        ui.horizontal_wrapped(|ui| {
+            if ui.button("Refresh").clicked() {
+                state.refresh_sessions();
+            }
+            if *state.playing.lock().unwrap() && ui.button("Stop playing").clicked() {
+                *state.playing.lock().unwrap() = false;
+            }
+            if state.retranscription.is_some() {
+                ui.label(format!("Transcribing again with {}...", state.engine_config.model_name()));
+            }
+            if state.compression.is_some() {
+                ui.label("Compressing...");
+            }
+            if state.speech_export.is_some() {
+                ui.label("Exporting speech...");
+            }
+        });
+        // voices are enrolled from transcript lines the speaker said, to label stories made from transcripts
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Speaker");
+            ui.add(TextEdit::singleline(&mut state.speaker_name).desired_width(100.0));
+            let mut to_remove: Option<String> = None;
+            for speaker in state.speakers.speakers() {
+                if ui.small_button(format!("{} x", speaker)).on_hover_text("Forget this voice").clicked() {
+                    to_remove = Some(speaker);
+                }
+            }
+            if let Some(speaker) = to_remove {
+                state.speakers.remove_speaker(&speaker);
+                state.speakers.save(Path::new(DEFAULT_SPEAKERS_FILE)).unwrap_or_else(|e| println!("Could not save the speakers: {}", e));
+            }
+        });
+        let mut to_delete: Option<String> = None;
+        let mut to_play: Option<(usize, u64)> = None;
+        let mut to_retranscribe: Option<usize> = None;
+        let mut to_compress: Option<usize> = None;
+        let mut to_export: Option<usize> = None;
+        let mut to_enroll: Option<(usize, usize)> = None;
+        ScrollArea::vertical().show(ui, |ui| {
+            // the audio moves to a gzipped file while compressing, so nothing reads it until that is done
+            let can_play = !*state.playing.lock().unwrap() && state.compression.is_none();
+            for (index, session) in state.sessions.iter().enumerate() {
+                ui.group(|ui| {
+                    ui.label(session.get_description());
+                    ui.horizontal_wrapped(|ui| {
+                        if ui.add_enabled(can_play, Button::new("play")).clicked() {
+                            to_play = Some((index, 0));
+                        }
+                        let can_retranscribe = !state.busy() && !*state.recording.lock().unwrap();
+                        if ui.add_enabled(can_retranscribe, Button::new("transcribe again"))
+                            .on_hover_text(format!("With {}", state.engine_config.get_description()))
+                            .clicked() {
+                            to_retranscribe = Some(index);
+                        }
+                        if !session.compressed && ui.add_enabled(!state.busy(), Button::new("compress")).clicked() {
+                            to_compress = Some(index);
+                        }
+                        // Each utterance as its own file, to check what the voice detection hears
+                        if ui.add_enabled(state.speech_export.is_none() && state.compression.is_none(), Button::new("export speech").small())
+                            .on_hover_text("Saves every utterance to the session's speech folder")
+                            .clicked() {
+                            to_export = Some(index);
+                        }
+                        if ui.add_enabled(!state.busy(), Button::new("delete")).clicked() {
+                            to_delete = Some(session.id.clone());
+                        }
+                    });
+                    if let Some(transcript) = session.transcript.as_ref() {
+                        CollapsingHeader::new("Transcript").id_source(&session.id).show(ui, |ui| {
+                            for (segment_index, segment) in transcript.segments.iter().enumerate() {
+                                ui.horizontal_wrapped(|ui| {
+                                    if ui.add_enabled(can_play, Button::new("play").small()).clicked() {
+                                        to_play = Some((index, segment.start_ms));
+                                    }
+                                    let can_enroll = !state.speaker_name.trim().is_empty() && state.compression.is_none();
+                                    if ui.add_enabled(can_enroll, Button::new("voice").small()).on_hover_text(format!("Enroll this line as the voice of {}", state.speaker_name)).clicked() {
+                                        to_enroll = Some((index, segment_index));
+                                    }
+                                    ui.label(segment.get_description());
+                                });
+                            }
+                        });
+                    }
+                });
+            }
+        });
+        if let Some((index, from_ms)) = to_play {
+            let session = state.sessions[index].clone();
+            let playing = state.playing.clone();
+            *playing.lock().unwrap() = true;
+            std::thread::spawn(move || {
+                if let Err(e) = session.replay(from_ms, playing.clone()) {
+                    println!("Could not play the session: {}", e);
+                    *playing.lock().unwrap() = false;
+                }
+            });
+        }
+        if let Some(index) = to_retranscribe {
+            let mut session = state.sessions[index].clone();
+            let engine = state.engine.take();
+            let engine_config = state.engine_config.clone();
+            state.retranscription = Some(std::thread::spawn(move || {
+                let mut engine = match engine.map_or_else(|| TranscriptionEngine::new(engine_config), Ok) {
+                    Ok(engine) => engine,
+                    Err(e) => return (Err(e.to_string()), None),
+                };
+                let result = session.retranscribe(&mut engine).map(|_| session).map_err(|e| e.to_string());
+                (result, Some(engine))
+            }));
+        }
+        // reading and splitting a long session takes a while, like transcribing it again
+        if let Some(index) = to_export {
+            let session = state.sessions[index].clone();
+            let vad = state.engine_config.vad.unwrap_or_default();
+            state.speech_export = Some(std::thread::spawn(move || {
+                let directory = session.directory.join("speech");
+                export_speech_segments(&session.audio_file(), &vad, &directory)
+                    .map(|paths| (paths.len(), directory))
+                    .map_err(|e| e.to_string())
+            }));
+        }
+        if let Some((index, segment_index)) = to_enroll {
+            let profile = state.sessions[index].transcript.as_ref()
+                .ok_or_else(|| "The session has no transcript".to_string())
+                .and_then(|transcript| transcript.audio_clip(segment_index).map_err(|e| e.to_string()))
+                .and_then(|(samples, spec)| {
+                    let samples = to_whisper_samples(&samples, spec.channels, spec.sample_rate);
+                    VoiceProfile::enroll(&state.speaker_name, &samples, WHISPER_SAMPLE_RATE).map_err(|e| e.to_string())
+                });
+            match profile {
+                Ok(profile) => {
+                    println!("Enrolled {}", profile.get_description());
+                    state.speakers.enroll(profile);
+                    state.speakers.save(Path::new(DEFAULT_SPEAKERS_FILE)).unwrap_or_else(|e| println!("Could not save the speakers: {}", e));
+                },
+                Err(e) => println!("Could not enroll the voice: {}", e),
+            }
+        }
+        if let Some(index) = to_compress {
+            let session = state.sessions[index].clone();
+            state.start_compression(session);
+        }
+        if let Some(id) = to_delete {
+            if let Some(archive) = state.archive.as_ref() {
+                archive.delete(&id).unwrap_or_else(|e| println!("Could not delete the session: {}", e));
+            }
+            state.refresh_sessions();
+        }
+    });
+    sessions_ui.response.rect.size()
+}
@@ -1,14 +1,14 @@
 use std::cell::Cell;
-use std::path::{Path, PathBuf};
-use std::env;
-use gm_helper_corelibrary::{TtrpgEntity, SaveLoad, record_audio, transcribe_audio_file};
-use eframe::egui::{Vec2, Ui, ComboBox, ScrollArea, TextBuffer};
+use std::path::Path;
+use std::collections::HashMap;
+use gm_helper_corelibrary::{TtrpgEntity, SaveLoad, SessionLog, Ruleset, find_template, Template, load_templates, delete_template};
+use super::{RecordingState, recording_controls};
+use eframe::egui::{Vec2, Ui, ComboBox, ScrollArea, DragValue};
 use sqlite::{Connection, State};
 use rand::{distributions::Alphanumeric, Rng}; 
-use std::sync::Arc;
 //TODO new ttrpg_entity 
 // returns the ui height and width as a egui::Vec2 in order to calculate ui sizes
-pub fn configuration_ui(ui: &mut Ui, ttrpgs: &mut Vec<TtrpgEntity>, new_database: &mut Cell<String>, new_ttrpg: &mut Cell<TtrpgEntity>, recording_bool: &mut Arc<std::sync::Mutex<bool>>, transcribed_audio: &mut String) -> Vec2 { // Select database and load elements
+pub fn configuration_ui(ui: &mut Ui, ttrpgs: &mut Vec<TtrpgEntity>, new_database: &mut Cell<String>, new_ttrpg: &mut Cell<TtrpgEntity>, rulesets: &Vec<Ruleset>, template: &mut String, recording_state: &mut RecordingState) -> Vec2 { // Select database and load elements
     let config_ui = ui.group(|ui| {
         ui.group(|ui|{
             ui.horizontal(|ui| {
@@ -47,8 +47,16 @@ pub fn configuration_ui(ui: &mut Ui, ttrpgs: &mut Vec<TtrpgEntity>, new_database
             ui.horizontal_wrapped(|ui| {
                 if ui.button("Create TTRPG!").clicked() {
                     if new_ttrpg.get_mut().name.clone().len() > 0 {
-                        //Create a new copy of dummy value to pass user defined name into active ttrpgs
-                        let new_ttrpg_element = TtrpgEntity::new(true, false, None, new_ttrpg.get_mut().name.clone().to_string(), None);
+                        //Create a new copy of dummy value to pass user defined name into active ttrpgs, starting from the chosen template if any
+                        let name = new_ttrpg.get_mut().name.clone().to_string();
+                        let new_ttrpg_element = match find_template(rulesets, template) {
+                            Some((ruleset, t)) => ruleset.create_entity(&t.name, name.clone(), None)
+                                .unwrap_or_else(|e| {
+                                    println!("Could not create {} from {}: {}", name, t.name, e);
+                                    TtrpgEntity::new(true, false, None, name.clone(), None)
+                                }),
+                            None => TtrpgEntity::new(true, false, None, name.clone(), None),
+                        };
                         let mut existing_names: Vec<String> = Vec::new();
                         for ttrpg in ttrpgs.iter() {
                             existing_names.push(ttrpg.name.clone())
@@ -63,37 +71,22 @@ pub fn configuration_ui(ui: &mut Ui, ttrpgs: &mut Vec<TtrpgEntity>, new_database
                     new_ttrpg.get_mut().active.set(false);
                 }
                 ui.text_edit_singleline(&mut new_ttrpg.get_mut().name);
+                let selected_text = if template.is_empty() {"Blank".to_string()} else {template.clone()};
+                ComboBox::from_id_source("ttrpg_template")
+                    .selected_text(selected_text)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(template, "".to_string(), "Blank");
+                        for ruleset in rulesets.iter() {
+                            for t in ruleset.templates.iter() {
+                                ui.selectable_value(template, t.name.clone(), t.name.clone())
+                                    .on_hover_text(format!("{} - {}", ruleset.name, t.description));
+                            }
+                        }
+                    });
             });
         }
         
-        //Handling the recording and transcription of audio to text with whisper.cpp
-        let current_path = env::current_dir().unwrap();
-        let mut recording_audio_path = PathBuf::from(current_path);
-        recording_audio_path.push("whisper_tmp/record.wav");
-        let path_for_transcription = recording_audio_path.clone();
-        //File::create(&recording_audio_path).unwrap(); // Create the audio_file
-        if !*recording_bool.lock().unwrap() {
-            if ui.button("record").clicked() {
-                *recording_bool.lock().unwrap() = true;
-                let recording_bool_clone = recording_bool.clone();
-                let handle = std::thread::spawn(move || {
-                    record_audio(&recording_audio_path.into_os_string().to_str().unwrap(), recording_bool_clone).unwrap();
-                });
-                if !*recording_bool.lock().unwrap() {
-                    handle.join().unwrap();
-                }
-            }
-        } else {
-            if ui.button("stop recording").clicked() {
-                *recording_bool.lock().unwrap() = false;
-                println!("path to transcribe from {:#?}", path_for_transcription);
-            if path_for_transcription.exists() {
-                std::thread::sleep(std::time::Duration::from_millis(110));
-                let transcribed_audio_text = transcribe_audio_file(&path_for_transcription.into_os_string().to_str().unwrap());
-                transcribed_audio.insert_text(&transcribed_audio_text, transcribed_audio_text.len() + 1);
-            }
-            }
-        }
+        recording_controls(ui, recording_state, ttrpgs);
 
     });
         
@@ -125,6 +118,12 @@ pub fn selected_ttrpg_elements(ui: &mut Ui, ttrpgs: &mut Vec<TtrpgEntity>) -> Ve
                     ui.horizontal_wrapped(|ui| {
                         let active_text = if ttrpg.active.get() {"Active"} else {"Not Active"};
                         ui.checkbox(ttrpg.active.get_mut(), active_text);
+                        ui.checkbox(ttrpg.player_character.get_mut(), "Player character");
+                        if let Some(entry) = ttrpg.history.last() {
+                            if ui.small_button("Undo").on_hover_text(entry.get_description()).clicked() {
+                                ttrpg.undo();
+                            }
+                        }
 
                         if ui.small_button("Delete").clicked() {
                             if db_selected && ttrpg.id.len() > 0 {
@@ -171,34 +170,29 @@ pub fn selected_ttrpg_elements(ui: &mut Ui, ttrpgs: &mut Vec<TtrpgEntity>) -> Ve
                                 .expect(format!("Failed to open database for ttrpg {} - {:?}", &ttrpg.name, &ttrpg.database.as_os_str()).as_str());
                                 if ttrpg.id.len() == 0 {
                                     ttrpg.id = random_string();
-                                    let query = format!(
-                                        "
+                                    let query = "
                                             INSERT INTO ttrpgs (
                                                 json_string,
                                                 string_id
                                             )
-                                            VALUES ('{}', '{}');
-
-                                        ",
-                                        ttrpg.values_to_json(),
-                                        &ttrpg.id
-                                    );
-                                    println!("Saved {}", &ttrpg.name);
-                                    connection.execute(query).expect(format!("Unable to save {}", &ttrpg.name).as_str());
+                                            VALUES (?, ?);
+                                        ";
+                                    match store_ttrpg(&connection, query, &ttrpg.values_to_json(), &ttrpg.id) {
+                                        Ok(()) => println!("Saved {}", &ttrpg.name),
+                                        Err(e) => println!("Unable to save {}: {}", &ttrpg.name, e),
+                                    }
                                 }
                                 }
                                 else {
                                     ttrpg.name = "No title".to_string();
                                     let connection = Connection::open(ttrpg.database.as_os_str())
                                         .expect(format!("Failed to open database for ttrpg {} - {:?}", &ttrpg.name, &ttrpg.database.as_os_str()).as_str());
-                                    let query = format!(
-                                        "
-                                            UPDATE ttrpgs SET json_string = '{}' WHERE string_id = '{}';
-                                        ",
-                                        ttrpg.values_to_json(),
-                                        &ttrpg.id
-                                    );
-                                    connection.execute(query).expect(format!("Unable to update {}", &ttrpg.name).as_str());
+                                    let query = "
+                                            UPDATE ttrpgs SET json_string = ? WHERE string_id = ?;
+                                        ";
+                                    if let Err(e) = store_ttrpg(&connection, query, &ttrpg.values_to_json(), &ttrpg.id) {
+                                        println!("Unable to update {}: {}", &ttrpg.name, e);
+                                    }
                                 }
                         }
                     }
@@ -235,10 +229,14 @@ pub fn selected_ttrpg_elements(ui: &mut Ui, ttrpgs: &mut Vec<TtrpgEntity>) -> Ve
                 TtrpgEntity {
                     active: Cell::new(false),
                     edit: Cell::new(false),
+                    player_character: Cell::new(t.player_character.get()),
+                    ruleset: t.ruleset.clone(),
                     id: t.id.clone(),
                     name: t.name.clone(),
                     database: t.database.clone(),
-                    elements: t.elements.clone()
+                    elements: t.elements.clone(),
+                    history: t.history.clone(),
+                    transcripts: t.transcripts.clone()
                 }
             );
         }
@@ -246,21 +244,124 @@ pub fn selected_ttrpg_elements(ui: &mut Ui, ttrpgs: &mut Vec<TtrpgEntity>) -> Ve
     selected_ttrpg_ui.response.rect.size()
 }
 
-pub fn dice_rolls_and_creation_history (ui: &mut Ui) -> Vec2 {
+pub fn dice_rolls_and_creation_history (ui: &mut Ui, session_log: &mut SessionLog) -> Vec2 {
     let dice_rolls_and_creation_history_ui = ui.group(|ui| {
         ui.horizontal_wrapped(|ui| {
-            ui.strong("dice_rolls_and_creation_history_ui test");
+            ui.strong("Session log");
+            if ui.small_button("Clear").clicked() {
+                session_log.clear();
+            }
+        });
+        ScrollArea::vertical().max_height(150.0).stick_to_bottom(true).show(ui, |ui| {
+            for entry in session_log.entries.iter() {
+                ui.label(entry.get_description());
+            }
         });
     });
     dice_rolls_and_creation_history_ui.response.rect.size()
 }
 
-pub fn saved_configs_window(ui: &mut Ui) -> Vec2 {
+// Templates saved to the campaign databases; `rolls` are rerolled for every copy, written like "HP 2d6, DEX 3d6"
+pub fn saved_configs_window(ui: &mut Ui, ttrpgs: &mut Vec<TtrpgEntity>, templates: &mut Vec<Template>, copies: &mut u32, rolls: &mut String, overrides: &mut String) -> Vec2 {
+    let mut new_ttrpgs: Vec<TtrpgEntity> = Vec::new();
+    let mut templates_to_delete: Vec<usize> = Vec::new();
     let saved_configs_window_ui = ui.group(|ui| {
         ui.horizontal_wrapped(|ui| {
-            ui.strong("saved_configs_window ui test");
+            ui.strong("Templates");
+            if ui.small_button("Load").clicked() {
+                templates.clear();
+                let existing_paths = std::fs::read_dir("./saved_dbs/").expect("Could not read existing database paths");
+                for p in existing_paths {
+                    let path = p.unwrap().path();
+                    match load_templates(&path) {
+                        Ok(loaded) => templates.extend(loaded),
+                        Err(e) => println!("Could not load templates from {}: {}", path.display(), e),
+                    }
+                }
+            }
+        });
+        // Only entities with a database can be saved as templates, the template goes to the same database
+        ui.group(|ui| {
+            ui.label("Save as template");
+            for ttrpg in ttrpgs.iter() {
+                let db_selected = ttrpg.database.as_os_str().to_str().unwrap()[12..].len().gt(&0);
+                if db_selected && ui.small_button(ttrpg.name.clone()).clicked() {
+                    match Template::from_entity(&ttrpg.name, ttrpg) {
+                        Ok(template) => {
+                            match template.save(&ttrpg.database) {
+                                Ok(()) => {
+                                    templates.retain(|t| !(t.name == template.name && t.database == template.database));
+                                    templates.push(template);
+                                },
+                                Err(e) => println!("Could not save template {}: {}", ttrpg.name, e),
+                            }
+                        },
+                        Err(e) => println!("{}", e),
+                    }
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Copies");
+            ui.add(DragValue::new(copies).clamp_range(1..=50));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Rolls");
+            ui.text_edit_singleline(rolls);
+        });
+        // one group per copy separated by ';', e.g. "HP 30, AC 15; HP 12" sets the first two copies
+        ui.horizontal(|ui| {
+            ui.label("Overrides");
+            ui.text_edit_singleline(overrides);
+        });
+        ScrollArea::vertical().show(ui, |ui| {
+            for (index, template) in templates.iter().enumerate() {
+                ui.group(|ui| {
+                    ui.label(template.get_description());
+                    ui.horizontal_wrapped(|ui| {
+                        if ui.small_button("Create").clicked() {
+                            let mut template = template.clone();
+                            for roll in rolls.split(',').filter(|r| !r.trim().is_empty()) {
+                                if let Some((label, dice)) = roll.trim().rsplit_once(' ') {
+                                    if let Err(e) = template.randomize(label.trim(), dice) {
+                                        println!("{}", e);
+                                    }
+                                }
+                            }
+                            let existing_names: Vec<String> = ttrpgs.iter().map(|t| t.name.clone()).collect();
+                            let mut per_copy: Vec<HashMap<String, i32>> = Vec::new();
+                            for copy in overrides.split(';') {
+                                let mut values: HashMap<String, i32> = HashMap::new();
+                                for value in copy.split(',').filter(|v| !v.trim().is_empty()) {
+                                    match value.trim().rsplit_once(' ').map(|(label, number)| (label.trim(), number.parse::<i32>())) {
+                                        Some((label, Ok(number))) => {
+                                            values.insert(label.to_string(), number);
+                                        },
+                                        _ => println!("Could not read override {}, expected a label and a number", value.trim()),
+                                    }
+                                }
+                                per_copy.push(values);
+                            }
+                            match template.instantiate(*copies as usize, &existing_names, &per_copy) {
+                                Ok(created) => new_ttrpgs.extend(created),
+                                Err(e) => println!("Could not create {}: {}", template.name, e),
+                            }
+                        }
+                        if ui.small_button("Delete").clicked() {
+                            match delete_template(&template.database, &template.name) {
+                                Ok(()) => templates_to_delete.push(index),
+                                Err(e) => println!("Could not delete template {}: {}", template.name, e),
+                            }
+                        }
+                    });
+                });
+            }
         });
     });
+    for index in templates_to_delete.into_iter().rev() {
+        templates.remove(index);
+    }
+    ttrpgs.extend(new_ttrpgs);
     saved_configs_window_ui.response.rect.size()
 }
 
@@ -289,3 +390,11 @@ fn load_selected_database(path: &Path) -> Vec<TtrpgEntity> {
     ttrpgs
 }
 
+// The json is bound rather than written into the query, stories and transcripts are full of quotes
+fn store_ttrpg(connection: &Connection, query: &str, json_string: &str, string_id: &str) -> Result<(), sqlite::Error> {
+    let mut statement = connection.prepare(query)?;
+    statement.bind((1, json_string))?;
+    statement.bind((2, string_id))?;
+    statement.next()?;
+    Ok(())
+}
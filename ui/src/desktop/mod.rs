@@ -2,10 +2,11 @@ use std::{collections::HashMap};
 use std::cell::Cell;
 use eframe::egui::{self, Ui, TextBuffer};
 use egui::Pos2;
-use gm_helper_corelibrary::{TtrpgEntity, Story, Attribute, Counter, Skill, Table, Elements};
+use gm_helper_corelibrary::{TtrpgEntity, Story, Attribute, Counter, Skill, Table, Elements, Combat, SessionLog, Ruleset, load_rulesets, Template, Transcript, VoiceControl, play_audio};
 use crate::collapsables::*;
 use whisper_installer::install_whisper_cpp_model;
-use std::sync:: {Arc, Mutex};
+use std::path::Path;
+use std::time::Duration;
 
 pub struct MainWindow {
     new_database: Cell<String>,
@@ -20,8 +21,20 @@ pub struct MainWindow {
     new_text_body: String,
     new_number: u32,
     transcribed_audio: String,
-    recording: Arc<Mutex<bool>>
-
+    recording: RecordingState,
+    sessions_window: bool,
+    voice_control: VoiceControl,
+    voice_feedback: String,
+    combat_window: bool,
+    combat: Combat,
+    combat_amount: i32,
+    session_log: SessionLog,
+    rulesets: Vec<Ruleset>,
+    new_ttrpg_template: String,
+    templates: Vec<Template>,
+    template_copies: u32,
+    template_rolls: String,
+    template_overrides: String
 }
 
 impl Default for MainWindow {
@@ -39,7 +52,24 @@ impl Default for MainWindow {
         let new_text_body = "".to_string();
         let new_number = 0;
         let transcribed_audio = String::from("");
-        let recording = Arc::new(Mutex::new(false));
+        let recording = RecordingState::default();
+        let sessions_window = false;
+        let voice_control = VoiceControl::new();
+        let voice_feedback = String::from("");
+        let combat_window = false;
+        let combat = Combat::new();
+        let combat_amount = 0;
+        let session_log = SessionLog::new();
+        // Built in ruleset packs plus any found in ./rulesets/
+        let (rulesets, ruleset_errors) = load_rulesets(Path::new("./rulesets/"));
+        for error in ruleset_errors.iter() {
+            println!("Could not load ruleset: {}", error);
+        }
+        let new_ttrpg_template = "".to_string();
+        let templates: Vec<Template> = Vec::new();
+        let template_copies = 1;
+        let template_rolls = "".to_string();
+        let template_overrides = "".to_string();
         Self {
             new_database,
             configure_creation_window,
@@ -53,13 +83,31 @@ impl Default for MainWindow {
             new_text_body,
             new_number,
             transcribed_audio,
-            recording
+            recording,
+            sessions_window,
+            voice_control,
+            voice_feedback,
+            combat_window,
+            combat,
+            combat_amount,
+            session_log,
+            rulesets,
+            new_ttrpg_template,
+            templates,
+            template_copies,
+            template_rolls,
+            template_overrides
         }
     }
 }
 
 impl eframe::App for MainWindow {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Streamed transcription arrives while recording, the engine is kept for the next recording once it is done
+        if self.recording.poll(&mut self.transcribed_audio) {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+        self.recording.finish_stories(&mut self.active_ttrpg_elements);
         // Track the cursor position to expand a detract the sections of the main window
         let cursor_pos = track_cursor_position(ctx);
         let upper_x = ctx.available_rect().size().x;
@@ -90,7 +138,7 @@ impl eframe::App for MainWindow {
         // SAVED CONFIGS WINDOW- right
         if self.saved_configs_window.get() {
             egui::SidePanel::right("saved_configs_window").show(ctx, |ui| {
-                let saved_configs_window_size = saved_configs_window(ui);
+                let saved_configs_window_size = saved_configs_window(ui, &mut self.active_ttrpg_elements, &mut self.templates, &mut self.template_copies, &mut self.template_rolls, &mut self.template_overrides);
                 if cursor_pos.x < (upper_x - saved_configs_window_size.x) {
                     self.saved_configs_window.set(false);
                 }
@@ -103,8 +151,9 @@ impl eframe::App for MainWindow {
                     ui,&mut self.active_ttrpg_elements, 
                     &mut self.new_database, 
                     &mut self.ttrpg_creation,
-                    &mut self.recording,
-                    &mut self.transcribed_audio
+                    &self.rulesets,
+                    &mut self.new_ttrpg_template,
+                    &mut self.recording
                 );
                 if cursor_pos.y > config_window_size.y {
                     self.configure_creation_window.set(false);
@@ -114,24 +163,41 @@ impl eframe::App for MainWindow {
          // DICE ROLLS CREATION HISTORY - botton
          if self.dice_rolls_creation_history.get() {
             egui::TopBottomPanel::bottom("dice_rolls_and_creation_history_window").show(ctx, |ui| {
-                let dice_rolls_and_creation_history_window_size = dice_rolls_and_creation_history(ui);
+                let dice_rolls_and_creation_history_window_size = dice_rolls_and_creation_history(ui, &mut self.session_log);
                 if cursor_pos.y < (upper_y - dice_rolls_and_creation_history_window_size.y) {
                     self.dice_rolls_creation_history.set(false);
                 }
             });
         }
+        // COMBAT WINDOW - floating
+        egui::Window::new("Combat").open(&mut self.combat_window).show(ctx, |ui| {
+            combat_panel(ui, &mut self.combat, &mut self.active_ttrpg_elements, &mut self.session_log, &mut self.combat_amount);
+        });
+        // SESSIONS WINDOW - floating
+        egui::Window::new("Sessions").open(&mut self.sessions_window).show(ctx, |ui| {
+            sessions_panel(ui, &mut self.recording);
+        });
         // ACTIVE TTRPG ELEMENTS CENTRAL PANEL
         egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.toggle_value(&mut self.combat_window, "Combat");
+                ui.toggle_value(&mut self.sessions_window, "Sessions");
+            });
+            voice_command_bar(ui, &mut self.recording, &mut self.voice_control, &mut self.voice_feedback, &mut self.combat, &mut self.active_ttrpg_elements, &mut self.session_log);
             egui::ScrollArea::vertical().show(ui, |ui| {
-                display_active_elements(ui, &mut self.active_ttrpg_elements, &mut self.new_text_label, &mut self.new_text_body, &mut self.new_number, &mut self.transcribed_audio);
+                display_active_elements(ui, &mut self.active_ttrpg_elements, &mut self.new_text_label, &mut self.new_text_body, &mut self.new_number, &mut self.transcribed_audio, &mut self.recording);
             });
         });
     }
 }
 
-fn display_active_elements(ui: &mut egui::Ui, ttrpg_entities: &mut Vec<TtrpgEntity>, new_text_label: &mut String, new_text_body: &mut String, new_number: &mut u32, transcribed_audio: &mut String) {
+fn display_active_elements(ui: &mut egui::Ui, ttrpg_entities: &mut Vec<TtrpgEntity>, new_text_label: &mut String, new_text_body: &mut String, new_number: &mut u32, transcribed_audio: &mut String, recording: &mut RecordingState) {
+    let transcript = &recording.transcript;
+    let is_playing = *recording.playing.lock().unwrap();
+    let can_make_stories = recording.story_building.is_none();
+    let mut stories_to_make: Option<(usize, Transcript)> = None;
     let mut elements_to_delete: Vec<String> = Vec::new();
-    for entity in ttrpg_entities {
+    for (entity_index, entity) in ttrpg_entities.iter_mut().enumerate() {
             if entity.active.get() {
                 ui.horizontal_top(|ui| {
                     if ui.button("Story").clicked() {
@@ -149,7 +215,44 @@ fn display_active_elements(ui: &mut egui::Ui, ttrpg_entities: &mut Vec<TtrpgEnti
                             Elements::Story(new_story.unwrap())
                         );
                     }
+                    if ui.add_enabled(!transcript.is_empty(), egui::Button::new("Attach transcript")).clicked() {
+                        entity.attach_transcript(transcript.clone()).unwrap_or_else(|e| println!("{}", e));
+                    }
+                    if ui.add_enabled(!transcript.is_empty() && can_make_stories, egui::Button::new("Stories from transcript"))
+                        .on_hover_text("A story for each scene, split on long pauses or when someone says \"new scene\"")
+                        .clicked() {
+                        stories_to_make = Some((entity_index, transcript.clone()));
+                    }
                 });
+                // attached transcripts can be exported as subtitles or text next to the saved databases
+                let mut transcript_to_remove = None;
+                let mut transcript_to_stories = None;
+                for (index, attached) in entity.transcripts.iter().enumerate() {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label(format!("Transcript {}: {}", index + 1, attached.get_description()));
+                        for extension in ["srt", "vtt", "txt"] {
+                            if ui.small_button(extension).clicked() {
+                                let path = Path::new("./transcripts").join(format!("{}_{}.{}", entity.name, index + 1, extension));
+                                match attached.export(&path) {
+                                    Ok(_) => println!("Exported {}", path.display()),
+                                    Err(e) => println!("Could not export {}: {}", path.display(), e),
+                                }
+                            }
+                        }
+                        if ui.add_enabled(can_make_stories, egui::Button::new("stories").small()).clicked() {
+                            transcript_to_stories = Some(index);
+                        }
+                        if ui.small_button("remove").clicked() {
+                            transcript_to_remove = Some(index);
+                        }
+                    });
+                }
+                if let Some(index) = transcript_to_stories {
+                    stories_to_make = Some((entity_index, entity.transcripts[index].clone()));
+                }
+                if let Some(index) = transcript_to_remove {
+                    entity.transcripts.remove(index);
+                }
                 // display elements that are active and where edit is false
                 let element_ids = entity.retrieve_all_element_keys();
                 for key in element_ids {
@@ -186,7 +289,24 @@ fn display_active_elements(ui: &mut egui::Ui, ttrpg_entities: &mut Vec<TtrpgEnti
             
                                 }
                                 else {
-                                    ui.label(s.label.clone());
+                                    ui.horizontal_wrapped(|ui| {
+                                        ui.label(s.label.clone());
+                                        // stories made from a transcript can be heard again
+                                        if let Some(audio) = s.audio.clone() {
+                                            if ui.add_enabled(!is_playing, egui::Button::new("play").small()).on_hover_text(audio.get_description()).clicked() {
+                                                let playing = recording.playing.clone();
+                                                *playing.lock().unwrap() = true;
+                                                std::thread::spawn(move || {
+                                                    let played = audio.audio_clip()
+                                                        .and_then(|(samples, spec)| play_audio(&samples, spec.channels, spec.sample_rate, playing.clone()));
+                                                    if let Err(e) = played {
+                                                        println!("Could not play the story: {}", e);
+                                                        *playing.lock().unwrap() = false;
+                                                    }
+                                                });
+                                            }
+                                        }
+                                    });
                                     ui.label(s.raw_narration.clone());
                                 }
                             });
@@ -199,6 +319,40 @@ fn display_active_elements(ui: &mut egui::Ui, ttrpg_entities: &mut Vec<TtrpgEnti
                         },
                         Elements::Table(t) => {
                         },
+                        Elements::Condition(cd) => {
+                            ui.group(|ui| {
+                                ui.horizontal_wrapped(|ui| {
+                                    ui.label(cd.get_description());
+                                    if ui.small_button("remove").clicked() {
+                                        elements_to_delete.push(key);
+                                    }
+                                });
+                            });
+                        },
+                        Elements::Resource(r) => {
+                            ui.group(|ui| {
+                                ui.horizontal_wrapped(|ui| {
+                                    ui.label(r.get_description());
+                                    if ui.small_button("use").clicked() {
+                                        let _ = r.spend(1);
+                                    }
+                                    if ui.small_button("restore").clicked() {
+                                        r.restore(1);
+                                    }
+                                });
+                            });
+                        },
+                        Elements::Inventory(i) => {
+                            ui.group(|ui| {
+                                ui.strong(i.get_description());
+                                for item in i.items.iter() {
+                                    ui.label(item.get_description());
+                                }
+                            });
+                        },
+                        Elements::Derived(d) => {
+                            ui.label(d.get_description());
+                        },
                     }
                 }
                 for el in elements_to_delete.iter() {
@@ -207,6 +361,10 @@ fn display_active_elements(ui: &mut egui::Ui, ttrpg_entities: &mut Vec<TtrpgEnti
             }
             
     }
+    // Each scene of the transcript becomes a story of the entity, with speakers if any voices are enrolled
+    if let Some((entity_index, transcript)) = stories_to_make {
+        recording.start_stories(transcript, entity_index, &ttrpg_entities[entity_index]);
+    }
  
 }
 